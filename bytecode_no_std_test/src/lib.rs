@@ -0,0 +1,76 @@
+#![no_std]
+
+// The types here are compiled without std, and without alloc unless the alloc feature is enabled,
+// so this checks that the generated code only uses what is available in such crates.
+// The tests are in the tests directory, as those are compiled with std.
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use bytecode::{Bytecodable, Bytecode, BytecodeError};
+
+#[derive(Bytecode, Debug, PartialEq, Eq, Clone, Copy)]
+#[bytecode(fixed_size)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq, Clone, Copy)]
+#[bytecode(fixed_size)]
+pub struct Memory {
+    pub base: Register,
+    pub offset: i16,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instruction {
+    Nop,
+    Mov(Register, Register),
+    Load {
+        #[bytecode(bits = 2)]
+        dst: Register,
+        #[bytecode(bits = 6)]
+        flags: u8,
+        src: Memory,
+    },
+    #[bytecode(opcode = 0x200)]
+    Jump(u32),
+    Halt,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq, Clone, Copy)]
+#[bytecode(fixed_size)]
+pub struct Pair<T>(pub T, pub T);
+
+/// Compiles the program into the buffer, returning the number of bytes written
+pub fn assemble(program: &[Instruction], buf: &mut [u8]) -> Result<usize, BytecodeError> {
+    let total = buf.len();
+    let mut rest = buf;
+    for instr in program {
+        instr.compile_into(&mut rest)?;
+    }
+    Ok(total - rest.len())
+}
+
+/// Parses the instructions from the bytes into the buffer, returning the number of instructions
+pub fn disassemble(mut bytes: &[u8], out: &mut [Instruction]) -> Result<usize, BytecodeError> {
+    let mut count = 0;
+    let mut offset = 0;
+    while !bytes.is_empty() {
+        let (instr, size) = Instruction::parse(bytes).map_err(|e| e.offset_by(offset))?;
+        out[count] = instr;
+        count += 1;
+        offset += size;
+        bytes = &bytes[size..];
+    }
+    Ok(count)
+}
+
+/// Compiles the program into a vec
+#[cfg(feature = "alloc")]
+pub fn assemble_vec(program: &[Instruction]) -> alloc::vec::Vec<u8> {
+    program.iter().flat_map(|i| i.compile()).collect()
+}
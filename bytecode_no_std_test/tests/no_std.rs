@@ -0,0 +1,64 @@
+use bytecode::{Bytecodable, ErrorKind, FixedSize};
+use bytecode_no_std_test::*;
+
+fn program() -> [Instruction; 5] {
+    [
+        Instruction::Nop,
+        Instruction::Mov(Register::B, Register::C),
+        Instruction::Load {
+            dst: Register::D,
+            flags: 5,
+            src: Memory {
+                base: Register::A,
+                offset: -2,
+            },
+        },
+        Instruction::Jump(0x01020304),
+        Instruction::Halt,
+    ]
+}
+
+#[test]
+fn test_assemble_disassemble() {
+    let program = program();
+    let mut buf = [0; 32];
+    let len = assemble(&program, &mut buf).unwrap();
+    assert_eq!(
+        &buf[..len],
+        &[0, 1, 1, 2, 2, 0xC5, 0, 0xFE, 0xFF, 0x82, 0x00, 4, 3, 2, 1, 3]
+    );
+
+    let mut out = [Instruction::Nop; 5];
+    assert_eq!(disassemble(&buf[..len], &mut out), Ok(5));
+    assert_eq!(out, program);
+
+    let e = disassemble(&buf[..len - 2], &mut out).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::IncompleteInstruction);
+    assert_eq!(e.offset(), 11);
+    assert_eq!(e.path().to_string(), "Instruction::Jump.0");
+}
+
+#[test]
+fn test_buffer_too_small() {
+    let mut buf = [0; 8];
+    assert_eq!(
+        assemble(&program(), &mut buf).map_err(|e| e.kind()),
+        Err(ErrorKind::BufferOverflow)
+    );
+}
+
+#[test]
+fn test_sizes() {
+    assert_eq!(Instruction::MIN_SIZE, 1);
+    assert_eq!(Instruction::MAX_SIZE, Some(6));
+    assert_eq!(Pair::<Memory>::SIZE, 6);
+    assert_eq!(program()[3].encoded_len(), 6);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_assemble_vec() {
+    let mut buf = [0; 32];
+    let len = assemble(&program(), &mut buf).unwrap();
+    assert_eq!(assemble_vec(&program()), &buf[..len]);
+}
@@ -1,4 +1,15 @@
-use bytecode::{Bytecodable, Bytecode, BytecodeError};
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::num::{
+    NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+    NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
+};
+
+use bytecode::{
+    BigEndian, BitPackable, Bytecodable, Bytecode, BytecodeDecode, BytecodeEncode, BytecodeError,
+    BytecodeSink, Dependent, Disp32, Displacement, ErrorKind, FixedSize, LittleEndian, ModRm, Sib,
+    VarI32, VarI64, VarU32, VarU64, ZigZagI32, ZigZagI64,
+};
 use seq_macro::seq;
 
 #[derive(Bytecode, Debug, PartialEq, Eq)]
@@ -29,6 +40,696 @@ enum TestBoundaryFromAbove{
 }
 });
 
+// all the opcodes of the tag are used, as the unknown variant does not take one
+seq!(N in 0..256{
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "u8")]
+enum TestFullTag{
+    #(T~N,)*
+    #[bytecode(unknown)]
+    Unknown(u8),
+}
+});
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum ExplicitOpcodeEnum {
+    T0,
+    #[bytecode(opcode = 0x10)]
+    T1(u8),
+    T2,
+    #[bytecode(opcode = 1)]
+    T3,
+    #[bytecode(opcode = 0x1234)]
+    T4 {
+        x: u8,
+    },
+    T5,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum DiscriminantEnum {
+    A = 2,
+    B = 0,
+    C,
+}
+
+// the variants after a discriminant follow it, as they do in rust
+#[derive(Bytecode, Debug, PartialEq, Eq, Clone, Copy)]
+enum FollowingDiscriminant {
+    X,
+    A = 5,
+    B,
+    C,
+}
+
+// opcodes of this are recorded in bytecode.lock, which also
+// has a removed variant, whose opcode must stay unused
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(lock)]
+enum LockedEnum {
+    T0,
+    T1(u8),
+    T2,
+}
+
+// the lockfile has a removed variant with an embedded field, all of whose
+// opcodes must stay unused
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(lock)]
+enum LockedEmbed {
+    Nop,
+    Inc,
+    #[bytecode(opcode = 0x10, embed(field = 0, bits = 3))]
+    Pop(u8),
+}
+
+// `B` was recorded in the lockfile without a prefix, and moved to a prefix group
+// later, so its old opcode stays reserved rather than going to `D`
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(lock, tag = "u8")]
+enum LockedGroups {
+    A,
+    #[bytecode(prefix = 0x0F)]
+    B,
+    C,
+    D,
+}
+
+// the code generated for the lockfile uses qualified paths, so it works
+// with user types named as the prelude ones
+mod locked_shadowed {
+    use bytecode::Bytecode;
+
+    #[allow(dead_code)]
+    struct Option;
+
+    #[derive(Bytecode)]
+    #[bytecode(lock = "LockedEnum")]
+    pub enum LockedEnum {
+        T0,
+        T1(u8),
+        T2,
+    }
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum PackedReg {
+    A,
+    B,
+    C,
+    D,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum PackedEnum {
+    Nop,
+    Add(
+        #[bytecode(bits = 4)] PackedReg,
+        #[bytecode(bits = 4)] PackedReg,
+    ),
+    AddI {
+        #[bytecode(bits = 2)]
+        dst: PackedReg,
+        #[bytecode(bits = 6)]
+        imm: u8,
+        rest: u16,
+    },
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct PackedStruct {
+    #[bytecode(bits = 1)]
+    flag: bool,
+    #[bytecode(bits = 3)]
+    small: i8,
+    #[bytecode(bits = 12)]
+    wide: u16,
+    tail: u8,
+}
+
+trait Word: Bytecodable {}
+impl Word for u8 {}
+impl Word for u16 {}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct GenericPair<A, B>(A, B);
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum GenericEnum<W>
+where
+    W: Word,
+{
+    Nop,
+    Load(W),
+    Packed {
+        #[bytecode(bits = 4)]
+        hi: W,
+        #[bytecode(bits = 4)]
+        lo: u8,
+    },
+}
+
+// the inferred bound would be W: Bytecodable, which is weaker than needed here
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(bound = "W: Word")]
+struct WordOperand<W: Word + Copy> {
+    v: W,
+}
+
+trait Operand {
+    type Value;
+}
+struct Imm16;
+impl Operand for Imm16 {
+    type Value = u16;
+}
+
+// has an inherent compile method, which must not be used by the derive
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct ShadowedCompile(u8);
+impl ShadowedCompile {
+    #[allow(dead_code)]
+    fn compile(&self) -> Vec<u8> {
+        vec![]
+    }
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum FieldTypeEnum {
+    Generic(GenericPair<u8, u16>),
+    Qualified(<Imm16 as Operand>::Value),
+    Nested {
+        x: GenericEnum<u8>,
+        y: crate::tests::ShadowedCompile,
+    },
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct FieldTypeStruct {
+    x: GenericEnum<u16>,
+    y: <Imm16 as Operand>::Value,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(fixed_size)]
+enum FixedEnum {
+    A(u16),
+    B(u8, i8),
+    C {
+        #[bytecode(bits = 4)]
+        x: u8,
+        #[bytecode(bits = 4)]
+        y: u8,
+        z: bool,
+    },
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(fixed_size)]
+struct FixedPair<T>(T, #[bytecode(bits = 3)] u8, T);
+
+// length prefixed bytes, which do not have an upper bound on their size
+#[derive(Debug, PartialEq, Eq)]
+struct Unbounded(Vec<u8>);
+
+impl Bytecodable for Unbounded {
+    const MIN_SIZE: usize = 1;
+
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        (self.0.len() as u8).compile_into(out)?;
+        out.write_bytes(&self.0)
+    }
+
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        let (len, size) = u8::parse(bytes)?;
+        let end = size + len as usize;
+        if bytes.len() < end {
+            return Err(BytecodeError::incomplete(end - bytes.len()));
+        }
+        Ok((Unbounded(bytes[size..end].to_vec()), end))
+    }
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum SizedEnum {
+    Nop,
+    Data(Unbounded, u16),
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct MemOperand {
+    base: u8,
+    offset: u16,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum Opcode {
+    Nop,
+    AddM(u8, MemOperand),
+    #[bytecode(opcode = 0x100)]
+    Far(
+        #[bytecode(bits = 4)] PackedReg,
+        #[bytecode(bits = 4)] PackedReg,
+    ),
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct Program(u8, Opcode);
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct Blob<'a> {
+    tag: u8,
+    data: &'a [u8],
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum BorrowedOp<'a> {
+    Nop,
+    Print(&'a str),
+    Data(Blob<'a>),
+    Pair { name: &'a str, value: u16 },
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct Tagged<'a, T> {
+    tag: T,
+    name: &'a str,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct StaticName(&'static str, u8);
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum CallOp {
+    Call(u8, Vec<u16>),
+    Print(String),
+    Short(#[bytecode(len = u8)] Vec<u8>),
+    Regs {
+        #[bytecode(len = u16, max_len = 4)]
+        regs: Box<[SimpleEnum]>,
+        queue: VecDeque<i8>,
+    },
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct Routine {
+    name: String,
+    #[bytecode(max_len = 3)]
+    body: Vec<Opcode>,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum VarOp<T> {
+    Jump {
+        #[bytecode(varint)]
+        offset: i32,
+        #[bytecode(varint)]
+        count: u16,
+        flags: u8,
+    },
+    Push(#[bytecode(varint)] T),
+    Index(usize, isize),
+}
+
+#[derive(Bytecode, Debug, PartialEq)]
+#[bytecode(fixed_size)]
+struct Literal<T> {
+    value: f64,
+    c: char,
+    id: NonZeroU16,
+    counter: Wrapping<u8>,
+    marker: PhantomData<T>,
+    unit: (),
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum ArrayOp<T, const N: usize> {
+    Load([u8; N], T),
+    Regs([PackedReg; 3]),
+    Pair((u8, i16), Option<T>),
+    Check {
+        result: Result<u16, SimpleEnum>,
+        rest: ([T; 2], ()),
+    },
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(fixed_size)]
+struct FixedArrays<T> {
+    key: [u8; 4],
+    pairs: [(T, u8); 2],
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(endian = "big")]
+struct BigHeader<T> {
+    magic: [u8; 4],
+    version: u16,
+    flags: T,
+    #[bytecode(endian = "little")]
+    checksum: u32,
+    #[bytecode(varint)]
+    count: u32,
+    #[bytecode(len = u16)]
+    name: String,
+    kind: SimpleEnum,
+    #[bytecode(bits = 4)]
+    hi: u8,
+    #[bytecode(bits = 4)]
+    lo: u8,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(endian = "big")]
+enum BigOp {
+    Nop,
+    Move(u16, i32),
+    #[bytecode(opcode = 0x100)]
+    Far {
+        addr: u64,
+        words: [u16; 2],
+        c: char,
+    },
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct MixedOrder {
+    a: u16,
+    #[bytecode(endian = "big")]
+    b: u16,
+    c: BigEndian<u32>,
+    #[bytecode(len = u16, endian = "big")]
+    d: Vec<BigEndian<u16>>,
+    e: LittleEndian<i16>,
+}
+
+// the elements of the containers are stored in the byte order of the type
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(endian = "big")]
+struct BigContainers {
+    a: Option<u16>,
+    b: Vec<u32>,
+    c: (u8, u16),
+    d: Result<i16, u32>,
+    e: VecDeque<u16>,
+    f: Box<[u16]>,
+    g: NonZeroU16,
+    h: Wrapping<u32>,
+    #[bytecode(len = u8)]
+    i: Vec<u16>,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "u8")]
+enum ByteTagOp {
+    Nop,
+    #[bytecode(opcode = 0xFF)]
+    Halt(u8),
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "u16_be")]
+enum WordTagOp {
+    Nop,
+    #[bytecode(opcode = 0xF00D)]
+    Load(u16),
+}
+
+// the tag follows the byte order of the enum, unless given explicitly
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "u32", endian = "big")]
+enum LongTagOp {
+    Nop,
+    #[bytecode(opcode = 0x0102_0304)]
+    Call(u16),
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "u16_le", fixed_size)]
+enum FixedTagOp {
+    A(u8),
+    #[bytecode(opcode = 0x1234)]
+    B(i8),
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "varint")]
+enum VarTagOp {
+    Nop,
+    #[bytecode(opcode = 300)]
+    Mid(u8),
+    #[bytecode(opcode = 0x10_0000)]
+    Far,
+}
+
+// opcodes of a newer version of the instruction set, which are kept as is
+#[derive(Bytecode, Debug, Clone, PartialEq, Eq)]
+enum UnknownOp {
+    Nop,
+    Push(u8),
+    #[bytecode(opcode = 0x200)]
+    Jump(u16),
+    #[bytecode(unknown)]
+    Unknown(u16),
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "u8", fixed_size)]
+enum UnknownByteOp {
+    #[bytecode(opcode = 0x10)]
+    Halt,
+    #[bytecode(unknown)]
+    Unknown { opcode: u8 },
+}
+
+// redundant encodings, such as the two forms of `MOV r/m, r`
+#[derive(Bytecode, Debug, Clone, Copy, PartialEq, Eq)]
+#[bytecode(tag = "u8")]
+enum AliasOp {
+    Nop,
+    #[bytecode(opcode = 0x88, alias = [0x8A, 0x89])]
+    Mov(u8),
+    #[bytecode(alias = 0x01)]
+    Hlt,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum CompactAliasOp {
+    #[bytecode(alias = [0x100])]
+    Push(u8),
+    #[bytecode(unknown)]
+    Unknown(u16),
+}
+
+// retired opcodes stay reserved, so they are never reused
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "u8", reserve = [0x02..0x04, 0x10..=0x1F], assembler)]
+enum ReservedOp {
+    Nop,
+    Push(u8),
+    Loop,
+    #[bytecode(deprecated)]
+    Jmp {
+        target: u16,
+    },
+    #[bytecode(opcode = 0x20, deprecated = "use `call` instead")]
+    OldCall(u16),
+    #[bytecode(unknown)]
+    Unknown(u8),
+}
+
+// x86 style escape prefixes, which open secondary opcode maps
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "u8")]
+enum PrefixOp {
+    Nop,
+    Push(u8),
+    #[bytecode(prefix = 0x0F)]
+    Syscall,
+    #[bytecode(prefix = 0x0F, opcode = 0xA2)]
+    Cpuid,
+    #[bytecode(prefix = 0x0F, alias = 0x06)]
+    Jcc(i32),
+    #[bytecode(prefix = 0xD8)]
+    Fadd(u8),
+    Hlt,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum CompactPrefixOp {
+    Nop,
+    #[bytecode(prefix = 0x7F, opcode = 0x100)]
+    Ext(u8),
+    #[bytecode(unknown)]
+    Unknown(u16),
+}
+
+#[derive(Bytecode, Debug, Clone, Copy, PartialEq, Eq)]
+enum Reg16 {
+    Ax,
+    Cx,
+    Dx,
+    Bx,
+    Sp,
+    Bp,
+    Si,
+    Di,
+}
+
+// 8086 style instructions, which hold the register in the lower bits of the opcode
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "u8")]
+enum EmbedOp {
+    Nop,
+    #[bytecode(opcode = 0x40, embed(field = 0, bits = 3))]
+    Inc(u8),
+    #[bytecode(opcode = 0x48, embed(field = 0, bits = 3))]
+    Dec(PackedReg),
+    #[bytecode(opcode = 0x50, embed(field = 0, bits = 3))]
+    Push(Reg16),
+    #[bytecode(opcode = 0x58, embed(field = 0, bits = 3))]
+    Pop(Reg16),
+    #[bytecode(opcode = 0xB8, embed(field = dst, bits = 3))]
+    MovImm {
+        imm: u16,
+        dst: Reg16,
+    },
+    #[bytecode(prefix = 0x0F, opcode = 0xC8, embed(field = 0, bits = 3))]
+    Bswap(Reg16),
+    Hlt,
+    #[bytecode(unknown)]
+    Unknown(u8),
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+enum CompactEmbedOp {
+    #[bytecode(opcode = 0, embed(field = 0, bits = 8))]
+    Short(u8),
+    Long(u16),
+}
+
+// RISC-V style instructions, each of which is a single 32 bit word
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(word = u32, endian = "little", assembler)]
+enum RiscOp {
+    #[bytecode(opcode = 0x0000_0033)]
+    Add {
+        #[bytecode(bits = 7..=11)]
+        rd: u8,
+        #[bytecode(bits = 15..=19)]
+        rs1: u8,
+        #[bytecode(bits = 20..=24)]
+        rs2: u8,
+    },
+    #[bytecode(opcode = 0x4000_0033)]
+    Sub {
+        #[bytecode(bits = 7..=11)]
+        rd: u8,
+        #[bytecode(bits = 15..=19)]
+        rs1: u8,
+        #[bytecode(bits = 20..=24)]
+        rs2: u8,
+    },
+    #[bytecode(opcode = 0x13)]
+    Addi {
+        #[bytecode(bits = 7..=11)]
+        rd: u8,
+        #[bytecode(bits = 15..=19)]
+        rs1: u8,
+        #[bytecode(bits = 20..=31)]
+        imm: i16,
+    },
+    #[bytecode(opcode = 0x63)]
+    Beq {
+        #[bytecode(bits = 15..=19)]
+        rs1: u8,
+        #[bytecode(bits = 20..=24)]
+        rs2: u8,
+        #[bytecode(bits = [31, 7, 25..=30, 8..=11], shift = 1)]
+        offset: i16,
+    },
+    #[bytecode(opcode = 0x0F, mask = 0x707F)]
+    Fence,
+    #[bytecode(opcode = 0x73)]
+    Ecall,
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(word = u16, endian = "big")]
+enum ThumbOp {
+    #[bytecode(opcode = 0x2000)]
+    Movs(#[bytecode(bits = 8..=10)] u8, #[bytecode(bits = 0..8)] u8),
+    #[bytecode(opcode = 0xB400)]
+    Push(#[bytecode(bits = 0..=2)] PackedReg),
+    #[bytecode(opcode = 0xBF00)]
+    Nop,
+}
+
+// 8086 instructions, where the mode bits of the ModR/M byte give the size of the displacement
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+#[bytecode(tag = "u8")]
+enum X86Op {
+    #[bytecode(opcode = 0x89)]
+    MovRmReg {
+        modrm: ModRm,
+        #[bytecode(depends = modrm)]
+        disp: Displacement,
+    },
+    #[bytecode(opcode = 0xC7)]
+    MovRmImm(ModRm, #[bytecode(depends = 0)] Displacement, u16),
+}
+
+// 32 bit addressing, where the SIB byte is present only for some of the modes, and the
+// size of the displacement is given by both bytes
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct Addr32 {
+    modrm: ModRm,
+    #[bytecode(depends = modrm)]
+    sib: Option<Sib>,
+    #[bytecode(depends = (modrm, sib))]
+    disp: Disp32,
+}
+
+// an immediate which is a byte or a word, as given by an earlier flag
+#[derive(Debug, PartialEq, Eq)]
+struct Imm(u16);
+
+impl Dependent<bool> for Imm {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: Option<usize> = Some(2);
+    fn encoded_len_with(&self, wide: &bool) -> usize {
+        1 + *wide as usize
+    }
+    fn compile_with<S: BytecodeSink + ?Sized>(
+        &self,
+        wide: &bool,
+        out: &mut S,
+    ) -> Result<(), BytecodeError> {
+        match *wide {
+            true => self.0.compile_into(out),
+            false => u8::try_from(self.0)
+                .map_err(|_| BytecodeError::new(ErrorKind::Other("immediate is too large")))?
+                .compile_into(out),
+        }
+    }
+    fn parse_with(bytes: &[u8], wide: &bool) -> Result<(Self, usize), BytecodeError> {
+        match *wide {
+            true => u16::parse(bytes).map(|(v, s)| (Imm(v), s)),
+            false => u8::parse(bytes).map(|(v, s)| (Imm(v as u16), s)),
+        }
+    }
+}
+
+#[derive(Bytecode, Debug, PartialEq, Eq)]
+struct ImmOperand<T> {
+    #[bytecode(bits = 1)]
+    wide: bool,
+    #[bytecode(bits = 7)]
+    reg: u8,
+    #[bytecode(depends = wide)]
+    imm: T,
+}
+
 #[derive(Bytecode, Debug, PartialEq, Eq)]
 struct SimpleTupleStruct(u8, u16, i16);
 
@@ -48,6 +749,29 @@ struct CompositeNamedStruct {
     z: SimpleEnum,
 }
 
+// most tests only care about the kind of error
+fn kind<T>(r: Result<T, BytecodeError>) -> Result<T, ErrorKind> {
+    r.map_err(|e| e.kind())
+}
+
+// checks that the values compile and parse or read back to the same value, and the
+// encoded length is same as the length of the bytecode
+fn assert_round_trip<T: Bytecodable + std::fmt::Debug + PartialEq>(
+    values: impl IntoIterator<Item = T>,
+) {
+    for v in values {
+        let bytes = v.compile();
+        assert_eq!(v.encoded_len(), bytes.len());
+        let mut extra = bytes.clone();
+        extra.push(0xFF);
+        assert_eq!(
+            T::parse(&extra),
+            Ok((T::read_from(&mut &bytes[..]).unwrap(), bytes.len()))
+        );
+        assert_eq!(T::parse(&bytes).unwrap().0, v);
+    }
+}
+
 #[test]
 fn test_simple_enum_compile() {
     let t1 = SimpleEnum::T0;
@@ -80,13 +804,13 @@ fn test_simple_enum_parse() {
     assert_eq!(t3, Ok((SimpleEnum::T2 { x: 14, y: 7 }, 3)));
 
     let err1 = SimpleEnum::parse(&err1);
-    assert_eq!(err1, Err(BytecodeError::InvalidInstruction));
+    assert_eq!(kind(err1), Err(ErrorKind::InvalidInstruction));
 
     let err2 = SimpleEnum::parse(&err2);
-    assert_eq!(err2, Err(BytecodeError::IncompleteInstruction));
+    assert_eq!(kind(err2), Err(ErrorKind::IncompleteInstruction));
 
     let err3 = SimpleEnum::parse(&[]);
-    assert_eq!(err3, Err(BytecodeError::IncompleteInstruction));
+    assert_eq!(kind(err3), Err(ErrorKind::IncompleteInstruction));
 }
 
 #[test]
@@ -142,16 +866,16 @@ fn test_composite_enum_parse() {
     let err4 = [2, 2, 5, 7];
 
     let err1 = CompositeEnum::parse(&err1);
-    assert_eq!(err1, Err(BytecodeError::InvalidInstruction));
+    assert_eq!(kind(err1), Err(ErrorKind::InvalidInstruction));
 
     let err2 = CompositeEnum::parse(&err2);
-    assert_eq!(err2, Err(BytecodeError::IncompleteInstruction));
+    assert_eq!(kind(err2), Err(ErrorKind::IncompleteInstruction));
 
     let err3 = CompositeEnum::parse(&err3);
-    assert_eq!(err3, Err(BytecodeError::IncompleteInstruction));
+    assert_eq!(kind(err3), Err(ErrorKind::IncompleteInstruction));
 
     let err4 = CompositeEnum::parse(&err4);
-    assert_eq!(err4, Err(BytecodeError::IncompleteInstruction));
+    assert_eq!(kind(err4), Err(ErrorKind::IncompleteInstruction));
 }
 
 #[test]
@@ -197,14 +921,14 @@ fn test_enum_boundary() {
 
     let err1 = [128, 5, 5];
     assert_eq!(
-        TestBoundaryFromBelow::parse(&err1),
-        Err(BytecodeError::InvalidInstruction)
+        kind(TestBoundaryFromBelow::parse(&err1)),
+        Err(ErrorKind::InvalidInstruction)
     );
 
     let err2 = [150, 5, 5];
     assert_eq!(
-        TestBoundaryFromBelow::parse(&err2),
-        Err(BytecodeError::InvalidInstruction)
+        kind(TestBoundaryFromBelow::parse(&err2)),
+        Err(ErrorKind::InvalidInstruction)
     );
 
     let r1 = [0, 8, 55];
@@ -249,24 +973,24 @@ fn test_enum_boundary() {
     // invalid instruction
     let err5 = [128, 155, 0, 0];
     assert_eq!(
-        TestBoundaryFromAbove::parse(&err1),
-        Err(BytecodeError::IncompleteInstruction)
+        kind(TestBoundaryFromAbove::parse(&err1)),
+        Err(ErrorKind::IncompleteInstruction)
     );
     assert_eq!(
-        TestBoundaryFromAbove::parse(&err2),
-        Err(BytecodeError::IncompleteInstruction)
+        kind(TestBoundaryFromAbove::parse(&err2)),
+        Err(ErrorKind::IncompleteInstruction)
     );
     assert_eq!(
-        TestBoundaryFromAbove::parse(&err3),
-        Err(BytecodeError::IncompleteInstruction)
+        kind(TestBoundaryFromAbove::parse(&err3)),
+        Err(ErrorKind::IncompleteInstruction)
     );
     assert_eq!(
-        TestBoundaryFromAbove::parse(&err4),
-        Err(BytecodeError::InvalidInstruction)
+        kind(TestBoundaryFromAbove::parse(&err4)),
+        Err(ErrorKind::InvalidInstruction)
     );
     assert_eq!(
-        TestBoundaryFromAbove::parse(&err5),
-        Err(BytecodeError::InvalidInstruction)
+        kind(TestBoundaryFromAbove::parse(&err5)),
+        Err(ErrorKind::InvalidInstruction)
     );
 }
 
@@ -291,8 +1015,8 @@ fn test_tuple_struct_parse() {
 
     let err1 = [12, 5, 7, 3];
     assert_eq!(
-        SimpleTupleStruct::parse(&err1),
-        Err(BytecodeError::IncompleteInstruction)
+        kind(SimpleTupleStruct::parse(&err1)),
+        Err(ErrorKind::IncompleteInstruction)
     );
 
     let r2 = [2, 97, 95, 72, 0];
@@ -301,13 +1025,13 @@ fn test_tuple_struct_parse() {
 
     let err2 = [1, 5];
     assert_eq!(
-        CompositeTupleStruct::parse(&err2),
-        Err(BytecodeError::IncompleteInstruction)
+        kind(CompositeTupleStruct::parse(&err2)),
+        Err(ErrorKind::IncompleteInstruction)
     );
     let err3 = [7, 5];
     assert_eq!(
-        CompositeTupleStruct::parse(&err3),
-        Err(BytecodeError::InvalidInstruction)
+        kind(CompositeTupleStruct::parse(&err3)),
+        Err(ErrorKind::InvalidInstruction)
     );
 }
 
@@ -347,8 +1071,8 @@ fn test_named_struct_parse() {
 
     let err1 = [0x1, 0x1, 0x1, 0x1];
     assert_eq!(
-        SimpleNamedStruct::parse(&err1),
-        Err(BytecodeError::IncompleteInstruction)
+        kind(SimpleNamedStruct::parse(&err1)),
+        Err(ErrorKind::IncompleteInstruction)
     );
 
     let r2 = [0, 0x12, 0, 0, 0x34, 0x12, 0, 0x55, 0x77, 2, 7, 5];
@@ -365,13 +1089,2088 @@ fn test_named_struct_parse() {
 
     let err1 = [0, 0x12, 0, 0, 0x34, 0x12, 0, 0x55, 0x77, 2, 7];
     assert_eq!(
-        CompositeNamedStruct::parse(&err1),
-        Err(BytecodeError::IncompleteInstruction)
+        kind(CompositeNamedStruct::parse(&err1)),
+        Err(ErrorKind::IncompleteInstruction)
     );
 
     let err2 = [0, 0x12, 0, 0, 0x34, 0x12, 0, 0x55, 0x77, 3, 7];
     assert_eq!(
-        CompositeNamedStruct::parse(&err2),
-        Err(BytecodeError::InvalidInstruction)
+        kind(CompositeNamedStruct::parse(&err2)),
+        Err(ErrorKind::InvalidInstruction)
+    );
+}
+
+#[test]
+fn test_explicit_opcode_compile() {
+    assert_eq!(ExplicitOpcodeEnum::T0.compile(), &[0]);
+    assert_eq!(ExplicitOpcodeEnum::T1(5).compile(), &[0x10, 5]);
+    // 1 is taken by T3, so T2 fills the next gap
+    assert_eq!(ExplicitOpcodeEnum::T2.compile(), &[2]);
+    assert_eq!(ExplicitOpcodeEnum::T3.compile(), &[1]);
+    assert_eq!(
+        ExplicitOpcodeEnum::T4 { x: 7 }.compile(),
+        &[0x12 | 1 << 7, 0x34, 7]
+    );
+    assert_eq!(ExplicitOpcodeEnum::T5.compile(), &[3]);
+}
+
+#[test]
+fn test_explicit_opcode_parse() {
+    assert_eq!(
+        ExplicitOpcodeEnum::parse(&[0x10, 5]),
+        Ok((ExplicitOpcodeEnum::T1(5), 2))
+    );
+    assert_eq!(
+        ExplicitOpcodeEnum::parse(&[1]),
+        Ok((ExplicitOpcodeEnum::T3, 1))
+    );
+    assert_eq!(
+        ExplicitOpcodeEnum::parse(&[0x92, 0x34, 9]),
+        Ok((ExplicitOpcodeEnum::T4 { x: 9 }, 3))
+    );
+    assert_eq!(
+        ExplicitOpcodeEnum::parse(&[3]),
+        Ok((ExplicitOpcodeEnum::T5, 1))
+    );
+
+    // gaps in the opcode table are invalid
+    assert_eq!(
+        kind(ExplicitOpcodeEnum::parse(&[4])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(ExplicitOpcodeEnum::parse(&[0x92, 0x35])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    // single byte opcodes cannot be encoded in two bytes
+    assert_eq!(
+        kind(ExplicitOpcodeEnum::parse(&[0x80, 0x10, 5])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(ExplicitOpcodeEnum::parse(&[0x92])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+}
+
+#[test]
+fn test_discriminant_opcode() {
+    assert_eq!(DiscriminantEnum::A.compile(), &[2]);
+    assert_eq!(DiscriminantEnum::B.compile(), &[0]);
+    assert_eq!(DiscriminantEnum::C.compile(), &[1]);
+
+    assert_eq!(DiscriminantEnum::parse(&[2]), Ok((DiscriminantEnum::A, 1)));
+    assert_eq!(DiscriminantEnum::parse(&[1]), Ok((DiscriminantEnum::C, 1)));
+    assert_eq!(
+        kind(DiscriminantEnum::parse(&[3])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+
+    for v in [
+        FollowingDiscriminant::X,
+        FollowingDiscriminant::A,
+        FollowingDiscriminant::B,
+        FollowingDiscriminant::C,
+    ] {
+        assert_eq!(v.compile(), &[v as u8]);
+        assert_eq!(FollowingDiscriminant::parse(&[v as u8]), Ok((v, 1)));
+    }
+    assert_eq!(FollowingDiscriminant::B.compile(), &[6]);
+
+    assert_eq!(TestFullTag::T255.compile(), &[0xFF]);
+    assert_eq!(TestFullTag::parse(&[0xFF]), Ok((TestFullTag::T255, 1)));
+}
+
+#[test]
+fn test_locked_opcode() {
+    assert_eq!(LockedEnum::T0.compile(), &[2]);
+    assert_eq!(LockedEnum::T1(5).compile(), &[0, 5]);
+    // new variants skip the opcode reserved for the removed variant
+    assert_eq!(LockedEnum::T2.compile(), &[3]);
+
+    assert_eq!(LockedEnum::parse(&[2]), Ok((LockedEnum::T0, 1)));
+    assert_eq!(LockedEnum::parse(&[0, 7]), Ok((LockedEnum::T1(7), 2)));
+    assert_eq!(LockedEnum::parse(&[3]), Ok((LockedEnum::T2, 1)));
+    assert_eq!(
+        kind(LockedEnum::parse(&[1])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+
+    assert_eq!(LockedEmbed::Nop.compile(), &[8]);
+    assert_eq!(LockedEmbed::Inc.compile(), &[9]);
+    assert_eq!(LockedEmbed::Pop(3).compile(), &[0x13]);
+    for opcode in 0..8 {
+        assert_eq!(
+            kind(LockedEmbed::parse(&[opcode])),
+            Err(ErrorKind::InvalidInstruction)
+        );
+    }
+
+    assert_eq!(locked_shadowed::LockedEnum::T2.compile(), &[3]);
+
+    assert_eq!(LockedGroups::A.compile(), &[0]);
+    assert_eq!(LockedGroups::B.compile(), &[0x0F, 0]);
+    assert_eq!(LockedGroups::C.compile(), &[2]);
+    assert_eq!(LockedGroups::D.compile(), &[3]);
+    assert_eq!(
+        kind(LockedGroups::parse(&[1])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+}
+
+#[test]
+fn test_bit_packed_compile() {
+    assert_eq!(PackedEnum::Nop.compile(), &[0]);
+    assert_eq!(
+        PackedEnum::Add(PackedReg::B, PackedReg::D).compile(),
+        &[1, 0x13]
+    );
+    let t = PackedEnum::AddI {
+        dst: PackedReg::C,
+        imm: 0x2A,
+        rest: 0x1234,
+    };
+    assert_eq!(t.compile(), &[2, 0b1010_1010, 0x34, 0x12]);
+
+    let t = PackedStruct {
+        flag: true,
+        small: -3,
+        wide: 0xABC,
+        tail: 7,
+    };
+    assert_eq!(t.compile(), &[0xDA, 0xBC, 7]);
+}
+
+#[test]
+fn test_bit_packed_parse() {
+    assert_eq!(
+        PackedEnum::parse(&[1, 0x20]),
+        Ok((PackedEnum::Add(PackedReg::C, PackedReg::A), 2))
+    );
+    assert_eq!(
+        PackedEnum::parse(&[2, 0b1111_1111, 0x01, 0x00]),
+        Ok((
+            PackedEnum::AddI {
+                dst: PackedReg::D,
+                imm: 0x3F,
+                rest: 1
+            },
+            4
+        ))
+    );
+    assert_eq!(
+        PackedStruct::parse(&[0x7F, 0xFF, 1]),
+        Ok((
+            PackedStruct {
+                flag: false,
+                small: -1,
+                wide: 0xFFF,
+                tail: 1
+            },
+            3
+        ))
+    );
+
+    // there is no register with value 5
+    assert_eq!(
+        kind(PackedEnum::parse(&[1, 0x51])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(PackedEnum::parse(&[1])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+    assert_eq!(
+        kind(PackedStruct::parse(&[0x5F, 0xFF])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+    // all the bytes of the group are needed, same as when reading
+    for bytes in [&[][..], &[0x7F]] {
+        let e = PackedStruct::parse(bytes).unwrap_err();
+        assert_eq!(
+            (e.needed(), e.offset(), e.path().to_string()),
+            (Some(2 - bytes.len()), 0, "PackedStruct.flag".to_string())
+        );
+        assert_eq!(Err(e), PackedStruct::read_from(&mut &bytes[..]));
+    }
+
+    // only zero fits in zero bits
+    assert_eq!(0_i8.to_bits(0), Ok(0));
+    assert_eq!(0_u8.to_bits(0), Ok(0));
+    assert_eq!(
+        kind((-1_i8).to_bits(0)),
+        Err(ErrorKind::Other("value does not fit in its bit width"))
+    );
+    assert_eq!(i8::from_bits(0xFF, 0), Ok(0));
+    assert_eq!(i64::from_bits(u64::MAX, 64), Ok(-1));
+}
+
+#[test]
+#[should_panic(expected = "value of field `imm` does not fit in 6 bits")]
+fn test_bit_packed_overflow() {
+    let t = PackedEnum::AddI {
+        dst: PackedReg::A,
+        imm: 64,
+        rest: 0,
+    };
+    t.compile();
+}
+
+#[test]
+fn test_generic_compile() {
+    assert_eq!(GenericPair(5_u8, 0x1234_u16).compile(), &[5, 0x34, 0x12]);
+    assert_eq!(GenericPair(-1_i8, true).compile(), &[0xFF, 1]);
+
+    assert_eq!(GenericEnum::<u16>::Nop.compile(), &[0]);
+    assert_eq!(GenericEnum::Load(0x1234_u16).compile(), &[1, 0x34, 0x12]);
+    assert_eq!(GenericEnum::Load(0x12_u8).compile(), &[1, 0x12]);
+    assert_eq!(
+        GenericEnum::Packed { hi: 0xA_u16, lo: 5 }.compile(),
+        &[2, 0xA5]
+    );
+
+    assert_eq!(WordOperand { v: 0x55_u8 }.compile(), &[0x55]);
+}
+
+#[test]
+fn test_generic_parse() {
+    assert_eq!(
+        GenericPair::<u8, u16>::parse(&[5, 0x34, 0x12]),
+        Ok((GenericPair(5, 0x1234), 3))
+    );
+    assert_eq!(
+        GenericEnum::<u16>::parse(&[1, 0x34, 0x12]),
+        Ok((GenericEnum::Load(0x1234), 3))
+    );
+    assert_eq!(
+        GenericEnum::<u8>::parse(&[1, 0x34, 0x12]),
+        Ok((GenericEnum::Load(0x34), 2))
+    );
+    assert_eq!(
+        GenericEnum::<u8>::parse(&[2, 0x5A]),
+        Ok((GenericEnum::Packed { hi: 5, lo: 0xA }, 2))
+    );
+    assert_eq!(
+        kind(GenericEnum::<u16>::parse(&[1, 0x34])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+    assert_eq!(
+        WordOperand::<u16>::parse(&[0x34, 0x12]),
+        Ok((WordOperand { v: 0x1234 }, 2))
+    );
+}
+
+#[test]
+fn test_field_type_syntax() {
+    let t = FieldTypeEnum::Generic(GenericPair(1, 0x0203));
+    assert_eq!(t.compile(), &[0, 1, 3, 2]);
+    assert_eq!(FieldTypeEnum::parse(&[0, 1, 3, 2]), Ok((t, 4)));
+
+    let t = FieldTypeEnum::Qualified(0x1234);
+    assert_eq!(t.compile(), &[1, 0x34, 0x12]);
+    assert_eq!(FieldTypeEnum::parse(&[1, 0x34, 0x12]), Ok((t, 3)));
+
+    let t = FieldTypeEnum::Nested {
+        x: GenericEnum::Load(7),
+        y: ShadowedCompile(9),
+    };
+    assert_eq!(t.compile(), &[2, 1, 7, 9]);
+    assert_eq!(FieldTypeEnum::parse(&[2, 1, 7, 9]), Ok((t, 4)));
+
+    let t = FieldTypeStruct {
+        x: GenericEnum::Packed { hi: 1, lo: 2 },
+        y: 0x0506,
+    };
+    assert_eq!(t.compile(), &[2, 0x12, 6, 5]);
+    assert_eq!(FieldTypeStruct::parse(&[2, 0x12, 6, 5]), Ok((t, 4)));
+}
+
+#[test]
+fn test_compile_into() {
+    let t = PackedEnum::AddI {
+        dst: PackedReg::C,
+        imm: 0x2A,
+        rest: 0x1234,
+    };
+
+    // compiling into a vec appends to the existing bytes
+    let mut out = vec![0xFF];
+    assert_eq!(t.compile_into(&mut out), Ok(()));
+    assert_eq!(out, &[0xFF, 2, 0b1010_1010, 0x34, 0x12]);
+
+    // compiling into a slice fills it from start, and leaves the rest
+    let mut buf = [0_u8; 8];
+    let mut rest = &mut buf[..];
+    assert_eq!(t.compile_into(&mut rest), Ok(()));
+    assert_eq!(SimpleEnum::T1(7, 8).compile_into(&mut rest), Ok(()));
+    assert_eq!(rest.len(), 1);
+    assert_eq!(buf, [2, 0b1010_1010, 0x34, 0x12, 1, 7, 8, 0]);
+
+    let t = FieldTypeStruct {
+        x: GenericEnum::Packed { hi: 1, lo: 2 },
+        y: 0x0506,
+    };
+    let mut buf = [0_u8; 4];
+    assert_eq!(t.compile_into(&mut &mut buf[..]), Ok(()));
+    assert_eq!(buf, [2, 0x12, 6, 5]);
+}
+
+#[test]
+fn test_compile_into_errors() {
+    let t = PackedEnum::AddI {
+        dst: PackedReg::C,
+        imm: 0x2A,
+        rest: 0x1234,
+    };
+    let mut buf = [0_u8; 3];
+    assert_eq!(
+        kind(t.compile_into(&mut &mut buf[..])),
+        Err(ErrorKind::BufferOverflow)
+    );
+
+    let t = PackedEnum::AddI {
+        dst: PackedReg::A,
+        imm: 64,
+        rest: 0,
+    };
+    assert_eq!(
+        kind(t.compile_into(&mut Vec::new())),
+        Err(ErrorKind::Other(
+            "value of field `imm` does not fit in 6 bits"
+        ))
+    );
+}
+
+#[test]
+fn test_encoded_size() {
+    assert_eq!(SimpleEnum::MIN_SIZE, 1);
+    assert_eq!(SimpleEnum::MAX_SIZE, Some(3));
+    assert_eq!(PackedEnum::MIN_SIZE, 1);
+    assert_eq!(PackedEnum::MAX_SIZE, Some(4));
+    assert_eq!(PackedStruct::MIN_SIZE, 3);
+    assert_eq!(PackedStruct::MAX_SIZE, Some(3));
+    // T4 has a two byte opcode
+    assert_eq!(ExplicitOpcodeEnum::MIN_SIZE, 1);
+    assert_eq!(ExplicitOpcodeEnum::MAX_SIZE, Some(3));
+    assert_eq!(GenericPair::<u8, u32>::MAX_SIZE, Some(5));
+
+    assert_eq!(SizedEnum::MIN_SIZE, 1);
+    assert_eq!(SizedEnum::MAX_SIZE, None);
+
+    let values = [
+        SizedEnum::Nop,
+        SizedEnum::Data(Unbounded(vec![]), 1),
+        SizedEnum::Data(Unbounded(vec![1, 2, 3, 4]), 1),
+    ];
+    for v in &values {
+        assert_eq!(v.encoded_len(), v.compile().len());
+    }
+    assert_eq!(values[2].encoded_len(), 8);
+
+    let t = PackedEnum::AddI {
+        dst: PackedReg::C,
+        imm: 0x2A,
+        rest: 0x1234,
+    };
+    assert_eq!(t.encoded_len(), 4);
+    assert_eq!(ExplicitOpcodeEnum::T4 { x: 7 }.encoded_len(), 3);
+    assert_eq!(CompositeTupleStruct(SimpleEnum::T0, 0).encoded_len(), 3);
+}
+
+#[test]
+fn test_fixed_size() {
+    assert_eq!(<u32 as FixedSize>::SIZE, 4);
+    assert_eq!(FixedEnum::SIZE, 3);
+    assert_eq!(FixedEnum::MIN_SIZE, 3);
+    assert_eq!(FixedEnum::MAX_SIZE, Some(3));
+    assert_eq!(
+        FixedEnum::C {
+            x: 1,
+            y: 2,
+            z: true
+        }
+        .compile(),
+        &[2, 0x12, 1]
+    );
+
+    assert_eq!(FixedPair::<u8>::SIZE, 3);
+    assert_eq!(FixedPair::<i64>::SIZE, 17);
+    assert_eq!(
+        FixedPair(1_u16, 2, 3).compile().len(),
+        FixedPair::<u16>::SIZE
+    );
+}
+
+#[test]
+fn test_error_details() {
+    let e = Opcode::parse(&[1, 3, 9, 0x34]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::IncompleteInstruction);
+    assert_eq!(e.offset(), 3);
+    assert_eq!(e.needed(), Some(1));
+    assert_eq!(e.path().to_string(), "Opcode::AddM.1.offset");
+    assert_eq!(
+        e.to_string(),
+        "incomplete instruction at offset 3, 1 more byte(s) needed in Opcode::AddM.1.offset"
+    );
+
+    let e = Opcode::parse(&[5]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInstruction);
+    assert_eq!(e.offset(), 0);
+    assert_eq!(e.needed(), None);
+    assert_eq!(e.bytes(), &[5]);
+    assert_eq!(
+        e.to_string(),
+        "invalid instruction at offset 0, found 0x05 in Opcode"
+    );
+
+    let e = Opcode::parse(&[0x80, 0x05]).unwrap_err();
+    assert_eq!(e.bytes(), &[0x80, 0x05]);
+    assert_eq!(
+        format!("{:?}", e),
+        "BytecodeError { kind: InvalidInstruction, offset: 0, needed: None, \
+         bytes: [128, 5], path: \"Opcode\" }"
+    );
+
+    // there is no register with value 5
+    let e = Opcode::parse(&[0x81, 0x00, 0x51]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInstruction);
+    assert_eq!(e.offset(), 2);
+    assert_eq!(e.bytes(), &[0x51]);
+    assert_eq!(e.path().to_string(), "Opcode::Far.0");
+
+    let e = Opcode::parse(&[0x81]).unwrap_err();
+    assert_eq!(e.needed(), Some(1));
+    assert_eq!(e.path().to_string(), "Opcode");
+    assert_eq!(Opcode::parse(&[]).unwrap_err().needed(), Some(1));
+
+    // nested types only add the field names to the path
+    let e = Program::parse(&[0, 1, 3]).unwrap_err();
+    assert_eq!(e.offset(), 3);
+    assert_eq!(e.path().to_string(), "Program.1::AddM.1.base");
+
+    let e: Box<dyn std::error::Error> = Box::new(u16::parse(&[1]).unwrap_err());
+    assert_eq!(
+        e.to_string(),
+        "incomplete instruction at offset 0, 1 more byte(s) needed"
+    );
+}
+
+#[test]
+fn test_write_to_read_from() {
+    let program = [
+        Opcode::AddM(
+            1,
+            MemOperand {
+                base: 2,
+                offset: 0x0304,
+            },
+        ),
+        Opcode::Nop,
+        Opcode::Far(PackedReg::B, PackedReg::D),
+    ];
+    let mut out = Vec::new();
+    for instr in &program {
+        instr.write_to(&mut out).unwrap();
+    }
+    assert_eq!(out, &[1, 1, 2, 4, 3, 0, 0x81, 0x00, 0x13]);
+
+    // only the bytes of each value are read, so the values can be read one after another
+    let mut reader = std::io::Cursor::new(out);
+    for instr in &program {
+        assert_eq!(&Opcode::read_from(&mut reader).unwrap(), instr);
+    }
+    assert_eq!(
+        kind(Opcode::read_from(&mut reader)),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+
+    let t = PackedStruct {
+        flag: true,
+        small: -3,
+        wide: 0xABC,
+        tail: 7,
+    };
+    assert_eq!(PackedStruct::read_from(&mut &[0xDA, 0xBC, 7][..]), Ok(t));
+    assert_eq!(
+        SimpleNamedStruct::read_from(&mut &[0x1, 0x0, 0x0, 0x0, 0x1, 0x0, 0x1][..]),
+        SimpleNamedStruct::parse(&[0x1, 0x0, 0x0, 0x0, 0x1, 0x0, 0x1]).map(|(v, _)| v)
+    );
+
+    // types which do not implement read_from are read using parse
+    let t = SizedEnum::Data(Unbounded(vec![5, 6, 7]), 0x0809);
+    let mut reader = &[1, 3, 5, 6, 7, 9, 8, 0xFF][..];
+    assert_eq!(SizedEnum::read_from(&mut reader), Ok(t));
+    assert_eq!(reader, &[0xFF]);
+}
+
+#[test]
+fn test_read_from_errors() {
+    // errors are same as the ones from parse
+    let bytes = [1, 3, 9, 0x34];
+    let e = Opcode::read_from(&mut &bytes[..]).unwrap_err();
+    assert_eq!(Err(e), Opcode::parse(&bytes));
+
+    for bytes in [&[5][..], &[0x80, 0x05], &[0x81], &[0x81, 0x00, 0x51], &[]] {
+        assert_eq!(
+            Opcode::read_from(&mut &bytes[..]).unwrap_err(),
+            Opcode::parse(bytes).unwrap_err()
+        );
+    }
+    let e = Program::read_from(&mut &[0, 1, 3][..]).unwrap_err();
+    assert_eq!(e.path().to_string(), "Program.1::AddM.1.base");
+    assert_eq!(e.offset(), 3);
+
+    struct FailingReader;
+    impl std::io::Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::PermissionDenied.into())
+        }
+    }
+    assert_eq!(
+        kind(Opcode::read_from(&mut FailingReader)),
+        Err(ErrorKind::Io(std::io::ErrorKind::PermissionDenied))
+    );
+    // the I/O error is kept as the source
+    let e = Opcode::read_from(&mut FailingReader).unwrap_err();
+    let source = std::error::Error::source(&e).unwrap();
+    assert_eq!(
+        source.downcast_ref::<std::io::Error>().map(|e| e.kind()),
+        Some(std::io::ErrorKind::PermissionDenied)
+    );
+    assert_eq!(e.clone(), e);
+    assert!(std::error::Error::source(&BytecodeError::new(ErrorKind::BufferOverflow)).is_none());
+
+    let mut buf = [0_u8; 3];
+    assert_eq!(
+        kind(Opcode::Far(PackedReg::A, PackedReg::B).write_to(&mut &mut buf[..])),
+        Ok(())
+    );
+    assert_eq!(
+        kind(Opcode::Nop.write_to(&mut &mut buf[..0])),
+        Err(ErrorKind::Io(std::io::ErrorKind::WriteZero))
+    );
+}
+
+#[test]
+fn test_borrowed_compile() {
+    assert_eq!(BorrowedOp::Nop.compile(), &[0]);
+    assert_eq!(BorrowedOp::Print("hi").compile(), &[1, 2, b'h', b'i']);
+    assert_eq!(
+        BorrowedOp::Data(Blob {
+            tag: 7,
+            data: &[1, 2, 3]
+        })
+        .compile(),
+        &[2, 7, 3, 1, 2, 3]
+    );
+    assert_eq!(
+        BorrowedOp::Pair {
+            name: "",
+            value: 0x0102
+        }
+        .compile(),
+        &[3, 0, 2, 1]
+    );
+
+    // lengths of 128 and more take two bytes
+    let data = [0xAA; 200];
+    let t = Blob {
+        tag: 1,
+        data: &data,
+    };
+    let compiled = t.compile();
+    assert_eq!(&compiled[..3], &[1, 0xC8, 0x01]);
+    assert_eq!(compiled.len(), 203);
+    assert_eq!(t.encoded_len(), 203);
+
+    assert_eq!(<BorrowedOp as BytecodeEncode>::MIN_SIZE, 1);
+    assert_eq!(<BorrowedOp as BytecodeEncode>::MAX_SIZE, None);
+    assert_eq!(<Tagged<u32> as BytecodeEncode>::MIN_SIZE, 5);
+}
+
+#[test]
+fn test_borrowed_decode() {
+    let bytes = [2, 7, 3, 1, 2, 3, 0xFF];
+    let (t, size) = BorrowedOp::decode(&bytes).unwrap();
+    assert_eq!(size, 6);
+    assert_eq!(
+        t,
+        BorrowedOp::Data(Blob {
+            tag: 7,
+            data: &[1, 2, 3]
+        })
+    );
+    // the data points into the bytes, rather than being copied
+    match t {
+        BorrowedOp::Data(blob) => assert_eq!(blob.data.as_ptr(), bytes[3..].as_ptr()),
+        _ => unreachable!(),
+    }
+
+    let bytes = BorrowedOp::Pair {
+        name: "name",
+        value: 9,
+    }
+    .compile();
+    assert_eq!(
+        BorrowedOp::decode(&bytes),
+        Ok((
+            BorrowedOp::Pair {
+                name: "name",
+                value: 9
+            },
+            8
+        ))
+    );
+    assert_eq!(
+        Tagged::<u16>::decode(&[1, 0, 2, b'o', b'k']),
+        Ok((
+            Tagged {
+                tag: 1_u16,
+                name: "ok"
+            },
+            5
+        ))
+    );
+    assert_eq!(
+        StaticName::decode(b"\x03abc\x05"),
+        Ok((StaticName("abc", 5), 5))
+    );
+
+    // walk over a buffer of instructions without copying
+    let mut program = Vec::new();
+    for t in [
+        BorrowedOp::Print("a"),
+        BorrowedOp::Nop,
+        BorrowedOp::Print("bc"),
+    ] {
+        t.compile_into(&mut program).unwrap();
+    }
+    let mut rest = &program[..];
+    let mut printed = Vec::new();
+    while !rest.is_empty() {
+        let (t, size) = BorrowedOp::decode(rest).unwrap();
+        if let BorrowedOp::Print(s) = t {
+            printed.push(s);
+        }
+        rest = &rest[size..];
+    }
+    assert_eq!(printed, ["a", "bc"]);
+}
+
+#[test]
+fn test_borrowed_decode_errors() {
+    let e = BorrowedOp::decode(&[1, 5, b'a', b'b']).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::IncompleteInstruction);
+    assert_eq!(e.needed(), Some(3));
+    assert_eq!(e.offset(), 1);
+    assert_eq!(e.path().to_string(), "BorrowedOp::Print.0");
+
+    let e = BorrowedOp::decode(&[1, 3, b'a', 0xFF, b'b']).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Other("string is not valid UTF-8"));
+    assert_eq!(e.offset(), 3);
+    assert_eq!(e.bytes(), &[0xFF, b'b']);
+
+    // lengths must be encoded in the shortest form
+    let e = Blob::decode(&[1, 0x81, 0x00]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInstruction);
+    assert_eq!(e.offset(), 1);
+    assert_eq!(e.path().to_string(), "Blob.data");
+    assert_eq!(
+        kind(Blob::decode(&[1, 0x81])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+    assert_eq!(
+        kind(<&[u8]>::decode(&[0xFF; 11])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(BorrowedOp::decode(&[4])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+}
+
+#[test]
+fn test_collection_compile() {
+    assert_eq!(
+        CallOp::Call(1, vec![0x0102, 3]).compile(),
+        &[0, 1, 2, 0x02, 0x01, 3, 0]
+    );
+    assert_eq!(CallOp::Print("hi".into()).compile(), &[1, 2, b'h', b'i']);
+    assert_eq!(CallOp::Short(vec![]).compile(), &[2, 0]);
+    assert_eq!(
+        CallOp::Regs {
+            regs: vec![SimpleEnum::T0, SimpleEnum::T2 { x: 5, y: 6 }].into(),
+            queue: VecDeque::from(vec![-1]),
+        }
+        .compile(),
+        &[3, 2, 0, 0, 2, 5, 6, 1, 0xFF]
+    );
+
+    // lengths of 128 and more take two bytes by default
+    let t = CallOp::Call(0, vec![7; 200]);
+    let compiled = t.compile();
+    assert_eq!(&compiled[..4], &[0, 0, 0xC8, 0x01]);
+    assert_eq!(compiled.len(), 404);
+    assert_eq!(t.encoded_len(), 404);
+    let t = CallOp::Short(vec![7; 200]);
+    assert_eq!(&t.compile()[..2], &[2, 200]);
+    assert_eq!(t.encoded_len(), 202);
+
+    assert_eq!(CallOp::MIN_SIZE, 2);
+    assert_eq!(CallOp::MAX_SIZE, None);
+    assert_eq!(<Vec<u64>>::MIN_SIZE, 1);
+    assert_eq!(<Vec<u64>>::MAX_SIZE, None);
+}
+
+#[test]
+fn test_collection_parse() {
+    for t in [
+        CallOp::Call(1, vec![0x0102, 3]),
+        CallOp::Print("héllo".into()),
+        CallOp::Short(vec![9; 255]),
+        CallOp::Regs {
+            regs: vec![SimpleEnum::T0, SimpleEnum::T2 { x: 5, y: 6 }].into(),
+            queue: VecDeque::from(vec![-1, 2, -3]),
+        },
+    ] {
+        let bytes = t.compile();
+        assert_eq!(CallOp::parse(&bytes), Ok((t, bytes.len())));
+    }
+
+    let r = Routine {
+        name: "main".into(),
+        body: vec![
+            Opcode::Nop,
+            Opcode::AddM(1, MemOperand { base: 2, offset: 3 }),
+        ],
+    };
+    let bytes = r.compile();
+    assert_eq!(Routine::parse(&bytes), Ok((r, bytes.len())));
+    let r = Routine::read_from(&mut &bytes[..]).unwrap();
+    assert_eq!(r.body.len(), 2);
+    assert_eq!(r.encoded_len(), bytes.len());
+
+    // bytes are copied at once, the rest of the types are parsed one by one
+    let mut bytes = vec![0xFF];
+    assert_eq!(u8::parse_into_vec(&[1, 2, 3], 2, &mut bytes), Ok(2));
+    assert_eq!(bytes, [0xFF, 1, 2]);
+    assert_eq!(
+        u8::parse_into_vec(&[1, 2], 3, &mut bytes).map_err(|e| e.needed()),
+        Err(Some(1))
+    );
+    let mut words = Vec::new();
+    assert_eq!(u16::parse_into_vec(&[1, 0, 2, 0], 2, &mut words), Ok(4));
+    assert_eq!(words, [1, 2]);
+    assert_eq!(<Vec<u8>>::parse(&[3, 7, 8, 9, 10]), Ok((vec![7, 8, 9], 4)));
+    assert_eq!(
+        <VecDeque<u8>>::parse(&[2, 7, 8]),
+        Ok((VecDeque::from(vec![7, 8]), 3))
+    );
+}
+
+#[test]
+fn test_collection_errors() {
+    // too long for the length prefix or the maximum length
+    let mut out = Vec::new();
+    let e = CallOp::Short(vec![0; 256])
+        .compile_into(&mut out)
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::LengthLimit);
+    let r = Routine {
+        name: String::new(),
+        body: (0..4).map(|_| Opcode::Nop).collect(),
+    };
+    assert_eq!(kind(r.compile_into(&mut out)), Err(ErrorKind::LengthLimit));
+
+    let e = Routine::parse(&[0, 4, 0, 0, 0, 0]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::LengthLimit);
+    assert_eq!(e.offset(), 1);
+    assert_eq!(e.bytes(), &[4]);
+    assert_eq!(e.path().to_string(), "Routine.body");
+    assert_eq!(kind(CallOp::parse(&[3, 5, 0])), Err(ErrorKind::LengthLimit));
+
+    // a large length is rejected before anything is allocated for it
+    let e = <Vec<u64>>::parse(&[0xFF, 0xFF, 0xFF, 0x07, 1]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::IncompleteInstruction);
+    assert_eq!(e.needed(), Some(((1 << 24) - 1) * 8 - 1));
+    assert_eq!(e.offset(), 4);
+    assert_eq!(
+        kind(<Vec<u8>>::parse(&[0x81, 0x80, 0x80, 0x08])),
+        Err(ErrorKind::LengthLimit)
+    );
+    let e = <Vec<u8>>::read_from(&mut &[0xFF, 0xFF, 0xFF, 0x07, 1, 2][..]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::IncompleteInstruction);
+    assert_eq!(e.offset(), 6);
+    let e = String::read_from(&mut &[0xFF, 0xFF, 0xFF, 0x07, b'a'][..]).unwrap_err();
+    assert_eq!(e.needed(), Some((1 << 24) - 2));
+    assert_eq!(e.offset(), 4);
+    assert_eq!(
+        String::parse(&[0xFF, 0xFF, 0xFF, 0x07, b'a']).unwrap_err(),
+        e
+    );
+
+    // errors in the elements are located within the collection
+    let e = CallOp::parse(&[0, 1, 2, 1, 0, 2]).unwrap_err();
+    assert_eq!(e.needed(), Some(1));
+    assert_eq!(e.offset(), 3);
+    assert_eq!(e.path().to_string(), "CallOp::Call.1");
+    let e = CallOp::parse(&[1, 3, b'a', 0xC3, b'b']).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Other("string is not valid UTF-8"));
+    assert_eq!(e.offset(), 3);
+    assert_eq!(
+        kind(<Vec<u8>>::parse(&[0x80, 0x00])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+}
+
+#[test]
+fn test_varint_encoding() {
+    assert_eq!(VarU64(0).compile(), &[0]);
+    assert_eq!(VarU64(127).compile(), &[0x7F]);
+    assert_eq!(VarU64(128).compile(), &[0x80, 0x01]);
+    assert_eq!(VarU64(300).compile(), &[0xAC, 0x02]);
+    let mut max = vec![0xFF; 9];
+    max.push(0x01);
+    assert_eq!(VarU64(u64::MAX).compile(), max);
+    assert_eq!(VarU32(u32::MAX).compile(), &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+
+    assert_eq!(VarI64(0).compile(), &[0]);
+    assert_eq!(VarI64(-1).compile(), &[0x7F]);
+    assert_eq!(VarI64(63).compile(), &[0x3F]);
+    assert_eq!(VarI64(64).compile(), &[0xC0, 0x00]);
+    assert_eq!(VarI64(-64).compile(), &[0x40]);
+    assert_eq!(VarI64(-65).compile(), &[0xBF, 0x7F]);
+    let mut min = vec![0x80; 9];
+    min.push(0x7F);
+    assert_eq!(VarI64(i64::MIN).compile(), min);
+    assert_eq!(VarI32(i32::MIN).compile(), &[0x80, 0x80, 0x80, 0x80, 0x78]);
+
+    assert_eq!(ZigZagI64(0).compile(), &[0]);
+    assert_eq!(ZigZagI64(-1).compile(), &[1]);
+    assert_eq!(ZigZagI64(1).compile(), &[2]);
+    assert_eq!(ZigZagI64(-64).compile(), &[0x7F]);
+    assert_eq!(ZigZagI64(64).compile(), &[0x80, 0x01]);
+    assert_eq!(
+        ZigZagI32(i32::MIN).compile(),
+        &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]
+    );
+
+    // usize and isize are the same on all targets
+    assert_eq!(300_usize.compile(), &[0xAC, 0x02]);
+    assert_eq!((-3_isize).compile(), &[5]);
+    assert_eq!(usize::MAX_SIZE, Some(10));
+    assert_eq!(VarU32::MAX_SIZE, Some(5));
+    assert_eq!(VarI32::MIN_SIZE, 1);
+}
+
+#[test]
+fn test_varint_round_trip() {
+    let unsigned = [
+        0,
+        1,
+        63,
+        64,
+        127,
+        128,
+        16383,
+        16384,
+        u32::MAX as u64,
+        u64::MAX,
+    ];
+    let signed = [0, 1, -1, 63, -64, 64, -65, 8191, -8192, i64::MAX, i64::MIN];
+    assert_round_trip(unsigned.map(VarU64));
+    assert_round_trip(unsigned.map(|v| v as usize));
+    assert_round_trip(unsigned.map(|v| VarU32(v as u32)));
+    assert_round_trip(signed.map(VarI64));
+    assert_round_trip(signed.map(ZigZagI64));
+    assert_round_trip(signed.map(|v| v as isize));
+    assert_round_trip(signed.map(|v| VarI32(v as i32)));
+    assert_round_trip(signed.map(|v| ZigZagI32(v as i32)));
+}
+
+#[test]
+fn test_varint_errors() {
+    // only the shortest encoding of a value is valid
+    for bytes in [&[0x80, 0x00][..], &[0xFF, 0x00], &[0x80, 0x80, 0x00]] {
+        let e = VarU64::parse(bytes).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInstruction);
+        assert_eq!(e.bytes(), &bytes[..bytes.len().min(4)]);
+        assert_eq!(
+            kind(ZigZagI64::parse(bytes)),
+            Err(ErrorKind::InvalidInstruction)
+        );
+    }
+    for bytes in [&[0xFF, 0x7F][..], &[0x80, 0x00], &[0xC0, 0x80, 0x00]] {
+        assert_eq!(
+            kind(VarI64::parse(bytes)),
+            Err(ErrorKind::InvalidInstruction)
+        );
+    }
+
+    // values too large for the type
+    let mut too_large = vec![0xFF; 9];
+    too_large.push(0x02);
+    assert_eq!(
+        kind(VarU64::parse(&too_large)),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(VarI64::parse(&too_large)),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(VarU64::parse(&[0x80; 11])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(VarU32::parse(&[0x80, 0x80, 0x80, 0x80, 0x10])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(VarI32::parse(&[0x80, 0x80, 0x80, 0x80, 0x08])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+
+    let e = VarU64::parse(&[0x80, 0x80]).unwrap_err();
+    assert_eq!(e.needed(), Some(1));
+    assert_eq!(
+        VarI64::read_from(&mut &[0x80, 0x80][..]).unwrap_err(),
+        BytecodeError::incomplete(1)
+    );
+    assert_eq!(
+        kind(usize::read_from(&mut &[0x80, 0x00][..])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+}
+
+#[test]
+fn test_varint_fields() {
+    let t: VarOp<u64> = VarOp::Jump {
+        offset: -2,
+        count: 300,
+        flags: 7,
+    };
+    assert_eq!(t.compile(), &[0, 3, 0xAC, 0x02, 7]);
+    assert_eq!(t.encoded_len(), 5);
+    assert_eq!(VarOp::<u64>::parse(&t.compile()), Ok((t, 5)));
+    assert_eq!(VarOp::Push(5_u64).compile(), &[1, 5]);
+    assert_eq!(VarOp::Push(-5_i8).compile(), &[1, 9]);
+    assert_eq!(VarOp::<u8>::Index(128, -1).compile(), &[2, 0x80, 0x01, 1]);
+
+    assert_eq!(VarOp::<u8>::MIN_SIZE, 2);
+    // the Index variant is the largest, with 10 bytes for each of usize and isize
+    assert_eq!(VarOp::<u8>::MAX_SIZE, Some(1 + 10 + 10));
+
+    // values which do not fit in the field type are invalid
+    let e = VarOp::<u64>::parse(&[0, 0, 0x80, 0x80, 0x04, 0]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInstruction);
+    assert_eq!(e.offset(), 2);
+    assert_eq!(e.bytes(), &[0x80, 0x80, 0x04]);
+    assert_eq!(e.path().to_string(), "VarOp::Jump.count");
+    let e = VarOp::<u8>::parse(&[1, 0x80, 0x02]).unwrap_err();
+    assert_eq!(e.path().to_string(), "VarOp::Push.0");
+    assert_eq!(VarOp::<u8>::read_from(&mut &[1, 0x80, 0x02][..]), Err(e));
+}
+
+#[test]
+fn test_float_round_trip() {
+    assert_eq!(1.5_f32.compile(), 1.5_f32.to_le_bytes());
+    assert_eq!((-0.25_f64).compile(), (-0.25_f64).to_le_bytes());
+    assert_round_trip([0.0, -0.0, 1.5, f32::MIN_POSITIVE, f32::MAX, f32::INFINITY]);
+    assert_round_trip([0.0, -0.0, -2.75, f64::EPSILON, f64::MIN, f64::NEG_INFINITY]);
+
+    // NaNs are not equal to themselves, so these are compared by their bits,
+    // which must be kept as is, including the sign and payload
+    for bits in [0x7FC0_0000_u32, 0xFFC0_0001, 0x7F80_0001, 0x7FBF_FFFF] {
+        let (v, size) = f32::parse(&f32::from_bits(bits).compile()).unwrap();
+        assert_eq!((v.to_bits(), size), (bits, 4));
+    }
+    for bits in [
+        0x7FF8_0000_0000_0000_u64,
+        0xFFF0_0000_DEAD_BEEF,
+        0x7FF0_0000_0000_0001,
+    ] {
+        let bytes = f64::from_bits(bits).compile();
+        assert_eq!(f64::parse(&bytes).unwrap().0.to_bits(), bits);
+        assert_eq!(f64::read_from(&mut &bytes[..]).unwrap().to_bits(), bits);
+    }
+    assert_eq!(f64::parse(&[0; 7]), Err(BytecodeError::incomplete(1)));
+}
+
+#[test]
+fn test_wide_int_round_trip() {
+    assert_eq!(
+        0x0102_u128.compile(),
+        &[2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+    );
+    assert_round_trip([0, 1, u64::MAX as u128 + 1, u128::MAX]);
+    assert_round_trip([0, -1, i64::MIN as i128 - 1, i128::MIN, i128::MAX]);
+    assert_eq!(<u128 as FixedSize>::SIZE, 16);
+    assert_eq!(i128::parse(&[0; 15]), Err(BytecodeError::incomplete(1)));
+}
+
+#[test]
+fn test_char_round_trip() {
+    assert_eq!('A'.compile(), &[0x41, 0, 0, 0]);
+    assert_round_trip(['\0', 'A', 'é', '\u{D7FF}', '\u{E000}', '🦀', char::MAX]);
+
+    // surrogates and values above char::MAX are not chars
+    for v in [0xD800_u32, 0xDFFF, 0x11_0000, u32::MAX] {
+        let e = char::parse(&v.to_le_bytes()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Other("value is not a valid char"));
+        assert_eq!(e.bytes(), &v.to_le_bytes());
+        assert_eq!(char::read_from(&mut &v.to_le_bytes()[..]), Err(e));
+    }
+}
+
+#[test]
+fn test_non_zero_round_trip() {
+    assert_eq!(NonZeroU16::new(0x0102).unwrap().compile(), &[2, 1]);
+    assert_eq!(NonZeroIsize::new(-1).unwrap().compile(), &[1]);
+    assert_round_trip([1, 0x80, u8::MAX].map(|v| NonZeroU8::new(v).unwrap()));
+    assert_round_trip([1, u16::MAX].map(|v| NonZeroU16::new(v).unwrap()));
+    assert_round_trip([1, u32::MAX].map(|v| NonZeroU32::new(v).unwrap()));
+    assert_round_trip([1, u64::MAX].map(|v| NonZeroU64::new(v).unwrap()));
+    assert_round_trip([1, u128::MAX].map(|v| NonZeroU128::new(v).unwrap()));
+    assert_round_trip([1, 300, usize::MAX].map(|v| NonZeroUsize::new(v).unwrap()));
+    assert_round_trip([-1, i8::MIN, i8::MAX].map(|v| NonZeroI8::new(v).unwrap()));
+    assert_round_trip([-1, i16::MIN].map(|v| NonZeroI16::new(v).unwrap()));
+    assert_round_trip([-1, i32::MIN].map(|v| NonZeroI32::new(v).unwrap()));
+    assert_round_trip([-1, i64::MIN].map(|v| NonZeroI64::new(v).unwrap()));
+    assert_round_trip([-1, i128::MIN].map(|v| NonZeroI128::new(v).unwrap()));
+    assert_round_trip([-1, 300, isize::MIN].map(|v| NonZeroIsize::new(v).unwrap()));
+
+    let e = NonZeroU32::parse(&[0, 0, 0, 0, 1]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Other("value must not be zero"));
+    assert_eq!(e.bytes(), &[0, 0, 0, 0]);
+    assert_eq!(NonZeroU32::read_from(&mut &[0, 0, 0, 0][..]), Err(e));
+    let e = NonZeroUsize::parse(&[0]).unwrap_err();
+    assert_eq!(e.bytes(), &[0]);
+    assert_eq!(
+        kind(NonZeroI128::parse(&[0; 16])),
+        Err(ErrorKind::Other("value must not be zero"))
+    );
+    assert_eq!(NonZeroU64::parse(&[1]), Err(BytecodeError::incomplete(7)));
+    assert_eq!(<NonZeroI64 as FixedSize>::SIZE, 8);
+    assert_eq!(NonZeroUsize::MAX_SIZE, Some(10));
+}
+
+#[test]
+fn test_marker_types() {
+    assert_eq!(().compile(), &[]);
+    assert_eq!(<()>::parse(&[]), Ok(((), 0)));
+    assert_eq!(<PhantomData<String>>::parse(&[1]), Ok((PhantomData, 0)));
+    assert_eq!(<PhantomData<str> as FixedSize>::SIZE, 0);
+    assert_round_trip([Wrapping(0_u32), Wrapping(u32::MAX)]);
+    assert_eq!(Wrapping(-2_i16).compile(), &[0xFE, 0xFF]);
+
+    let l = Literal::<u8> {
+        value: -1.5,
+        c: 'λ',
+        id: NonZeroU16::new(7).unwrap(),
+        counter: Wrapping(255),
+        marker: PhantomData,
+        unit: (),
+    };
+    let bytes = l.compile();
+    assert_eq!(bytes.len(), 8 + 4 + 2 + 1);
+    assert_eq!(<Literal<u8> as FixedSize>::SIZE, 15);
+    assert_round_trip([l]);
+
+    let mut bytes = bytes;
+    bytes[12..14].copy_from_slice(&[0, 0]);
+    let e = Literal::<u8>::parse(&bytes).unwrap_err();
+    assert_eq!(e.offset(), 12);
+    assert_eq!(e.path().to_string(), "Literal.id");
+}
+
+#[test]
+fn test_option_result() {
+    assert_eq!(None::<u16>.compile(), &[0]);
+    assert_eq!(Some(0x0102_u16).compile(), &[1, 2, 1]);
+    assert_eq!(Ok::<u8, u16>(5).compile(), &[0, 5]);
+    assert_eq!(Err::<u8, u16>(5).compile(), &[1, 5, 0]);
+    assert_round_trip([None, Some(0_u32), Some(u32::MAX)]);
+    assert_round_trip([Some(None), Some(Some(-1_i8)), None]);
+    assert_round_trip([Ok(7_u8), Err(String::from("fault")), Ok(0)]);
+
+    assert_eq!(<Option<u16>>::MIN_SIZE, 1);
+    assert_eq!(<Option<u16>>::MAX_SIZE, Some(3));
+    assert_eq!(<Option<Vec<u8>>>::MAX_SIZE, None);
+    assert_eq!(<Result<u8, u32>>::MIN_SIZE, 2);
+    assert_eq!(<Result<u8, u32>>::MAX_SIZE, Some(5));
+
+    let e = <Option<u8>>::parse(&[2, 0]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInstruction);
+    assert_eq!(e.bytes(), &[2]);
+    assert_eq!(<Option<u8>>::read_from(&mut &[2, 0][..]), Err(e));
+    assert_eq!(<Option<u8>>::parse(&[]), Err(BytecodeError::incomplete(1)));
+    let e = <Result<u8, u32>>::parse(&[1, 0]).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.needed()),
+        (ErrorKind::IncompleteInstruction, Some(3))
+    );
+    assert_eq!(e.offset(), 1);
+    assert_eq!(
+        kind(<Result<u8, u8>>::parse(&[0xFF])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+}
+
+#[test]
+fn test_tuple_round_trip() {
+    assert_eq!((1_u8, 0x0203_u16).compile(), &[1, 3, 2]);
+    assert_round_trip([(5_u8,)]);
+    assert_round_trip([(1_u8, -1_i64, 'x'), (0, i64::MIN, '🦀')]);
+    assert_round_trip([(String::from("a"), (Some(3_u8), ()), VarU32(300))]);
+    let twelve = (
+        1_u8, 2_u16, 3_u32, 4_u64, 5_i8, 6_i16, 7_i32, 8_i64, 9_u8, 10_u8, 11_u8, 12_u8,
+    );
+    assert_round_trip([twelve]);
+    assert_eq!(twelve.encoded_len(), 34);
+
+    assert_eq!(<(u8, u16, bool) as FixedSize>::SIZE, 4);
+    assert_eq!(<(u8, Vec<u8>)>::MIN_SIZE, 2);
+    assert_eq!(<(u8, Vec<u8>)>::MAX_SIZE, None);
+
+    let e = <(u8, u16, u32)>::parse(&[1, 2, 3, 4]).unwrap_err();
+    assert_eq!((e.needed(), e.offset()), (Some(3), 3));
+    assert_eq!(<(u8, u16, u32)>::read_from(&mut &[1, 2, 3, 4][..]), Err(e));
+}
+
+#[test]
+fn test_array_round_trip() {
+    assert_eq!([1_u8, 2, 3].compile(), &[1, 2, 3]);
+    assert_eq!([0x0102_u16, 0x0304].compile(), &[2, 1, 4, 3]);
+    assert_round_trip([[0_u8; 0]]);
+    assert_round_trip([[0_u8; 32], [0xFF; 32]]);
+    assert_round_trip([[1_i32, -1, i32::MAX]]);
+    assert_round_trip([[VarU64(1), VarU64(300)], [VarU64(u64::MAX), VarU64(0)]]);
+    assert_round_trip([[[1_u8, 2], [3, 4]]]);
+
+    assert_eq!(<[u16; 3] as FixedSize>::SIZE, 6);
+    assert_eq!(<[VarU32; 2]>::MIN_SIZE, 2);
+    assert_eq!(<[VarU32; 2]>::MAX_SIZE, Some(10));
+    assert_eq!(<[Vec<u8>; 0]>::MAX_SIZE, Some(0));
+    assert_eq!(<[Vec<u8>; 1]>::MAX_SIZE, None);
+
+    // all the missing bytes are reported at once, for bytes and other types alike
+    assert_eq!(<[u8; 8]>::parse(&[0; 5]), Err(BytecodeError::incomplete(3)));
+    assert_eq!(
+        <[u32; 2]>::parse(&[0; 5]),
+        Err(BytecodeError::incomplete(3))
+    );
+    assert_eq!(
+        kind(<[u8; 4]>::read_from(&mut &[0; 3][..])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+    let e = <[SimpleEnum; 2]>::parse(&[0, 5]).unwrap_err();
+    assert_eq!((e.kind(), e.offset()), (ErrorKind::InvalidInstruction, 1));
+    assert_eq!(<[SimpleEnum; 2]>::read_from(&mut &[0, 5][..]), Err(e));
+}
+
+#[test]
+fn test_array_tuple_fields() {
+    let ops = [
+        ArrayOp::<u16, 2>::Load([0xAA, 0xBB], 0x0102),
+        ArrayOp::Regs([PackedReg::C, PackedReg::A, PackedReg::D]),
+        ArrayOp::Pair((7, -2), Some(9)),
+        ArrayOp::Pair((0, 0), None),
+        ArrayOp::Check {
+            result: Err(SimpleEnum::T1(1, 2)),
+            rest: ([3, 4], ()),
+        },
+    ];
+    assert_eq!(ops[0].compile(), &[0, 0xAA, 0xBB, 2, 1]);
+    assert_eq!(ops[2].compile(), &[2, 7, 0xFE, 0xFF, 1, 9, 0]);
+    assert_eq!(ops[4].compile(), &[3, 1, 1, 1, 2, 3, 0, 4, 0]);
+    assert_round_trip(ops);
+    assert_eq!(<ArrayOp<u8, 4>>::MAX_SIZE, Some(7));
+
+    let v = FixedArrays {
+        key: *b"BYTE",
+        pairs: [(1_u16, 2), (3, 4)],
+    };
+    assert_eq!(v.compile(), b"BYTE\x01\x00\x02\x03\x00\x04");
+    assert_eq!(<FixedArrays<u16> as FixedSize>::SIZE, 10);
+    assert_round_trip([v]);
+
+    let e = ArrayOp::<u8, 2>::parse(&[2, 1, 0, 0, 5]).unwrap_err();
+    assert_eq!(e.offset(), 4);
+    assert_eq!(e.path().to_string(), "ArrayOp::Pair.1");
+}
+
+#[test]
+fn test_endian_wrappers() {
+    assert_eq!(BigEndian(0x0102_u16).compile(), &[1, 2]);
+    assert_eq!(LittleEndian(0x0102_u16).compile(), &[2, 1]);
+    assert_eq!(BigEndian(-2_i32).compile(), &[0xFF, 0xFF, 0xFF, 0xFE]);
+    assert_eq!(BigEndian(1.0_f32).compile(), &[0x3F, 0x80, 0, 0]);
+    assert_eq!(BigEndian('A').compile(), &[0, 0, 0, 0x41]);
+    assert_eq!(BigEndian([0x0102_u16, 0x0304]).compile(), &[1, 2, 3, 4]);
+    assert_eq!(BigEndian(VarU32(300)).compile(), VarU32(300).compile());
+    assert_round_trip([
+        BigEndian(0_u64),
+        BigEndian(u64::MAX),
+        BigEndian(0x0102_0304),
+    ]);
+    assert_round_trip([BigEndian(i128::MIN), BigEndian(-1)]);
+    assert_round_trip([BigEndian(f64::MAX), BigEndian(-0.5)]);
+    assert_round_trip([LittleEndian(0x0102_u16)]);
+    assert_round_trip([BigEndian([[1_u32, 2], [3, 4]])]);
+    assert_eq!(<BigEndian<u32> as FixedSize>::SIZE, 4);
+    assert_eq!(<BigEndian<[u16; 3]> as FixedSize>::SIZE, 6);
+
+    assert_eq!(
+        BigEndian::<u32>::parse(&[0; 3]),
+        Err(BytecodeError::incomplete(1))
+    );
+    let e = BigEndian::<char>::parse(&[0, 0, 0xD8, 0]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Other("value is not a valid char"));
+    assert_eq!(e.bytes(), &[0, 0, 0xD8, 0]);
+    assert_eq!(
+        BigEndian::<char>::read_from(&mut &[0, 0, 0xD8, 0][..]),
+        Err(e)
+    );
+}
+
+#[test]
+fn test_big_endian_fields() {
+    let h = BigHeader {
+        magic: *b"\x7fELF",
+        version: 0x0102,
+        flags: 0x0A0B0C0D_u32,
+        checksum: 0x01020304,
+        count: 300,
+        name: String::from("hi"),
+        kind: SimpleEnum::T1(1, 2),
+        hi: 0xA,
+        lo: 0x5,
+    };
+    let bytes = h.compile();
+    assert_eq!(
+        bytes,
+        [
+            0x7F, b'E', b'L', b'F', 1, 2, 0x0A, 0x0B, 0x0C, 0x0D, 4, 3, 2, 1, 0xAC, 0x02, 0, 2,
+            b'h', b'i', 1, 1, 2, 0xA5
+        ]
+    );
+    assert_round_trip([h]);
+
+    let ops = [
+        BigOp::Nop,
+        BigOp::Move(0x0102, -2),
+        BigOp::Far {
+            addr: 0x0102_0304_0506_0708,
+            words: [0x0A0B, 0x0C0D],
+            c: 'λ',
+        },
+    ];
+    assert_eq!(ops[1].compile(), &[1, 1, 2, 0xFF, 0xFF, 0xFF, 0xFE]);
+    // the opcode is stored the same way, irrespective of the byte order of the fields
+    assert_eq!(
+        ops[2].compile(),
+        &[0x81, 0x00, 1, 2, 3, 4, 5, 6, 7, 8, 0x0A, 0x0B, 0x0C, 0x0D, 0, 0, 0x03, 0xBB]
+    );
+    assert_round_trip(ops);
+
+    let m = MixedOrder {
+        a: 0x0102,
+        b: 0x0102,
+        c: BigEndian(0x0A0B0C0D),
+        d: vec![BigEndian(0x0304), BigEndian(0x0506)],
+        e: LittleEndian(-2),
+    };
+    assert_eq!(
+        m.compile(),
+        &[2, 1, 1, 2, 0x0A, 0x0B, 0x0C, 0x0D, 0, 2, 3, 4, 5, 6, 0xFE, 0xFF]
+    );
+    assert_round_trip([m]);
+
+    let e = BigOp::parse(&[1, 1, 2, 0xFF]).unwrap_err();
+    assert_eq!((e.needed(), e.offset()), (Some(3), 3));
+    assert_eq!(e.path().to_string(), "BigOp::Move.1");
+    assert_eq!(BigOp::read_from(&mut &[1, 1, 2, 0xFF][..]), Err(e));
+}
+
+#[test]
+fn test_fixed_tags() {
+    assert_eq!(ByteTagOp::Nop.compile(), &[0]);
+    assert_eq!(ByteTagOp::Halt(7).compile(), &[0xFF, 7]);
+    assert_eq!(WordTagOp::Nop.compile(), &[0, 0]);
+    assert_eq!(WordTagOp::Load(0x0102).compile(), &[0xF0, 0x0D, 2, 1]);
+    assert_eq!(LongTagOp::Nop.compile(), &[0, 0, 0, 0]);
+    assert_eq!(LongTagOp::Call(0x0506).compile(), &[1, 2, 3, 4, 5, 6]);
+    assert_eq!(FixedTagOp::B(-1).compile(), &[0x34, 0x12, 0xFF]);
+    assert_round_trip([ByteTagOp::Nop, ByteTagOp::Halt(0x80)]);
+    assert_round_trip([WordTagOp::Nop, WordTagOp::Load(0xFFFF)]);
+    assert_round_trip([LongTagOp::Nop, LongTagOp::Call(0)]);
+    assert_round_trip([FixedTagOp::A(1), FixedTagOp::B(-1)]);
+    assert_eq!(<FixedTagOp as FixedSize>::SIZE, 3);
+    assert_eq!(WordTagOp::MIN_SIZE, 2);
+    assert_eq!(LongTagOp::MAX_SIZE, Some(6));
+
+    // bytes with the MSB set are not special for fixed tags
+    let e = ByteTagOp::parse(&[0x80, 0]).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.bytes()),
+        (ErrorKind::InvalidInstruction, &[0x80][..])
+    );
+    assert_eq!(ByteTagOp::read_from(&mut &[0x80, 0][..]), Err(e));
+    let e = WordTagOp::parse(&[0x0D, 0xF0, 0, 0]).unwrap_err();
+    assert_eq!(e.bytes(), &[0x0D, 0xF0]);
+    assert_eq!(WordTagOp::read_from(&mut &[0x0D, 0xF0][..]), Err(e));
+    assert_eq!(
+        LongTagOp::parse(&[1, 2]),
+        Err(BytecodeError::incomplete(2).within("LongTagOp"))
+    );
+    assert_eq!(
+        ByteTagOp::parse(&[]),
+        Err(BytecodeError::incomplete(1).within("ByteTagOp"))
+    );
+    assert_eq!(
+        kind(LongTagOp::read_from(&mut &[1, 2][..])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+}
+
+#[test]
+fn test_varint_tag() {
+    assert_eq!(VarTagOp::Nop.compile(), &[0]);
+    assert_eq!(VarTagOp::Mid(9).compile(), &[0xAC, 0x02, 9]);
+    assert_eq!(VarTagOp::Far.compile(), &[0x80, 0x80, 0x40]);
+    assert_round_trip([VarTagOp::Nop, VarTagOp::Mid(1), VarTagOp::Far]);
+    assert_eq!(VarTagOp::MIN_SIZE, 1);
+    assert_eq!(VarTagOp::MAX_SIZE, Some(3));
+
+    // unknown opcodes and overlong encodings are invalid
+    let e = VarTagOp::parse(&[0xAD, 0x02, 0]).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.bytes()),
+        (ErrorKind::InvalidInstruction, &[0xAD, 0x02][..])
+    );
+    assert_eq!(VarTagOp::read_from(&mut &[0xAD, 0x02, 0][..]), Err(e));
+    assert_eq!(
+        kind(VarTagOp::parse(&[0x80, 0])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(VarTagOp::parse(&[0xAC])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+    let e = VarTagOp::parse(&[0xAC, 0x02]).unwrap_err();
+    assert_eq!(
+        (e.offset(), e.path().to_string()),
+        (2, "VarTagOp::Mid.0".to_string())
+    );
+}
+
+#[test]
+fn test_alias_opcodes() {
+    // every alias is parsed, and the primary opcode is always compiled
+    for bytes in [[0x88, 7], [0x8A, 7], [0x89, 7]] {
+        assert_eq!(AliasOp::parse(&bytes), Ok((AliasOp::Mov(7), 2)));
+        assert_eq!(AliasOp::read_from(&mut &bytes[..]), Ok(AliasOp::Mov(7)));
+    }
+    assert_eq!(AliasOp::Mov(7).compile(), &[0x88, 7]);
+
+    // aliases are skipped when assigning the opcodes automatically
+    assert_eq!(AliasOp::parse(&[0x01]), Ok((AliasOp::Hlt, 1)));
+    assert_eq!(AliasOp::Hlt.compile(), &[0x02]);
+    assert_round_trip([AliasOp::Nop, AliasOp::Mov(1), AliasOp::Hlt]);
+    assert_eq!(
+        kind(AliasOp::parse(&[0x8B, 0])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+
+    // the alias can take more bytes than the primary opcode
+    let bytes = [0x81, 0x00, 5];
+    assert_eq!(
+        CompactAliasOp::parse(&bytes),
+        Ok((CompactAliasOp::Push(5), 3))
+    );
+    assert_eq!(
+        CompactAliasOp::read_from(&mut &bytes[..]),
+        Ok(CompactAliasOp::Push(5))
+    );
+    assert_eq!(CompactAliasOp::Push(5).compile(), &[0, 5]);
+    assert_eq!(CompactAliasOp::MIN_SIZE, 1);
+    assert_eq!(CompactAliasOp::MAX_SIZE, Some(3));
+    let e = CompactAliasOp::parse(&[0x81, 0x00]).unwrap_err();
+    assert_eq!(
+        (e.offset(), e.path().to_string()),
+        (2, "CompactAliasOp::Push.0".to_string())
+    );
+
+    // the unknown variant cannot hold an alias either
+    assert_eq!(
+        kind(CompactAliasOp::Unknown(0x100).compile_into(&mut vec![])),
+        Err(ErrorKind::Other(
+            "unknown variant holds an opcode which is assigned or out of range"
+        ))
+    );
+}
+
+#[test]
+fn test_reserved_opcodes() {
+    // the reserved opcodes are skipped when numbering the variants
+    assert_eq!(ReservedOp::Nop.compile(), &[0x00]);
+    assert_eq!(ReservedOp::Push(7).compile(), &[0x01, 7]);
+    assert_eq!(ReservedOp::Loop.compile(), &[0x04]);
+    assert_eq!(ReservedOp::Jmp { target: 1 }.compile(), &[0x05, 1, 0]);
+    assert_eq!(ReservedOp::OldCall(2).compile(), &[0x20, 2, 0]);
+    assert_round_trip([
+        ReservedOp::Nop,
+        ReservedOp::Push(7),
+        ReservedOp::Loop,
+        ReservedOp::Jmp { target: 0x1234 },
+        ReservedOp::OldCall(2),
+        ReservedOp::Unknown(0x30),
+    ]);
+
+    // and are rejected by parse, even though there is an unknown variant
+    for op in [0x02, 0x03, 0x10, 0x1F] {
+        let e = ReservedOp::parse(&[op, 0, 0]).unwrap_err();
+        assert_eq!(
+            (e.kind(), e.bytes()),
+            (ErrorKind::InvalidInstruction, &[op][..])
+        );
+        assert_eq!(ReservedOp::read_from(&mut &[op, 0, 0][..]), Err(e));
+        assert_eq!(
+            kind(ReservedOp::Unknown(op).compile_into(&mut vec![])),
+            Err(ErrorKind::Other(
+                "unknown variant holds an opcode which is assigned or out of range"
+            ))
+        );
+    }
+    assert_eq!(ReservedOp::parse(&[0x04, 0x21]), Ok((ReservedOp::Loop, 1)));
+    assert_eq!(
+        ReservedOp::parse(&[0x21]),
+        Ok((ReservedOp::Unknown(0x21), 1))
+    );
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_assembler_helpers() {
+    assert_eq!(ReservedOp::nop(), ReservedOp::Nop);
+    assert_eq!(ReservedOp::push(3), ReservedOp::Push(3));
+    // keywords are raw identifiers
+    assert_eq!(ReservedOp::r#loop(), ReservedOp::Loop);
+    assert_eq!(ReservedOp::unknown(0x30), ReservedOp::Unknown(0x30));
+
+    // the deprecated variants still decode, and can be assembled with a warning
+    assert_eq!(ReservedOp::jmp(9), ReservedOp::Jmp { target: 9 });
+    assert_eq!(ReservedOp::old_call(9), ReservedOp::OldCall(9));
+    const CALL: ReservedOp = ReservedOp::old_call(1);
+    assert_eq!(ReservedOp::parse(&[0x20, 1, 0]), Ok((CALL, 3)));
+}
+
+#[test]
+fn test_prefix_groups() {
+    // the opcodes in each group are numbered separately
+    for (op, bytes) in [
+        (PrefixOp::Nop, &[0x00][..]),
+        (PrefixOp::Push(5), &[0x01, 5][..]),
+        (PrefixOp::Hlt, &[0x02][..]),
+        (PrefixOp::Syscall, &[0x0F, 0x00][..]),
+        (PrefixOp::Cpuid, &[0x0F, 0xA2][..]),
+        (PrefixOp::Jcc(-1), &[0x0F, 0x01, 0xFF, 0xFF, 0xFF, 0xFF][..]),
+        (PrefixOp::Fadd(3), &[0xD8, 0x00, 3][..]),
+    ] {
+        assert_eq!(op.compile(), bytes);
+        assert_eq!(op.encoded_len(), bytes.len());
+        assert_eq!(PrefixOp::parse(bytes), Ok((op, bytes.len())));
+        assert_eq!(
+            PrefixOp::read_from(&mut &*bytes),
+            PrefixOp::parse(bytes).map(|v| v.0)
+        );
+    }
+    assert_eq!(PrefixOp::MIN_SIZE, 1);
+    assert_eq!(PrefixOp::MAX_SIZE, Some(6));
+    assert_eq!(
+        PrefixOp::parse(&[0x0F, 0x06, 1, 0, 0, 0]),
+        Ok((PrefixOp::Jcc(1), 6))
+    );
+
+    // errors after the prefix are offset by its length
+    let e = PrefixOp::parse(&[0x0F, 0x50]).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.offset(), e.bytes()),
+        (ErrorKind::InvalidInstruction, 1, &[0x50][..])
+    );
+    assert_eq!(PrefixOp::read_from(&mut &[0x0F, 0x50][..]), Err(e));
+    assert_eq!(
+        kind(PrefixOp::parse(&[0x0F])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+    let e = PrefixOp::parse(&[0x0F, 0x01, 0]).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.offset(), e.path().to_string()),
+        (
+            ErrorKind::IncompleteInstruction,
+            2,
+            "PrefixOp::Jcc.0".to_string()
+        )
+    );
+    assert_eq!(PrefixOp::read_from(&mut &[0x0F, 0x01, 0][..]), Err(e));
+    // secondary opcodes are not valid without the prefix
+    assert_eq!(
+        kind(PrefixOp::parse(&[0xA2])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+
+    // the prefix is not given to the unknown variant
+    let bytes = [0x7F, 0x81, 0x00, 9];
+    assert_eq!(
+        CompactPrefixOp::parse(&bytes),
+        Ok((CompactPrefixOp::Ext(9), 4))
+    );
+    assert_eq!(CompactPrefixOp::Ext(9).compile(), &bytes);
+    assert_eq!(
+        CompactPrefixOp::parse(&[0x7E]),
+        Ok((CompactPrefixOp::Unknown(0x7E), 1))
+    );
+    assert_eq!(
+        kind(CompactPrefixOp::parse(&[0x7F, 0x05])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(CompactPrefixOp::Unknown(0x7F).compile_into(&mut vec![])),
+        Err(ErrorKind::Other(
+            "unknown variant holds an opcode which is assigned or out of range"
+        ))
+    );
+}
+
+#[test]
+fn test_unknown_variant() {
+    // the opcodes which are not assigned give the unknown variant, which compiles them back
+    for (bytes, op) in [
+        (&[2][..], UnknownOp::Unknown(2)),
+        (&[0x7F][..], UnknownOp::Unknown(0x7F)),
+        (&[0x81, 0x23][..], UnknownOp::Unknown(0x123)),
+        (&[0xFF, 0xFF][..], UnknownOp::Unknown(0x7FFF)),
+    ] {
+        assert_eq!(UnknownOp::parse(bytes), Ok((op.clone(), bytes.len())));
+        assert_eq!(UnknownOp::read_from(&mut &*bytes), Ok(op.clone()));
+        assert_eq!(op.encoded_len(), bytes.len());
+        assert_eq!(op.compile(), bytes);
+    }
+    // the operands of the unknown opcode are not known, so they are left for the caller
+    assert_eq!(UnknownOp::parse(&[5, 1, 2]), Ok((UnknownOp::Unknown(5), 1)));
+    assert_round_trip([
+        UnknownOp::Nop,
+        UnknownOp::Push(3),
+        UnknownOp::Jump(0x1234),
+        UnknownOp::Unknown(9),
+    ]);
+    assert_eq!(UnknownOp::MIN_SIZE, 1);
+    assert_eq!(UnknownOp::MAX_SIZE, Some(4));
+
+    // the two byte form of single byte opcodes is still invalid
+    assert_eq!(
+        kind(UnknownOp::parse(&[0x80, 0x05])),
+        Err(ErrorKind::InvalidInstruction)
+    );
+    assert_eq!(
+        kind(UnknownOp::parse(&[0x81])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+
+    // opcodes of other variants, or too large for the tag, would not parse back the same
+    let err = Err(ErrorKind::Other(
+        "unknown variant holds an opcode which is assigned or out of range",
+    ));
+    for op in [0, 1, 0x200, 0x8000] {
+        assert_eq!(kind(UnknownOp::Unknown(op).compile_into(&mut vec![])), err);
+    }
+
+    assert_eq!(
+        UnknownByteOp::parse(&[0x10, 7]),
+        Ok((UnknownByteOp::Halt, 1))
+    );
+    assert_eq!(
+        UnknownByteOp::parse(&[0xFE, 7]),
+        Ok((UnknownByteOp::Unknown { opcode: 0xFE }, 1))
+    );
+    assert_eq!(UnknownByteOp::Unknown { opcode: 0 }.compile(), &[0]);
+    assert_eq!(
+        kind(UnknownByteOp::Unknown { opcode: 0x10 }.compile_into(&mut vec![])),
+        err
+    );
+    assert_eq!(<UnknownByteOp as FixedSize>::SIZE, 1);
+}
+
+#[test]
+fn test_embedded_fields() {
+    // the field is set in the lower bits of the opcode
+    for (op, bytes) in [
+        (EmbedOp::Nop, &[0x00][..]),
+        (EmbedOp::Hlt, &[0x01][..]),
+        (EmbedOp::Inc(7), &[0x47][..]),
+        (EmbedOp::Dec(PackedReg::D), &[0x4B][..]),
+        (EmbedOp::Push(Reg16::Ax), &[0x50][..]),
+        (EmbedOp::Push(Reg16::Di), &[0x57][..]),
+        (EmbedOp::Pop(Reg16::Bx), &[0x5B][..]),
+        (
+            EmbedOp::MovImm {
+                imm: 0x1234,
+                dst: Reg16::Cx,
+            },
+            &[0xB9, 0x34, 0x12][..],
+        ),
+        (EmbedOp::Bswap(Reg16::Sp), &[0x0F, 0xCC][..]),
+        (EmbedOp::Unknown(0x60), &[0x60][..]),
+    ] {
+        assert_eq!(op.compile(), bytes);
+        assert_eq!(op.encoded_len(), bytes.len());
+        assert_eq!(EmbedOp::parse(bytes), Ok((op, bytes.len())));
+        assert_eq!(
+            EmbedOp::read_from(&mut &*bytes),
+            EmbedOp::parse(bytes).map(|v| v.0)
+        );
+    }
+    assert_eq!(EmbedOp::MIN_SIZE, 1);
+    assert_eq!(EmbedOp::MAX_SIZE, Some(3));
+
+    // values which do not fit in the bits, or are not valid for the field, give an error
+    assert_eq!(
+        kind(EmbedOp::Inc(8).compile_into(&mut vec![])),
+        Err(ErrorKind::Other(
+            "value of field `0` does not fit in 3 bits"
+        ))
+    );
+    let e = EmbedOp::parse(&[0x4C, 0xFF]).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.offset(), e.bytes(), e.path().to_string()),
+        (
+            ErrorKind::InvalidInstruction,
+            0,
+            &[0x4C][..],
+            "EmbedOp::Dec.0".to_string()
+        )
+    );
+    assert_eq!(EmbedOp::read_from(&mut &[0x4C][..]), Err(e));
+    let e = EmbedOp::parse(&[0x0F, 0xB8]).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.offset(), e.bytes()),
+        (ErrorKind::InvalidInstruction, 1, &[0xB8][..])
+    );
+
+    // the whole range is assigned to the variant, so the unknown variant cannot hold it
+    assert_eq!(
+        kind(EmbedOp::Unknown(0x52).compile_into(&mut vec![])),
+        Err(ErrorKind::Other(
+            "unknown variant holds an opcode which is assigned or out of range"
+        ))
+    );
+
+    // the length of the opcode can depend on the value of the field
+    for (op, bytes) in [
+        (CompactEmbedOp::Short(0x7F), &[0x7F][..]),
+        (CompactEmbedOp::Short(0x80), &[0x80, 0x80][..]),
+        (CompactEmbedOp::Long(1), &[0x81, 0x00, 1, 0][..]),
+    ] {
+        assert_eq!(op.compile(), bytes);
+        assert_eq!(op.encoded_len(), bytes.len());
+        assert_eq!(CompactEmbedOp::parse(bytes), Ok((op, bytes.len())));
+    }
+    assert_eq!(CompactEmbedOp::MIN_SIZE, 1);
+    assert_eq!(CompactEmbedOp::MAX_SIZE, Some(4));
+}
+
+#[test]
+fn test_instruction_words() {
+    for (op, bytes) in [
+        (
+            RiscOp::Add {
+                rd: 1,
+                rs1: 2,
+                rs2: 3,
+            },
+            0x003100B3_u32,
+        ),
+        (
+            RiscOp::Sub {
+                rd: 31,
+                rs1: 0,
+                rs2: 1,
+            },
+            0x40100FB3,
+        ),
+        (
+            RiscOp::Addi {
+                rd: 1,
+                rs1: 0,
+                imm: -1,
+            },
+            0xFFF00093,
+        ),
+        (
+            RiscOp::Addi {
+                rd: 2,
+                rs1: 2,
+                imm: 2047,
+            },
+            0x7FF10113,
+        ),
+        // the immediate is split over the word, and its lowest bit is not stored
+        (
+            RiscOp::Beq {
+                rs1: 1,
+                rs2: 2,
+                offset: -4,
+            },
+            0xFE208EE3,
+        ),
+        (
+            RiscOp::Beq {
+                rs1: 0,
+                rs2: 0,
+                offset: 0x800,
+            },
+            0x00000063 | 1 << 7,
+        ),
+        (RiscOp::Fence, 0x0000000F),
+        (RiscOp::Ecall, 0x00000073),
+    ]
+    .map(|(op, word)| (op, word.to_le_bytes()))
+    {
+        assert_eq!(op.compile(), bytes);
+        assert_eq!(op.encoded_len(), 4);
+        assert_eq!(RiscOp::parse(&bytes), Ok((op, 4)));
+        assert_eq!(
+            RiscOp::read_from(&mut &bytes[..]),
+            RiscOp::parse(&bytes).map(|v| v.0)
+        );
+    }
+    assert_eq!((RiscOp::MIN_SIZE, RiscOp::MAX_SIZE), (4, Some(4)));
+    assert_eq!(<RiscOp as FixedSize>::SIZE, 4);
+    assert_eq!(
+        RiscOp::addi(1, 0, -1).compile(),
+        0xFFF00093_u32.to_le_bytes()
+    );
+    assert_eq!(RiscOp::ecall(), RiscOp::Ecall);
+
+    // the bits outside the mask are not compared
+    assert_eq!(
+        RiscOp::parse(&0x0FF0000F_u32.to_le_bytes()),
+        Ok((RiscOp::Fence, 4))
+    );
+    // the remaining bytes are not consumed
+    assert_eq!(
+        RiscOp::parse(&[0x73, 0, 0, 0, 0x73]),
+        Ok((RiscOp::Ecall, 4))
+    );
+    let e = RiscOp::parse(&[0xFF, 0xFF, 0xFF, 0xFF, 0]).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.bytes(), e.path().to_string()),
+        (
+            ErrorKind::InvalidInstruction,
+            &[0xFF; 4][..],
+            "RiscOp".to_string()
+        )
+    );
+    assert_eq!(RiscOp::read_from(&mut &[0xFF; 4][..]), Err(e));
+    assert_eq!(
+        kind(RiscOp::parse(&[0x73, 0, 0])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+    assert_eq!(
+        kind(RiscOp::read_from(&mut &[0x73, 0, 0][..])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+
+    // values which cannot be stored in the bits give an error
+    assert_eq!(
+        kind(RiscOp::addi(1, 0, 2048).compile_into(&mut vec![])),
+        Err(ErrorKind::Other(
+            "value of field `imm` does not fit in 12 bits"
+        ))
+    );
+    assert_eq!(
+        kind(RiscOp::beq(1, 2, 4096).compile_into(&mut vec![])),
+        Err(ErrorKind::Other(
+            "value of field `offset` does not fit in 13 bits"
+        ))
+    );
+    assert_eq!(
+        kind(RiscOp::beq(1, 2, 3).compile_into(&mut vec![])),
+        Err(ErrorKind::Other(
+            "value of field `offset` has some of the lower 1 bits set"
+        ))
+    );
+    assert_eq!(
+        kind(RiscOp::add(32, 0, 0).compile_into(&mut vec![])),
+        Err(ErrorKind::Other(
+            "value of field `rd` does not fit in 5 bits"
+        ))
+    );
+
+    // the byte order of the word is given on the enum
+    for (op, bytes) in [
+        (ThumbOp::Movs(1, 5), [0x21, 0x05]),
+        (ThumbOp::Push(PackedReg::D), [0xB4, 0x03]),
+        (ThumbOp::Nop, [0xBF, 0x00]),
+    ] {
+        assert_eq!(op.compile(), bytes);
+        assert_eq!(ThumbOp::parse(&bytes), Ok((op, 2)));
+    }
+    let e = ThumbOp::parse(&[0xB4, 0x05]).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.bytes(), e.path().to_string()),
+        (
+            ErrorKind::InvalidInstruction,
+            &[0xB4, 0x05][..],
+            "ThumbOp::Push.0".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_dependent_fields() {
+    let modrm = ModRm::new(0b01, 3, 6);
+    assert_eq!(
+        (modrm.mode(), modrm.reg(), modrm.rm(), modrm.to_byte()),
+        (1, 3, 6, 0x5E)
+    );
+    assert_eq!(ModRm::from_byte(0x5E), modrm);
+    assert!(ModRm::new(0b11, 0, 1).is_register());
+
+    for (op, bytes) in [
+        // [bx+si]
+        (
+            X86Op::MovRmReg {
+                modrm: ModRm::new(0b00, 1, 0),
+                disp: Displacement::None,
+            },
+            vec![0x89, 0x08],
+        ),
+        // direct address
+        (
+            X86Op::MovRmReg {
+                modrm: ModRm::new(0b00, 0, 6),
+                disp: Displacement::Disp16(0x1234),
+            },
+            vec![0x89, 0x06, 0x34, 0x12],
+        ),
+        // [bp-2]
+        (
+            X86Op::MovRmReg {
+                modrm: ModRm::new(0b01, 3, 6),
+                disp: Displacement::Disp8(-2),
+            },
+            vec![0x89, 0x5E, 0xFE],
+        ),
+        // [di+0x100]
+        (
+            X86Op::MovRmReg {
+                modrm: ModRm::new(0b10, 2, 5),
+                disp: Displacement::Disp16(0x100),
+            },
+            vec![0x89, 0x95, 0x00, 0x01],
+        ),
+        // register to register
+        (
+            X86Op::MovRmReg {
+                modrm: ModRm::new(0b11, 0, 3),
+                disp: Displacement::None,
+            },
+            vec![0x89, 0xC3],
+        ),
+        (
+            X86Op::MovRmImm(ModRm::new(0b01, 0, 7), Displacement::Disp8(4), 0xBEEF),
+            vec![0xC7, 0x47, 0x04, 0xEF, 0xBE],
+        ),
+    ] {
+        assert_eq!(op.compile(), bytes);
+        assert_eq!(X86Op::parse(&bytes), Ok((op, bytes.len())));
+    }
+    assert_round_trip([
+        X86Op::MovRmImm(ModRm::new(0b10, 0, 1), Displacement::Disp16(-300), 7),
+        X86Op::MovRmImm(ModRm::new(0b00, 0, 4), Displacement::None, 1),
+    ]);
+    assert_eq!(Displacement::Disp8(-2).value(), -2);
+    // the displacement is absent or up to two bytes
+    assert_eq!((X86Op::MIN_SIZE, X86Op::MAX_SIZE), (2, Some(6)));
+
+    // the displacement has to match the mode
+    assert_eq!(
+        kind(
+            X86Op::MovRmReg {
+                modrm: ModRm::new(0b01, 0, 0),
+                disp: Displacement::Disp16(0x100),
+            }
+            .compile_into(&mut vec![])
+        ),
+        Err(ErrorKind::Other(
+            "value does not match the addressing mode of the ModR/M byte"
+        ))
+    );
+    let e = X86Op::parse(&[0x89, 0x86, 0x00]).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.needed(), e.path().to_string()),
+        (
+            ErrorKind::IncompleteInstruction,
+            Some(1),
+            "X86Op::MovRmReg.disp".to_string()
+        )
+    );
+    assert_eq!(
+        kind(X86Op::read_from(&mut &[0x89, 0x86, 0x00][..])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+
+    // [eax+ecx*4+8] has a SIB byte, followed by an 8 bit displacement
+    let addr = Addr32 {
+        modrm: ModRm::new(0b01, 0, 4),
+        sib: Some(Sib::new(2, 1, 0)),
+        disp: Disp32::Disp8(8),
+    };
+    assert_eq!(addr.compile(), [0x44, 0x88, 0x08]);
+    assert_eq!(addr.modrm.disp_len32(addr.sib), 1);
+    assert_eq!(
+        ModRm::new(0b00, 0, 4).disp_len32(Some(Sib::new(0, 4, 5))),
+        4
+    );
+    // mode 0 with rm 5 is a 32 bit address, as is the base 5 of a SIB byte in mode 0
+    let direct = Addr32 {
+        modrm: ModRm::new(0b00, 0, 5),
+        sib: None,
+        disp: Disp32::Disp32(0x1234_5678),
+    };
+    assert_eq!(direct.compile(), [0x05, 0x78, 0x56, 0x34, 0x12]);
+    let no_base = Addr32 {
+        modrm: ModRm::new(0b00, 0, 4),
+        sib: Some(Sib::new(3, 1, 5)),
+        disp: Disp32::Disp32(-4),
+    };
+    assert_eq!(no_base.compile(), [0x04, 0xCD, 0xFC, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(Disp32::Disp8(-2).value(), -2);
+    assert_eq!((Addr32::MIN_SIZE, Addr32::MAX_SIZE), (1, Some(6)));
+    assert_round_trip([
+        addr,
+        direct,
+        no_base,
+        Addr32 {
+            modrm: ModRm::new(0b00, 0, 3),
+            sib: None,
+            disp: Disp32::None,
+        },
+        Addr32 {
+            modrm: ModRm::new(0b10, 0, 4),
+            sib: Some(Sib::new(0, 4, 5)),
+            disp: Disp32::Disp32(0x100),
+        },
+        Addr32 {
+            modrm: ModRm::new(0b10, 0, 1),
+            sib: None,
+            disp: Disp32::Disp32(-300),
+        },
+    ]);
+    assert_eq!(
+        kind(
+            Addr32 {
+                modrm: ModRm::new(0b00, 0, 5),
+                sib: None,
+                disp: Disp32::Disp8(1),
+            }
+            .compile_into(&mut vec![])
+        ),
+        Err(ErrorKind::Other(
+            "value does not match the addressing mode of the ModR/M byte"
+        ))
+    );
+    assert_eq!(
+        kind(Addr32::parse(&[0x04, 0x25, 0x00])),
+        Err(ErrorKind::IncompleteInstruction)
+    );
+    assert_eq!(
+        kind(
+            Addr32 {
+                modrm: ModRm::new(0b11, 0, 4),
+                sib: Some(Sib::new(0, 0, 0)),
+                disp: Disp32::None,
+            }
+            .compile_into(&mut vec![])
+        ),
+        Err(ErrorKind::Other(
+            "value does not match the addressing mode of the ModR/M byte"
+        ))
+    );
+
+    // fields can depend on bit-packed fields, and on user types
+    assert_round_trip([
+        ImmOperand {
+            wide: true,
+            reg: 5,
+            imm: Imm(0x1234),
+        },
+        ImmOperand {
+            wide: false,
+            reg: 1,
+            imm: Imm(0x12),
+        },
+    ]);
+    assert_eq!(
+        ImmOperand {
+            wide: false,
+            reg: 1,
+            imm: Imm(0x12),
+        }
+        .compile(),
+        [0x01, 0x12]
+    );
+    assert_eq!(
+        (ImmOperand::<Imm>::MIN_SIZE, ImmOperand::<Imm>::MAX_SIZE),
+        (2, Some(3))
+    );
+}
+
+#[test]
+fn test_big_endian_containers() {
+    let c = BigContainers {
+        a: Some(0x0102),
+        b: vec![0x0102_0304],
+        c: (5, 0x0607),
+        d: Err(0x0A0B_0C0D),
+        e: VecDeque::from([0x0102]),
+        f: Box::new([0x0304]),
+        g: NonZeroU16::new(0x0506).unwrap(),
+        h: Wrapping(0x0102_0304),
+        i: vec![0x0708, 0x090A],
+    };
+    assert_eq!(
+        c.compile(),
+        [
+            1, 1, 2, 1, 1, 2, 3, 4, 5, 6, 7, 1, 0xA, 0xB, 0xC, 0xD, 1, 1, 2, 1, 3, 4, 5, 6, 1, 2,
+            3, 4, 2, 7, 8, 9, 0xA
+        ]
+    );
+    assert_round_trip([
+        c,
+        BigContainers {
+            a: None,
+            b: vec![],
+            c: (0, 1),
+            d: Ok(-2),
+            e: VecDeque::new(),
+            f: Box::new([]),
+            g: NonZeroU16::new(1).unwrap(),
+            h: Wrapping(0),
+            i: vec![],
+        },
+    ]);
+
+    assert_eq!(BigEndian(Some(0x0102_u16)).compile(), &[1, 1, 2]);
+    assert_eq!(
+        BigEndian(vec![(0x0102_u16, 0x03_u8)]).compile(),
+        &[1, 1, 2, 3]
+    );
+    assert_round_trip([BigEndian(Ok::<_, u8>(vec![Some(0x0102_u16)]))]);
+    assert_eq!(
+        kind(BigEndian::<NonZeroU16>::parse(&[0, 0])),
+        Err(ErrorKind::Other("value must not be zero"))
     );
 }
@@ -0,0 +1,6 @@
+// These pin the error messages the derive gives for incorrect input
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,21 @@
+#![deny(deprecated)]
+
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+#[bytecode(assembler)]
+pub enum Opcode {
+    Nop,
+    #[bytecode(deprecated)]
+    Jmp(u16),
+    #[bytecode(opcode = 0x10, deprecated = "use `call` instead")]
+    OldCall { target: u16 },
+}
+
+fn main() {
+    // the variants themselves can still be used, such as when decoding
+    let _ = Opcode::Jmp(1);
+    let _ = Opcode::nop();
+    let _ = Opcode::jmp(1);
+    let _ = Opcode::old_call(2);
+}
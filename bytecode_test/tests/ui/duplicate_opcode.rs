@@ -0,0 +1,14 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+enum Opcode {
+    Nop,
+    #[bytecode(opcode = 0)]
+    Hlt,
+    #[bytecode(opcode = 5)]
+    Add(u8),
+    #[bytecode(opcode = 5)]
+    Sub(u8),
+}
+
+fn main() {}
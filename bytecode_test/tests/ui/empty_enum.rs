@@ -0,0 +1,10 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+enum Empty {}
+
+#[derive(Bytecode)]
+#[bytecode(word = 2)]
+enum EmptyWord {}
+
+fn main() {}
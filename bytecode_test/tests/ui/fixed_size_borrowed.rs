@@ -0,0 +1,7 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+#[bytecode(fixed_size)]
+struct Name<'a>(&'a str);
+
+fn main() {}
@@ -0,0 +1,32 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+enum Collision {
+    Nop,
+    #[bytecode(alias = [0x10, 0x00])]
+    Mov,
+    #[bytecode(opcode = 0x10)]
+    Jmp,
+}
+
+#[derive(Bytecode)]
+#[bytecode(tag = "u8")]
+enum TooLarge {
+    #[bytecode(alias = [0x100])]
+    Nop,
+}
+
+#[derive(Bytecode)]
+enum NotLiteral {
+    #[bytecode(alias = [ALIAS])]
+    Nop,
+}
+
+#[derive(Bytecode)]
+enum UnknownAlias {
+    Nop,
+    #[bytecode(unknown, alias = 5)]
+    Unknown(u8),
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+struct Packed {
+    #[bytecode(bits = 0)]
+    x: u8,
+    #[bytecode(bits = 65)]
+    y: u64,
+}
+
+fn main() {}
@@ -0,0 +1,48 @@
+use bytecode::{Bytecode, Disp32, Displacement, ModRm, Sib};
+
+#[derive(Bytecode)]
+struct Mov {
+    #[bytecode(depends = modrm)]
+    disp: Displacement,
+    modrm: ModRm,
+}
+
+#[derive(Bytecode)]
+struct Lea(ModRm, #[bytecode(depends = 2)] Displacement);
+
+#[derive(Bytecode)]
+struct Jmp {
+    modrm: ModRm,
+    #[bytecode(depends = reg)]
+    disp: Displacement,
+    #[bytecode(depends = modrm, endian = "big")]
+    far: Displacement,
+}
+
+#[derive(Bytecode)]
+struct Load<'a> {
+    modrm: ModRm,
+    #[bytecode(depends = modrm)]
+    data: &'a [u8],
+}
+
+#[derive(Bytecode)]
+#[bytecode(fixed_size)]
+struct Store {
+    modrm: ModRm,
+    #[bytecode(depends = modrm)]
+    disp: Displacement,
+}
+
+#[derive(Bytecode)]
+struct Addr {
+    modrm: ModRm,
+    #[bytecode(depends = (modrm, sib))]
+    disp: Disp32,
+    #[bytecode(depends = modrm)]
+    sib: Option<Sib>,
+    #[bytecode(depends = ())]
+    far: Displacement,
+}
+
+fn main() {}
@@ -0,0 +1,46 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+#[bytecode(tag = "u8")]
+enum InvalidRange {
+    #[bytecode(opcode = 0x51, embed(field = 0, bits = 3))]
+    Push(u8),
+    #[bytecode(opcode = 0x100, embed(field = 0, bits = 3))]
+    Large(u8),
+}
+
+#[derive(Bytecode)]
+enum Overlap {
+    #[bytecode(opcode = 0x53)]
+    Nop,
+    #[bytecode(opcode = 0x50, embed(field = 0, bits = 3))]
+    Push(u8),
+}
+
+#[derive(Bytecode)]
+enum InvalidField {
+    #[bytecode(opcode = 0x50, embed(field = 1, bits = 3))]
+    Position(u8),
+    #[bytecode(opcode = 0x58, embed(field = reg, bits = 3))]
+    Name { dst: u8 },
+    #[bytecode(opcode = 0x60, embed(field = 0, bits = 3))]
+    Attrs(#[bytecode(varint)] u32),
+    #[bytecode(opcode = 0x70, embed(field = 0, bits = 9))]
+    Bits(u16),
+    #[bytecode(embed(field = 0))]
+    Incomplete(u8),
+    #[bytecode(embed = 0)]
+    Value(u8),
+}
+
+#[derive(Bytecode)]
+enum InvalidVariant {
+    #[bytecode(embed(field = 0, bits = 3))]
+    Auto(u8),
+    #[bytecode(opcode = 0x50, alias = 0x60, embed(field = 0, bits = 3))]
+    Alias(u8),
+    #[bytecode(unknown, embed(field = 0, bits = 3))]
+    Unknown(u8),
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+#[bytecode(endian = "middle")]
+struct Header {
+    #[bytecode(endian = big)]
+    version: u16,
+    #[bytecode(varint, endian = "big")]
+    count: u32,
+}
+
+#[derive(Bytecode)]
+enum Op<'a> {
+    Print(#[bytecode(endian = "big")] &'a str),
+}
+
+fn main() {}
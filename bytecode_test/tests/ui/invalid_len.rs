@@ -0,0 +1,10 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+enum Opcode<'a> {
+    Packed(#[bytecode(bits = 4, len = u8)] u8),
+    Borrowed(#[bytecode(len = u16)] &'a [u8]),
+    Missing(#[bytecode(max_len)] Vec<u8>),
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+enum Opcode {
+    #[bytecode(opcode = 0x8000)]
+    Nop,
+    #[bytecode(opcode = 2)]
+    Hlt = 3,
+}
+
+#[derive(Bytecode)]
+enum Following {
+    A = 5,
+    #[bytecode(opcode = 2)]
+    B,
+}
+
+fn main() {}
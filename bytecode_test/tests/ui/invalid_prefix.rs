@@ -0,0 +1,39 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+enum UsedOpcode {
+    Nop,
+    #[bytecode(opcode = 0x0F)]
+    Jmp,
+    #[bytecode(prefix = 0x0F)]
+    Syscall,
+}
+
+#[derive(Bytecode)]
+enum Duplicate {
+    #[bytecode(prefix = 0x0F, opcode = 1)]
+    Syscall,
+    #[bytecode(prefix = 0x0F, opcode = 1)]
+    Sysret,
+    // the same opcode in another group is fine
+    #[bytecode(prefix = 0x0E, opcode = 1)]
+    Cpuid,
+}
+
+#[derive(Bytecode)]
+#[bytecode(tag = "u8", reserve = 0x0F)]
+enum Invalid {
+    #[bytecode(prefix = 0x100)]
+    Large,
+    #[bytecode(prefix = 0x0F)]
+    Reserved,
+}
+
+#[derive(Bytecode)]
+enum UnknownPrefix {
+    Nop,
+    #[bytecode(unknown, prefix = 0x0F)]
+    Unknown(u8),
+}
+
+fn main() {}
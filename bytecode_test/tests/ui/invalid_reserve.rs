@@ -0,0 +1,32 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+#[bytecode(reserve = 0x10..0x20)]
+enum Explicit {
+    Nop,
+    #[bytecode(opcode = 0x18)]
+    Jmp,
+    #[bytecode(alias = [0x10])]
+    Call,
+}
+
+#[derive(Bytecode)]
+#[bytecode(reserve = [0x10..0x10, 0x20..])]
+enum Empty {
+    Nop,
+}
+
+#[derive(Bytecode)]
+#[bytecode(tag = "u8", reserve = 0xF0..0x110)]
+enum TooLarge {
+    Nop,
+}
+
+#[derive(Bytecode)]
+#[bytecode(tag = "u8", reserve = 0x00..=0xFE)]
+enum Filled {
+    Nop,
+    Hlt,
+}
+
+fn main() {}
@@ -0,0 +1,26 @@
+use bytecode::Bytecode;
+use seq_macro::seq;
+
+#[derive(Bytecode)]
+#[bytecode(tag = "u24")]
+enum Unknown {
+    Nop,
+}
+
+#[derive(Bytecode)]
+#[bytecode(tag = "u8")]
+enum TooLarge {
+    Nop,
+    #[bytecode(opcode = 0x100)]
+    Far,
+}
+
+seq!(N in 0..257 {
+    #[derive(Bytecode)]
+    #[bytecode(tag = "u8")]
+    enum Filled {
+        #(T~N,)*
+    }
+});
+
+fn main() {}
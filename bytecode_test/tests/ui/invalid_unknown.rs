@@ -0,0 +1,47 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+enum NoField {
+    Nop,
+    #[bytecode(unknown)]
+    Unknown,
+}
+
+#[derive(Bytecode)]
+enum TwoFields {
+    Nop,
+    #[bytecode(unknown)]
+    Unknown(u8, u8),
+}
+
+#[derive(Bytecode)]
+enum FieldAttr {
+    Nop,
+    #[bytecode(unknown)]
+    Unknown(#[bytecode(varint)] u32),
+}
+
+#[derive(Bytecode)]
+enum WithOpcode {
+    Nop,
+    #[bytecode(unknown, opcode = 5)]
+    Unknown(u8),
+}
+
+#[derive(Bytecode)]
+enum Twice {
+    #[bytecode(unknown)]
+    First(u8),
+    #[bytecode(unknown)]
+    Second(u8),
+}
+
+#[derive(Bytecode)]
+#[bytecode(fixed_size)]
+enum NotFixed {
+    Nop,
+    #[bytecode(unknown)]
+    Unknown(u16),
+}
+
+fn main() {}
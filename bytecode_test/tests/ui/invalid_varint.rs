@@ -0,0 +1,21 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+struct Jump {
+    #[bytecode(varint, bits = 4)]
+    cond: u8,
+    #[bytecode(varint = 1)]
+    count: u8,
+}
+
+#[derive(Bytecode)]
+#[bytecode(fixed_size)]
+struct Call {
+    target: u16,
+    #[bytecode(varint)]
+    offset: i32,
+    #[bytecode(len = u8)]
+    args: Vec<u8>,
+}
+
+fn main() {}
@@ -0,0 +1,68 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+#[bytecode(word = u24)]
+enum InvalidType {
+    Nop,
+}
+
+#[derive(Bytecode)]
+#[bytecode(word = u16, tag = "u8", reserve = 0x10, lock)]
+enum OpcodeOptions {
+    #[bytecode(opcode = 0)]
+    Nop,
+}
+
+#[derive(Bytecode)]
+#[bytecode(word = u16)]
+enum InvalidVariant {
+    Auto,
+    #[bytecode(opcode = 1, alias = 2, prefix = 3)]
+    Alias,
+    #[bytecode(opcode = 0x10000)]
+    Large,
+    #[bytecode(opcode = 0x20, mask = 0x0F)]
+    OutsideMask,
+    #[bytecode(opcode = 0x30, mask = 0xFF)]
+    MaskOverlap(#[bytecode(bits = 0..4)] u8),
+}
+
+#[derive(Bytecode)]
+#[bytecode(word = u8)]
+enum Ambiguous {
+    #[bytecode(opcode = 0x40, mask = 0xF0)]
+    Group,
+    #[bytecode(opcode = 0x41)]
+    Single,
+    #[bytecode(opcode = 0x80)]
+    Field(#[bytecode(bits = 0..=1)] u8),
+    #[bytecode(opcode = 0x82)]
+    Exact,
+}
+
+#[derive(Bytecode)]
+#[bytecode(word = u16)]
+enum InvalidField {
+    #[bytecode(opcode = 0x1000)]
+    Missing(u8),
+    #[bytecode(opcode = 0x2000)]
+    OutsideWord(#[bytecode(bits = 12..=16)] u8),
+    #[bytecode(opcode = 0x3000)]
+    Empty(#[bytecode(bits = 4..4)] u8),
+    #[bytecode(opcode = 0x4000)]
+    Overlap(#[bytecode(bits = 0..=3)] u8, #[bytecode(bits = 3..=5)] u8),
+    #[bytecode(opcode = 0x5000)]
+    SelfOverlap(#[bytecode(bits = [0..=3, 2])] u8),
+    #[bytecode(opcode = 0x6000)]
+    Varint(#[bytecode(bits = 0..=3, varint)] u8),
+    #[bytecode(opcode = 0x7000)]
+    Wide(#[bytecode(bits = 0..=11, shift = 60)] u64),
+}
+
+#[derive(Bytecode)]
+enum MaskWithoutWord {
+    #[bytecode(mask = 0xF0)]
+    Nop,
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+enum Opcode {
+    #[bytecode(opcode)]
+    Nop,
+    #[bytecode(opcode = "1")]
+    Hlt,
+    #[bytecode(opcode = 1 +)]
+    Add(u8),
+}
+
+#[derive(Bytecode)]
+#[bytecode(bound = "T Copy")]
+struct Operand<T>(T);
+
+fn main() {}
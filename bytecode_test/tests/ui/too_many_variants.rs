@@ -0,0 +1,27 @@
+use bytecode::Bytecode;
+use seq_macro::seq;
+
+seq!(N in 0..32769 {
+    #[derive(Bytecode)]
+    enum Opcode {
+        #(T~N,)*
+    }
+});
+
+// only the variants of the prefix group are counted, without the unknown variant
+seq!(N in 0..300 {
+    #[derive(Bytecode)]
+    #[bytecode(tag = "u8")]
+    enum Prefixed {
+        A,
+        B,
+        #(
+            #[bytecode(prefix = 0x0F)]
+            T~N,
+        )*
+        #[bytecode(unknown)]
+        Unknown(u8),
+    }
+});
+
+fn main() {}
@@ -0,0 +1,9 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+union Value {
+    x: u8,
+    y: u16,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+struct Empty;
+
+fn main() {}
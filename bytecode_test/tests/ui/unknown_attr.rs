@@ -0,0 +1,15 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+#[bytecode(locked)]
+enum Opcode {
+    #[bytecode(opcod = 1)]
+    Nop,
+    Add(#[bytecode(bit = 4)] u8),
+}
+
+#[derive(Bytecode)]
+#[bytecode(bounds = "T: Copy")]
+struct Operand<T>(T);
+
+fn main() {}
@@ -0,0 +1,11 @@
+use bytecode::Bytecode;
+
+#[derive(Bytecode)]
+enum Opcode<'a> {
+    Call(fn()),
+    Load { ptr: *const u8 },
+    Dyn(Box<dyn Fn()>, &'static dyn Fn()),
+    Store(&'a mut [u8]),
+}
+
+fn main() {}
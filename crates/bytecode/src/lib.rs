@@ -1,3 +1,5 @@
+#![no_std]
+
 pub use bytecode_derive;
 pub use bytecode_derive::*;
 pub use bytecode_trait;
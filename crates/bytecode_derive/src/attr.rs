@@ -0,0 +1,191 @@
+use crate::errors::Errors;
+use proc_macro2::Span;
+use std::ops::RangeInclusive;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Ident, Token};
+
+// A single argument of the #[bytecode(...)] attribute,
+// this is kept generic so that it can hold all the argument forms we support :
+// `flag`, `key = value` and `key(arg, ...)`
+pub struct BytecodeArg {
+    pub name: Ident,
+    pub value: ArgValue,
+}
+
+pub enum ArgValue {
+    Flag,
+    Value(Box<syn::Expr>),
+    // nested arguments, such as `embed(field = 0, bits = 3)`
+    List(Vec<BytecodeArg>),
+}
+
+impl Parse for BytecodeArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        // parse_any, so that keyword-like names can also be used as arguments
+        let name = Ident::parse_any(input)?;
+        let value = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            ArgValue::Value(Box::new(input.parse()?))
+        } else if input.peek(syn::token::Paren) {
+            let content;
+            syn::parenthesized!(content in input);
+            ArgValue::List(parse_arg_list(&content)?)
+        } else {
+            ArgValue::Flag
+        };
+        Ok(BytecodeArg { name, value })
+    }
+}
+
+fn parse_arg_list(input: ParseStream) -> syn::Result<Vec<BytecodeArg>> {
+    let args = Punctuated::<BytecodeArg, Token![,]>::parse_terminated(input)?;
+    Ok(args.into_iter().collect())
+}
+
+impl BytecodeArg {
+    // this is the name of the argument as string, used for matching on known arguments
+    pub fn key(&self) -> String {
+        self.name.unraw().to_string()
+    }
+
+    pub fn span(&self) -> Span {
+        self.name.span()
+    }
+
+    // returns the value of `key = value` form, and errors for other forms
+    pub fn expr(&self) -> syn::Result<&syn::Expr> {
+        match &self.value {
+            ArgValue::Value(v) => Ok(v),
+            _ => Err(syn::Error::new(
+                self.span(),
+                format!("expected `{} = ...`", self.key()),
+            )),
+        }
+    }
+
+    // returns the nested arguments of `key(...)` form, and errors for other forms
+    pub fn list(&self) -> syn::Result<&[BytecodeArg]> {
+        match &self.value {
+            ArgValue::List(args) => Ok(args),
+            _ => Err(syn::Error::new(
+                self.span(),
+                format!("expected `{}(...)`", self.key()),
+            )),
+        }
+    }
+
+    // checks that the argument is given as a plain `flag`
+    pub fn expect_flag(&self) -> syn::Result<()> {
+        match &self.value {
+            ArgValue::Flag => Ok(()),
+            ArgValue::Value(v) => Err(syn::Error::new(
+                v.span(),
+                format!("`{}` does not take a value", self.key()),
+            )),
+            ArgValue::List(_) => Err(syn::Error::new(
+                self.span(),
+                format!("`{}` does not take any arguments", self.key()),
+            )),
+        }
+    }
+
+    pub fn unknown(&self) -> syn::Error {
+        syn::Error::new(
+            self.span(),
+            format!("unknown bytecode attribute `{}`", self.key()),
+        )
+    }
+}
+
+// collects the arguments from all #[bytecode(...)] attributes in the given list
+pub fn parse_attrs(attrs: &[syn::Attribute]) -> syn::Result<Vec<BytecodeArg>> {
+    let mut ret = Vec::new();
+    let mut errors = Errors::default();
+    for attr in attrs {
+        if !attr.path.is_ident("bytecode") {
+            continue;
+        }
+        if let Some(args) = errors.check(attr.parse_args_with(parse_arg_list)) {
+            ret.extend(args);
+        }
+    }
+    errors.finish()?;
+    Ok(ret)
+}
+
+// parses an integer literal expression, such as opcode values
+pub fn expr_to_usize(expr: &syn::Expr) -> syn::Result<usize> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(i),
+            ..
+        }) => i.base10_parse::<usize>(),
+        syn::Expr::Group(g) => expr_to_usize(&g.expr),
+        syn::Expr::Paren(p) => expr_to_usize(&p.expr),
+        _ => Err(syn::Error::new(
+            expr.span(),
+            "expected a non-negative integer literal",
+        )),
+    }
+}
+
+// Parses a range of integers, a single integer, or a list of these, such as `[0x10..0x20, 0xFF]`.
+// Each range is returned along with the expression it is given by, for the errors about it.
+// `empty` is the error for a range which does not contain any values
+pub fn expr_to_ranges<'a>(
+    expr: &'a syn::Expr,
+    empty: &str,
+) -> syn::Result<Vec<(RangeInclusive<usize>, &'a syn::Expr)>> {
+    let elems: Vec<_> = match expr {
+        syn::Expr::Array(a) => a.elems.iter().collect(),
+        _ => vec![expr],
+    };
+    let mut ret = Vec::with_capacity(elems.len());
+    for e in elems {
+        let range = match e {
+            syn::Expr::Range(syn::ExprRange {
+                from: Some(from),
+                limits,
+                to: Some(to),
+                ..
+            }) => {
+                let (start, end) = (expr_to_usize(from)?, expr_to_usize(to)?);
+                // the end of a half open range is one past the last value
+                let end = match limits {
+                    syn::RangeLimits::Closed(_) => Some(end),
+                    syn::RangeLimits::HalfOpen(_) => end.checked_sub(1),
+                };
+                match end {
+                    Some(end) if end >= start => start..=end,
+                    _ => return Err(syn::Error::new(e.span(), empty)),
+                }
+            }
+            syn::Expr::Range(_) => {
+                return Err(syn::Error::new(
+                    e.span(),
+                    "expected a range with both the start and the end",
+                ))
+            }
+            _ => {
+                let v = expr_to_usize(e)?;
+                v..=v
+            }
+        };
+        ret.push((range, e));
+    }
+    Ok(ret)
+}
+
+// parses a string literal expression, such as keys and paths
+pub fn expr_to_string(expr: &syn::Expr) -> syn::Result<String> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(s),
+            ..
+        }) => Ok(s.value()),
+        _ => Err(syn::Error::new(expr.span(), "expected a string literal")),
+    }
+}
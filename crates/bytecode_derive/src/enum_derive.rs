@@ -1,323 +1,1186 @@
+use crate::attr;
+use crate::errors::Errors;
+use crate::fields;
+use crate::generics;
+use crate::impls;
+use crate::lockfile::{LockSection, Lockfile, UPDATE_VAR};
+use crate::opcode;
 use crate::util;
-use proc_macro::TokenStream;
+use crate::word;
 use proc_macro2::Ident;
-use quote::quote;
+use quote::{quote, ToTokens};
 use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
 
-// we use at max 2 bytes for instructions, and thus 1 bit is reserved to show length of instruction
-// we might need to add more constants in future to indicate this, rather than hardcoding directly
-const MAX_VARIANTS_ALLOWED: usize = 1 << 15;
-
-pub fn derive_enum(name: &syn::Ident, input_enum: &syn::DataEnum) -> TokenStream {
-    // maximum instructions possible for this enum
-    // maybe change this name a bit
-    let max_possible_instructions = input_enum.variants.len();
-
-    // make sure possible instructions are less than allowed
-    if max_possible_instructions > MAX_VARIANTS_ALLOWED {
-        panic!(
-            "Currently at max {} enum variants are supported, found {}",
-            MAX_VARIANTS_ALLOWED,
-            input_enum.variants.len()
+// options which can be given on the enum itself using #[bytecode(...)]
+#[derive(Default)]
+struct EnumAttrs {
+    // key of the lockfile section for this enum, if the lockfile is used
+    lock: Option<String>,
+    // bounds given explicitly for the generic parameters
+    bound: Option<Vec<syn::WherePredicate>>,
+    // whether to implement FixedSize for the enum
+    fixed_size: bool,
+    // byte order of the fields which do not give their own, the opcode is
+    // only affected by this if it is of fixed size
+    endian: Option<fields::ByteOrder>,
+    // how the opcodes are stored
+    tag: util::Tag,
+    // opcodes which are never assigned to any variant, nor parsed
+    reserved: Vec<RangeInclusive<usize>>,
+    // whether to generate a constructor for each variant
+    assembler: bool,
+    // number of bytes in the word, if each instruction is a single word
+    word: Option<usize>,
+}
+
+fn parse_enum_attrs(name: &syn::Ident, attrs: &[syn::Attribute]) -> syn::Result<EnumAttrs> {
+    let mut ret = EnumAttrs::default();
+    let mut errors = Errors::default();
+    let mut reserve_args = Vec::new();
+    let args = attr::parse_attrs(attrs)?;
+    for arg in args.iter() {
+        match arg.key().as_str() {
+            "lock" => {
+                // by default the section is keyed by the enum name, but in case
+                // there are multiple enums with same name in the crate, a different
+                // key can be given as #[bytecode(lock = "key")]
+                ret.lock = match &arg.value {
+                    attr::ArgValue::Flag => Some(name.to_string()),
+                    _ => errors.check(arg.expr().and_then(attr::expr_to_string)),
+                };
+            }
+            "bound" => ret.bound = errors.check(generics::parse_bound(arg)),
+            "fixed_size" => ret.fixed_size = errors.check(arg.expect_flag()).is_some(),
+            "endian" => ret.endian = errors.check(fields::parse_endian(arg)),
+            "tag" => ret.tag = errors.check(util::parse_tag(arg)).unwrap_or_default(),
+            "reserve" => {
+                if let Some(expr) = errors.check(arg.expr()) {
+                    reserve_args.push(expr);
+                }
+            }
+            "assembler" => ret.assembler = errors.check(arg.expect_flag()).is_some(),
+            "word" => ret.word = errors.check(word::parse_word(arg)),
+            _ => errors.push(arg.unknown()),
+        }
+    }
+    // the opcodes of word enums are given as bit patterns, so they are not numbered
+    if ret.word.is_some() {
+        for arg in args.iter() {
+            if matches!(arg.key().as_str(), "lock" | "tag" | "reserve") {
+                errors.push(syn::Error::new(
+                    arg.span(),
+                    format!("`{}` cannot be used with `word`", arg.key()),
+                ));
+            }
+        }
+    }
+    // the ranges are checked once the tag is known, which can be given after them
+    for expr in reserve_args {
+        let ranges = match errors.check(opcode::parse_reserve(expr)) {
+            Some(ranges) => ranges,
+            None => continue,
+        };
+        if ranges.iter().any(|r| *r.end() > ret.tag.max_opcode()) {
+            errors.push(syn::Error::new_spanned(
+                expr,
+                format!(
+                    "reserved opcodes are too large, at max {:#x} is supported",
+                    ret.tag.max_opcode()
+                ),
+            ));
+        }
+        ret.reserved.extend(ranges);
+    }
+    errors.finish()?;
+    if let util::Tag::Fixed(_, order @ None) = &mut ret.tag {
+        *order = ret.endian;
+    }
+    Ok(ret)
+}
+
+// a variant along with its opcode and its fields,
+// which is everything needed to generate code for it
+struct VariantCode<'a> {
+    variant: &'a syn::Variant,
+    opcode: usize,
+    aliases: Vec<usize>,
+    prefix: Option<usize>,
+    embed: Option<opcode::Embed>,
+    fields: Vec<fields::FieldCode<'a>>,
+}
+
+impl VariantCode<'_> {
+    // all the opcodes which are parsed as this variant, the ones it is compiled to first
+    fn opcodes(&self) -> impl Iterator<Item = usize> + '_ {
+        (self.opcode..=self.last_opcode()).chain(self.aliases.iter().copied())
+    }
+
+    // the largest opcode the variant is compiled to, which differs from
+    // the opcode only if a field is embedded in it
+    fn last_opcode(&self) -> usize {
+        self.opcode | self.embed.map_or(0, |e| e.mask())
+    }
+
+    // the patterns which match the opcodes of the variant
+    fn opcode_patterns(&self) -> Vec<proc_macro2::TokenStream> {
+        let (first, last) = (self.opcode as u32, self.last_opcode() as u32);
+        let range = match self.embed {
+            Some(_) => quote! { #first..=#last },
+            None => quote! { #first },
+        };
+        let aliases = self.aliases.iter().map(|&op| {
+            let op = op as u32;
+            quote! { #op }
+        });
+        core::iter::once(range).chain(aliases).collect()
+    }
+
+    // number of bytes the prefix takes, if the variant has one
+    fn prefix_len(&self, tag: util::Tag) -> usize {
+        self.prefix.map_or(0, |p| tag.instr_len(p))
+    }
+
+    // the opcode as shown in the docs, along with the prefix
+    fn opcode_text(&self) -> String {
+        let opcode = match self.embed {
+            Some(_) => format!("{:#x}..={:#x}", self.opcode, self.last_opcode()),
+            None => format!("{:#x}", self.opcode),
+        };
+        match self.prefix {
+            Some(p) => format!("{:#x} {}", p, opcode),
+            None => opcode,
+        }
+    }
+}
+
+// the variant with #[bytecode(unknown)], which holds the opcodes that are not
+// assigned to any other variant, in its only field
+struct UnknownCode<'a> {
+    variant: &'a syn::Variant,
+    fields: Vec<fields::FieldCode<'a>>,
+}
+
+pub fn derive_enum(
+    name: &syn::Ident,
+    vis: &syn::Visibility,
+    attrs: &[syn::Attribute],
+    generics: &syn::Generics,
+    input_enum: &syn::DataEnum,
+) -> syn::Result<proc_macro2::TokenStream> {
+    // there is no value to compile, and nothing a tag could be parsed into
+    if input_enum.variants.is_empty() {
+        return Err(syn::Error::new(
+            name.span(),
+            "Bytecode is not supported for enums without variants",
+        ));
+    }
+    let mut errors = Errors::default();
+    let enum_attrs = errors.check(parse_enum_attrs(name, attrs));
+    if let Some(EnumAttrs {
+        word: Some(bytes),
+        endian,
+        bound,
+        assembler,
+        ..
+    }) = &enum_attrs
+    {
+        let layout = word::WordLayout {
+            bytes: *bytes,
+            order: endian.unwrap_or(fields::ByteOrder::Little),
+        };
+        return word::derive_word_enum(
+            name,
+            vis,
+            generics,
+            input_enum,
+            layout,
+            bound.as_deref(),
+            *assembler,
         );
     }
+    let tag = enum_attrs.as_ref().map(|a| a.tag).unwrap_or_default();
+
+    // make sure possible instructions are less than allowed by the tag
+    // no point in checking anything else in that case. Each prefix group
+    // has opcodes of its own, so they are counted separately, and the unknown
+    // variant does not take an opcode
+    let max_variants = tag.max_opcode().saturating_add(1);
+    // the number of variants in each group, with the first one over the limit
+    let mut group_sizes: BTreeMap<Option<usize>, (usize, Option<&syn::Variant>)> = BTreeMap::new();
+    for v in input_enum.variants.iter() {
+        let attrs = opcode::parse_variant_attrs(v).ok();
+        if matches!(&attrs, Some(a) if a.unknown.is_some()) {
+            continue;
+        }
+        let prefix = attrs.and_then(|a| a.prefix).map(|(p, _)| p);
+        let (count, over) = group_sizes.entry(prefix).or_default();
+        *count += 1;
+        if *count > max_variants {
+            over.get_or_insert(v);
+        }
+    }
+    for (prefix, (count, over)) in group_sizes {
+        if let Some(v) = over {
+            let group = match prefix {
+                Some(p) => format!(" with the prefix {:#x}", p),
+                None => String::new(),
+            };
+            return Err(syn::Error::new(
+                v.ident.span(),
+                format!(
+                    "Currently at max {} enum variants{} are supported, found {}",
+                    max_variants, group, count
+                ),
+            ));
+        }
+    }
+
+    let mut variant_fields: Vec<_> = input_enum
+        .variants
+        .iter()
+        .map(|v| errors.check(variant_fields(v)))
+        .collect();
+    let unknown = errors.check(unknown_variant(input_enum));
+    // the opcode table, which maps each variant to the value it is compiled to
+    // the lockfile is only used if everything else is correct, so it is not updated
+    // with a partial assignment
+    let opcodes = match &enum_attrs {
+        Some(enum_attrs) if !errors.has_errors() => {
+            errors.check(assign_opcodes(name, enum_attrs, input_enum))
+        }
+        _ => {
+            let enum_attrs = EnumAttrs {
+                tag,
+                ..Default::default()
+            };
+            errors.check(assign_opcodes(name, &enum_attrs, input_enum))
+        }
+    };
+    errors.finish()?;
+    let enum_attrs = enum_attrs.unwrap();
+    let (opcodes, lock_dependency) = opcodes.unwrap();
+
+    let mut variants: Vec<_> = opcodes
+        .into_iter()
+        .map(|v| {
+            let mut fields = variant_fields[v.index].take().unwrap();
+            // the embedded field is bounded the same way as the bit-packed fields
+            if let Some(e) = v.embed {
+                fields[e.field].embedded = true;
+                fields[e.field].attrs.bits = Some(e.bits);
+            }
+            fields::apply_endian(&mut fields, enum_attrs.endian);
+            VariantCode {
+                variant: v.variant,
+                opcode: v.opcode,
+                aliases: v.aliases,
+                prefix: v.prefix,
+                embed: v.embed,
+                fields,
+            }
+        })
+        .collect();
+    // sorting is not needed for correctness, but this way the generated
+    // match arms are in numerical order, so the output is easier to debug
+    variants.sort_by_key(|v| (v.prefix, v.opcode));
+    let unknown = unknown.unwrap().map(|i| UnknownCode {
+        variant: &input_enum.variants[i],
+        fields: variant_fields[i].take().unwrap(),
+    });
+    // the variants with a prefix are parsed in a nested match for each prefix
+    let mut groups: BTreeMap<usize, Vec<&VariantCode>> = BTreeMap::new();
+    for v in variants.iter() {
+        if let Some(p) = v.prefix {
+            groups.entry(p).or_default().push(v);
+        }
+    }
+    // the opcodes in the first byte, which are the ones of the variants without a prefix,
+    // and the prefixes. The unknown variant can hold any opcode the tag can store,
+    // so all of them are parsed
+    let main_opcodes: Vec<usize> = variants
+        .iter()
+        .filter(|v| v.prefix.is_none())
+        .flat_map(VariantCode::opcodes)
+        .chain(groups.keys().copied())
+        .collect();
+    let max_opcode = match &unknown {
+        Some(_) => tag.max_opcode(),
+        None => main_opcodes.iter().copied().max().unwrap_or(0),
+    };
+
+    let generics_ref = generics;
+    let generics = generics::bounded_generics(
+        generics,
+        variants.iter().flat_map(|v| v.fields.iter()),
+        enum_attrs.bound.as_deref(),
+    );
 
     // this maps the variants into the code for the compile method
     // for each individual enum variant
     // we later iter over this to fill the body of compile method impl
-    let compiled = input_enum
-        .variants
+    let compile_fn_param_name = Ident::new("__out", name.span());
+    let compiled = variants
         .iter()
-        .enumerate()
-        .map(|(i, v)| compile_enum_variant(name, i, v));
+        .map(|v| compile_enum_variant(name, tag, &compile_fn_param_name, v))
+        .chain(unknown.iter().map(|u| {
+            compile_unknown(
+                name,
+                tag,
+                &compile_fn_param_name,
+                u,
+                &main_opcodes,
+                &enum_attrs.reserved,
+            )
+        }));
 
     // we extract this here, as multiple places need this name
     let parse_fn_param_name = Ident::new("__bytes", name.span());
+    let opcode_var = Ident::new("__opcode", name.span());
 
-    // this generates code for initial check for validity of the byte stream
-    // in the parse method impl.
-    // If this check fails, we can quickly exit the compile method
-    // with appropriate error
-    // <=, as max_possible_instruction is total number of enum variants, which start from 1
-    let parse_check_logic = if max_possible_instructions <= 1 << 7 {
-        // If the number of enum variants are less than 1<<7, all bytecodes generated
-        // will be 1 byte length, so we have to only check the first byte
-        let _max = max_possible_instructions as u8;
-        // >= max, as counting starts at 0
+    // this generates code for extracting the opcode value from the byte stream
+    // in the parse method impl. In case the bytes cannot be a valid opcode
+    // we can quickly exit the parse method with appropriate error
+    let type_path = name.to_string();
+    let parse_opcode_logic =
+        tag.parse_instr(&parse_fn_param_name, &opcode_var, max_opcode, &type_path);
+
+    // for reading from a reader, the opcode bytes are read first,
+    // and then the opcode is extracted from them, same as in parse method
+    let reader_name = Ident::new("__reader", name.span());
+    let opcode_bytes = Ident::new("__opcode_bytes", name.span());
+    let read_opcode_logic = tag.read_instr(&reader_name, &opcode_bytes, max_opcode, &type_path);
+    let extract_opcode_logic = tag.parse_instr(&opcode_bytes, &opcode_var, max_opcode, &type_path);
+    let main_variants: Vec<_> = variants.iter().filter(|v| v.prefix.is_none()).collect();
+    let read_logic = read_arms(name, &reader_name, &opcode_var, &main_variants)
+        .into_iter()
+        .chain(groups.iter().map(|(&prefix, group)| {
+            read_prefix_group(name, tag, prefix, group, &reader_name, &opcode_var)
+        }));
+
+    // then we match on the extracted opcode value using the opcode table,
+    // any value not in the table is an invalid instruction
+    let parse_logic = parse_arms(name, &parse_fn_param_name, &opcode_var, &main_variants)
+        .into_iter()
+        .chain(groups.iter().map(|(&prefix, group)| {
+            parse_prefix_group(name, tag, prefix, group, &parse_fn_param_name, &opcode_var)
+        }));
+
+    // the sizes of each variant, which are combined to get the sizes of the enum.
+    // The aliases can take a different number of bytes than the primary opcode
+    let min_sizes = variants.iter().map(|v| {
+        let init_size = v.prefix_len(tag) + v.opcodes().map(|op| tag.instr_len(op)).min().unwrap();
+        let fields_size = fields::min_size(&v.fields);
+        quote! { #init_size + #fields_size }
+    });
+    let max_sizes = variants.iter().map(|v| {
+        let init_size = v.prefix_len(tag) + v.opcodes().map(|op| tag.instr_len(op)).max().unwrap();
+        let fields_size = fields::max_size(&v.fields);
         quote! {
-            if #parse_fn_param_name[0] >= #_max{
-                return std::result::Result::Err(bytecode::BytecodeError::InvalidInstruction);
-            }
+            bytecode::size::max_size_sum(&[core::option::Option::Some(#init_size), #fields_size])
         }
-    } else {
-        // If the total variants are greater that 1<<7. we need to check first as well as second byte
-        // If the 0th byte of bytestream is less than 1<<7, then it is definitely valid bytecode,
-        // as 2-byte bytecode only begin after 1<<7 bytecode
-        // if the 0th byte is greater than 1<<7, then there must be at least two bytes
-        // and the u16 formed by both of them must be less than max possible instructions
-        let _max = max_possible_instructions as u16;
-        quote! {
-            if #parse_fn_param_name[0] >= 1<< 7 && #parse_fn_param_name.len() < 2{
-                return std::result::Result::Err(bytecode::BytecodeError::IncompleteInstruction);
+    });
+    let encoded_lens = variants.iter().map(|v| {
+        let prefix_len = v.prefix_len(tag);
+        let pattern = variant_pattern(name, v.variant, &v.fields);
+        let fields_len = fields::encoded_len(&v.fields);
+        // the length of an opcode with an embedded field can depend on the value of the field
+        let op = Ident::new("__op", name.span());
+        let init_size = match embedded_opcode(v, &op, quote! { .unwrap_or_default() }) {
+            Some(opcode) if tag.instr_len(v.opcode) != tag.instr_len(v.last_opcode()) => {
+                let len = tag.dynamic_len(&op);
+                quote! { #prefix_len + { #opcode #len } }
             }
-            if #parse_fn_param_name[0] >= 1<< 7{
-                let higher_byte:u8 = #parse_fn_param_name[0] & (1<<7 -1);
-                let lower_byte:u8 = #parse_fn_param_name[1];
-                let instr:u16 = (higher_byte as u16) << 8 | lower_byte as u16;
-                if instr > #_max {
-                    return std::result::Result::Err(bytecode::BytecodeError::InvalidInstruction);
-                }
+            _ => {
+                let init_size = prefix_len + tag.instr_len(v.opcode);
+                quote! { #init_size }
             }
+        };
+        quote! {
+            #pattern => #init_size + #fields_len
         }
-    };
-
-    // this handles only single byte length instruction
-    // parsing logic, the two-byte instruction parsing logic
-    // is in separate variable
-    let mut single_byte_parse_logic = Vec::new();
-    for i in 0..max_possible_instructions {
-        if i < 1 << 7 {
-            let variant = &input_enum.variants[i];
-            let i = i as u8;
-            let parsed = parse_single_byte(name, &parse_fn_param_name, variant);
-            // add conditional parse logic depending on unit type or unnamed type
-            single_byte_parse_logic.push(quote! {
-                #i => {
-                    #parsed
+    });
+    let (min_sizes, max_sizes, encoded_lens): (Vec<_>, Vec<_>, Vec<_>) = (
+        min_sizes.collect(),
+        max_sizes.collect(),
+        encoded_lens.collect(),
+    );
+    let (min_sizes, max_sizes, encoded_lens) = match &unknown {
+        Some(u) => {
+            let (min_len, max_len) = tag.len_range();
+            let pattern = variant_pattern(name, u.variant, &u.fields);
+            let var = &u.fields[0].var;
+            let op = Ident::new("__op", name.span());
+            let len = tag.dynamic_len(&op);
+            let encoded_len = quote! {
+                #pattern => {
+                    let #op: u32 = <u32 as core::convert::TryFrom<_>>::try_from(*#var).unwrap_or_default();
+                    #len
                 }
-            });
-        } else {
-            // we're beyond the single byte length as per encoding scheme
-            // so we can stop the loop
-            break;
+            };
+            (
+                [min_sizes, vec![quote! { #min_len }]].concat(),
+                [
+                    max_sizes,
+                    vec![quote! { core::option::Option::Some(#max_len) }],
+                ]
+                .concat(),
+                [encoded_lens, vec![encoded_len]].concat(),
+            )
         }
-    }
-    let single_byte_parse_logic = single_byte_parse_logic.iter();
-
-    let mut two_byte_parse_logic = Vec::new();
-    // if number of instructions i s less than 1<<7 then this should be empty
-    if max_possible_instructions > 1 << 7 {
-        // we can use std::hashmap here, but then the order of output is dependent on
-        // how the higher byte is hashed, BTreeMap allows the inter to be in order of numerical
-        // values, so the output is ordered, and easier to debug
-        let mut hm: BTreeMap<u8, Vec<_>> = BTreeMap::new();
-
-        // fist we split the two-byte instructions into two bytes
-        // as in the byte array the higher bytes will be first, we must
-        // first match on it, and then match on the next byte
-        // so here we map the pair of lower byte and corresponding enum variant to
-        // corresponding higher byte
-        for i in 1 << 7..max_possible_instructions {
-            let [higher_byte, lower_byte] = util::split_into_instr_bytes(i);
-            let entry = hm.entry(higher_byte).or_default();
-            entry.push((lower_byte, &input_enum.variants[i]));
+        None => (min_sizes, max_sizes, encoded_lens),
+    };
+
+    let fixed_size = if enum_attrs.fixed_size {
+        fields::check_fixed_size(name, variants.iter().flat_map(|v| v.fields.iter()))?;
+        if let (Some(u), (min, max)) = (&unknown, tag.len_range()) {
+            if min != max {
+                return Err(syn::Error::new(
+                    u.variant.ident.span(),
+                    "fixed_size enums with an unknown variant need a tag of fixed size",
+                ));
+            }
         }
-        // then here we get the code for parsing the individual variant, and
-        // then generate the code for parsing the complete two bytes
-        for (higher_byte, list) in hm.into_iter() {
-            let lower_byte_matches = list.into_iter().map(|(lower_byte, var)| {
-                parse_two_byte(name, &parse_fn_param_name, lower_byte, var)
-            });
-            two_byte_parse_logic.push(quote! {
-                #higher_byte => {
-                    match #parse_fn_param_name[1] {
-                        #(#lower_byte_matches),*
-                        _ => unreachable!()
-                    }
+        derive_fixed_size(
+            name,
+            generics_ref,
+            &enum_attrs,
+            &variants,
+            unknown.is_some(),
+        )
+    } else {
+        quote! {}
+    };
+
+    // the opcodes of variants with a prefix are not unique by themselves
+    let bit_packable = if input_enum
+        .variants
+        .iter()
+        .all(|v| matches!(v.fields, syn::Fields::Unit))
+        && groups.is_empty()
+    {
+        derive_bit_packable(name, &generics, &variants)
+    } else {
+        quote! {}
+    };
+
+    let assembler = if enum_attrs.assembler {
+        derive_assembler(name, vis, generics_ref, input_enum, |v| {
+            let code = variants.iter().find(|code| code.variant.ident == v.ident)?;
+            Some(code.opcode_text())
+        })?
+    } else {
+        quote! {}
+    };
+
+    // opcodes which are not assigned to any variant are invalid, unless there is an unknown variant
+    let parse_fallback = parse_unknown(
+        name,
+        unknown.as_ref(),
+        &enum_attrs.reserved,
+        &opcode_var,
+        quote! { &#parse_fn_param_name[..__opcode_len] },
+        |pattern| quote! { (#pattern, __opcode_len) },
+    );
+    let read_fallback = parse_unknown(
+        name,
+        unknown.as_ref(),
+        &enum_attrs.reserved,
+        &opcode_var,
+        opcode_bytes.to_token_stream(),
+        |pattern| pattern,
+    );
+
+    let parts = impls::ImplParts {
+        encode: quote! {
+            const MIN_SIZE: usize = bytecode::size::min_size_of(&[#(#min_sizes),*]);
+            const MAX_SIZE: core::option::Option<usize> = bytecode::size::max_size_of(&[#(#max_sizes),*]);
+
+            // the variables of fields which are bit-packed are not needed for the length
+            #[allow(unused_variables)]
+            fn encoded_len(&self) -> usize {
+                match self {
+                    #(#encoded_lens),*
                 }
-            });
-        }
-    }
-    let two_byte_parse_logic = two_byte_parse_logic.iter();
+            }
 
-    let output = quote! {
-        impl bytecode::Bytecodable for #name{
-            fn compile(&self)->Vec<u8>{
+            fn compile_into<__S: bytecode::BytecodeSink + ?Sized>(&self, #compile_fn_param_name: &mut __S)->core::result::Result<(),bytecode::BytecodeError>{
                 match self {
                     #(#compiled ),*
                 }
+                core::result::Result::Ok(())
             }
+        },
+        parse_body: quote! {
+            #parse_opcode_logic
 
-            fn parse(#parse_fn_param_name:&[u8])->std::result::Result<(#name,usize),bytecode::BytecodeError>{
-                if #parse_fn_param_name.len() < 1 {
-                    return std::result::Result::Err(bytecode::BytecodeError::IncompleteInstruction);
-                }
-                #parse_check_logic
+            match #opcode_var{
+                #(#parse_logic ),*
+                _ => #parse_fallback
+            }
+        },
+        parse_param: parse_fn_param_name,
+        read: quote! {
+            // reading needs std::io, so this is only implemented when it is available
+            bytecode::__std_only! {
+                fn read_from<__R: bytecode::io::Read + ?Sized>(#reader_name: &mut __R)->core::result::Result<Self,bytecode::BytecodeError>{
+                    #read_opcode_logic
+                    #extract_opcode_logic
 
-                match #parse_fn_param_name[0]{
-                    #(#single_byte_parse_logic ),*
-                    #(#two_byte_parse_logic),*
-                    _ => {unreachable!();}
+                    match #opcode_var{
+                        #(#read_logic ),*
+                        _ => #read_fallback
+                    }
                 }
             }
+        },
+    };
+    let impls = impls::codec_impls(
+        name,
+        &generics,
+        variants.iter().flat_map(|v| v.fields.iter()),
+        parts,
+    );
+
+    Ok(quote! {
+        #lock_dependency
+
+        #impls
+
+        #fixed_size
+
+        #bit_packable
+
+        #assembler
+    })
+}
+
+// Generates a constructor for each variant, named after the variant in snake case,
+// so that programs can be written as `Opcode::push(1)`. The constructors of the
+// deprecated variants are marked #[deprecated], so that using them gives a warning.
+// `opcode_text` gives the opcode of a variant as shown in the docs
+pub fn derive_assembler(
+    name: &syn::Ident,
+    vis: &syn::Visibility,
+    generics: &syn::Generics,
+    input_enum: &syn::DataEnum,
+    opcode_text: impl Fn(&syn::Variant) -> Option<String>,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut helpers = Vec::with_capacity(input_enum.variants.len());
+    for v in input_enum.variants.iter() {
+        let attrs = opcode::parse_variant_attrs(v)?;
+        let ident = &v.ident;
+        let opcode = opcode_text(v);
+        let doc = match &opcode {
+            Some(op) => format!(
+                "Assembles [`{}::{}`], which is compiled to opcode `{}`",
+                name, ident, op
+            ),
+            None => format!("Assembles [`{}::{}`]", name, ident),
+        };
+        let deprecated = attrs.deprecated.map(|note| {
+            let note = note.unwrap_or_else(|| match &opcode {
+                Some(op) => format!("opcode {} is deprecated", op),
+                None => format!("`{}::{}` is deprecated", name, ident),
+            });
+            quote! { #[deprecated(note = #note)] }
+        });
+        let params = v.fields.iter().enumerate().map(|(i, f)| {
+            let ty = &f.ty;
+            let var = match &f.ident {
+                Some(ident) => ident.clone(),
+                None => Ident::new(&format!("v{}", i), ident.span()),
+            };
+            (var, ty)
+        });
+        let (vars, types): (Vec<_>, Vec<_>) = params.unzip();
+        let value = match &v.fields {
+            syn::Fields::Named(_) => quote! { #name::#ident { #(#vars),* } },
+            syn::Fields::Unnamed(_) => quote! { #name::#ident ( #(#vars),* ) },
+            syn::Fields::Unit => quote! { #name::#ident },
+        };
+        let fn_name = util::snake_case(ident);
+        helpers.push(quote! {
+            #[doc = #doc]
+            #deprecated
+            #[inline]
+            #vis const fn #fn_name(#(#vars: #types),*) -> Self {
+                #value
+            }
+        });
+    }
+    Ok(quote! {
+        // a program does not need to use every instruction
+        #[allow(dead_code, clippy::too_many_arguments)]
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#helpers)*
         }
+    })
+}
+
+// all variants must have the same size for the enum to be of fixed size,
+// this is checked when the SIZE const is evaluated
+fn derive_fixed_size(
+    name: &syn::Ident,
+    generics: &syn::Generics,
+    enum_attrs: &EnumAttrs,
+    variants: &[VariantCode],
+    unknown: bool,
+) -> proc_macro2::TokenStream {
+    let bounded = generics::fixed_size_generics(
+        generics,
+        variants.iter().flat_map(|v| v.fields.iter()),
+        enum_attrs.bound.as_deref(),
+    );
+    let (impl_generics, ty_generics, where_clause) = bounded.split_for_impl();
+    // each of the aliases must give the same size as well
+    let sizes = variants.iter().flat_map(|v| {
+        let fields_size = fields::fixed_size(&v.fields);
+        let prefix_len = v.prefix_len(enum_attrs.tag);
+        v.opcodes().map(move |op| {
+            let init_size = prefix_len + enum_attrs.tag.instr_len(op);
+            quote! { #init_size + #fields_size }
+        })
+    });
+    // the unknown variant only has the opcode, which is of fixed size here
+    let unknown_size = unknown.then(|| enum_attrs.tag.len_range().0);
+    let sizes = sizes.chain(unknown_size.map(|size| quote! { #size }));
+    // consts of generic impls are only evaluated when used, so the check
+    // can be forced at the definition only for types without generics
+    let check = if generics.params.is_empty() {
+        quote! {
+            const _: usize = <#name as bytecode::FixedSize>::SIZE;
+        }
+    } else {
+        quote! {}
     };
-    output.into()
+    quote! {
+        impl #impl_generics bytecode::FixedSize for #name #ty_generics #where_clause{
+            const SIZE: usize = bytecode::size::same_size(&[#(#sizes),*]);
+        }
+
+        #check
+    }
 }
 
-// helper function which returns code for compiling
-// individual enum variant
-fn compile_enum_variant(name: &syn::Ident, i: usize, v: &syn::Variant) -> proc_macro2::TokenStream {
+// assigns the opcodes to the variants, using the lockfile if asked to.
+// When the lockfile is used, this also returns code which makes the
+// compiler track the lockfile, so that changes to it cause recompilation.
+// The variants of each prefix group are assigned separately, and recorded
+// in a section of their own, keyed as `Opcode/0xf`
+fn assign_opcodes<'a>(
+    name: &syn::Ident,
+    enum_attrs: &EnumAttrs,
+    input_enum: &'a syn::DataEnum,
+) -> syn::Result<(Vec<opcode::VariantOpcode<'a>>, proc_macro2::TokenStream)> {
+    let mut groups = vec![None];
+    groups.extend(opcode::prefixes(input_enum).into_iter().map(Some));
+    // the reserved opcodes only apply to the opcodes without a prefix
+    let assign = |prefix: Option<usize>, lock: Option<&mut LockSection>| {
+        let reserved: &[_] = match prefix {
+            Some(_) => &[],
+            None => &enum_attrs.reserved,
+        };
+        opcode::assign_opcodes(
+            input_enum,
+            lock,
+            enum_attrs.tag.max_opcode(),
+            reserved,
+            prefix,
+        )
+    };
+    let mut errors = Errors::default();
+    let mut ret = Vec::with_capacity(input_enum.variants.len());
+
+    let key = match &enum_attrs.lock {
+        Some(key) => key,
+        None => {
+            for prefix in groups {
+                ret.extend(errors.check(assign(prefix, None)).unwrap_or_default());
+            }
+            errors.finish()?;
+            return Ok((ret, quote! {}));
+        }
+    };
+    let lock_err = |e: String| syn::Error::new(name.span(), e);
+
+    let mut lockfile = Lockfile::open().map_err(lock_err)?;
+    for prefix in groups {
+        let key = match prefix {
+            Some(p) => format!("{}/{:#x}", key, p),
+            None => key.clone(),
+        };
+        let mut section = lockfile.section(&key);
+        if let Some(opcodes) = errors.check(assign(prefix, Some(&mut section))) {
+            ret.extend(opcodes);
+            lockfile.set_section(&key, section);
+        }
+    }
+    // the lockfile is only updated if all the groups are assigned
+    errors.finish()?;
+    lockfile.save().map_err(lock_err)?;
+
+    // the crate is rebuilt when the file or the update variable changes,
+    // so the opcodes are checked again
+    let path = lockfile.path().display().to_string();
+    let dependency = quote! {
+        const _: &[u8] = include_bytes!(#path);
+        const _: core::option::Option<&'static str> = option_env!(#UPDATE_VAR);
+    };
+    Ok((ret, dependency))
+}
+
+// the fields of the variant, which are bound to variables of the same name
+// for named fields, and v0, v1 ... for unnamed fields
+fn variant_fields(v: &syn::Variant) -> syn::Result<Vec<fields::FieldCode<'_>>> {
+    let var = |f: &syn::Field, i: usize| match &f.ident {
+        Some(ident) => ident.clone(),
+        None => Ident::new(&format!("v{}", i), v.ident.span()),
+    };
+    fields::field_list(&v.fields, |f, i| var(f, i).into_token_stream(), var)
+}
+
+// the pattern or expression which destructures or constructs the variant
+// from the variables of its fields
+pub fn variant_pattern(
+    name: &syn::Ident,
+    v: &syn::Variant,
+    fields: &[fields::FieldCode],
+) -> proc_macro2::TokenStream {
     let ident = &v.ident;
-    let instr = util::compile_instr(i);
+    let vars = fields.iter().map(|f| &f.var);
     match &v.fields {
-        syn::Fields::Unit => {
-            quote! {
-                #name::#ident => {#instr}
-            }
+        syn::Fields::Unit => quote! { #name::#ident },
+        syn::Fields::Unnamed(_) => quote! { #name::#ident(#(#vars),*) },
+        syn::Fields::Named(_) => quote! { #name::#ident{#(#vars),*} },
+    }
+}
+
+// finds the unknown variant, if any, and checks that it can hold the opcode
+fn unknown_variant(input_enum: &syn::DataEnum) -> syn::Result<Option<usize>> {
+    let mut errors = Errors::default();
+    let mut ret = None;
+    for (i, v) in input_enum.variants.iter().enumerate() {
+        // errors in the attributes are reported when the opcodes are assigned
+        let span = match opcode::parse_variant_attrs(v) {
+            Ok(opcode::VariantAttrs {
+                unknown: Some(span),
+                ..
+            }) => span,
+            _ => continue,
+        };
+        if ret.is_some() {
+            errors.push(syn::Error::new(span, "only one variant can be unknown"));
+            continue;
         }
-        syn::Fields::Unnamed(fields) => {
-            let field_list = fields
-                .unnamed
-                .iter()
-                .enumerate()
-                .map(|(i, _)| Ident::new(&format!("v{}", i), ident.span()));
-            let fields_compiled = field_list.clone().map(|f| {
-                quote! {
-                    #f.compile()
-                }
-            });
-            let field_list_bracketed = quote! {
-                (#(#field_list),*)
+        if v.fields.len() != 1 {
+            errors.push(syn::Error::new(
+                v.ident.span(),
+                "the unknown variant must have a single field, which holds the opcode",
+            ));
+        } else if let Some(a) = v
+            .fields
+            .iter()
+            .flat_map(|f| &f.attrs)
+            .find(|a| a.path.is_ident("bytecode"))
+        {
+            errors.push(syn::Error::new_spanned(
+                a,
+                "the field of the unknown variant cannot have bytecode attributes",
+            ));
+        }
+        ret = Some(i);
+    }
+    errors.finish()?;
+    Ok(ret)
+}
+
+// returns the code for compiling the unknown variant, which writes the opcode it holds.
+// Opcodes of other variants or prefixes, and the ones which the tag cannot store, give an error,
+// as they would not be parsed back as the unknown variant
+fn compile_unknown(
+    name: &syn::Ident,
+    tag: util::Tag,
+    out: &Ident,
+    u: &UnknownCode,
+    main_opcodes: &[usize],
+    reserved: &[RangeInclusive<usize>],
+) -> proc_macro2::TokenStream {
+    let pattern = variant_pattern(name, u.variant, &u.fields);
+    let var = &u.fields[0].var;
+    let op = Ident::new("__op", name.span());
+    let compile = tag.compile_dynamic(&op, out);
+    let max_opcode = tag.max_opcode() as u32;
+    let opcodes = main_opcodes.iter().map(|&op| {
+        let op = op as u32;
+        quote! { #op }
+    });
+    let assigned = matches_any(
+        &Ident::new("op", name.span()),
+        opcodes.chain(reserved_patterns(reserved)),
+    );
+    quote! {
+        #pattern => {
+            let #op: u32 = match <u32 as core::convert::TryFrom<_>>::try_from(*#var) {
+                core::result::Result::Ok(op) if op <= #max_opcode && !#assigned => op,
+                _ => return core::result::Result::Err(bytecode::BytecodeError::new(
+                    bytecode::ErrorKind::Other("unknown variant holds an opcode which is assigned or out of range")
+                )),
             };
+            #compile
+        }
+    }
+}
+
+// returns the code for an opcode which is not assigned to any variant, which gives
+// the unknown variant if there is one, and an invalid instruction error otherwise.
+// `bytes` are the bytes of the opcode, and `ok_value` wraps the constructed variant
+fn parse_unknown(
+    name: &syn::Ident,
+    unknown: Option<&UnknownCode>,
+    reserved: &[RangeInclusive<usize>],
+    opcode_var: &Ident,
+    bytes: proc_macro2::TokenStream,
+    ok_value: impl Fn(proc_macro2::TokenStream) -> proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let path = name.to_string();
+    let invalid = quote! {
+        core::result::Result::Err(bytecode::BytecodeError::invalid(#bytes).within(#path))
+    };
+    let u = match unknown {
+        Some(u) => u,
+        None => return invalid,
+    };
+    let ty = &u.fields[0].field.ty;
+    let var = &u.fields[0].var;
+    let value = ok_value(variant_pattern(name, u.variant, &u.fields));
+    // the reserved opcodes are invalid even with an unknown variant
+    let reserved = matches_any(opcode_var, reserved_patterns(reserved));
+    quote! {
+        match <#ty as core::convert::TryFrom<u32>>::try_from(#opcode_var) {
+            core::result::Result::Ok(#var) if !#reserved => core::result::Result::Ok(#value),
+            _ => #invalid,
+        }
+    }
+}
+
+// the match patterns for the reserved opcodes
+fn reserved_patterns(
+    reserved: &[RangeInclusive<usize>],
+) -> impl Iterator<Item = proc_macro2::TokenStream> + '_ {
+    reserved.iter().map(|r| {
+        let (start, end) = (*r.start() as u32, *r.end() as u32);
+        quote! { #start..=#end }
+    })
+}
+
+// generates an expression checking if the u32 variable matches any of the patterns
+fn matches_any(
+    var: &Ident,
+    patterns: impl Iterator<Item = proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream {
+    let patterns: Vec<_> = patterns.collect();
+    if patterns.is_empty() {
+        return quote! { false };
+    }
+    quote! { core::matches!(#var, #(#patterns)|*) }
+}
+
+// returns the match arms which parse each of the variants, once its opcode is extracted
+fn parse_arms(
+    name: &syn::Ident,
+    param_name: &Ident,
+    opcode_var: &Ident,
+    variants: &[&VariantCode],
+) -> Vec<proc_macro2::TokenStream> {
+    variants
+        .iter()
+        .map(|v| {
+            let opcodes = v.opcode_patterns();
+            let parsed = parse_variant(name, param_name, opcode_var, v);
             quote! {
-                #name::#ident #field_list_bracketed => {
-                    let mut _i = #instr;
-                    #(_i.extend(&#fields_compiled);)*
-                    return _i;
+                #(#opcodes)|* => {
+                    #parsed
                 }
             }
-        }
-        syn::Fields::Named(named) => {
-            let fields = &named.named;
-            let field_names = fields.iter().map(|f| f.ident.as_ref().unwrap());
-            let fields_compiled = fields.iter().map(|f| {
-                let ident = &f.ident;
-                quote! {
-                    #ident.compile()
-                }
-            });
+        })
+        .collect()
+}
 
-            let compiled = quote! {
-                let mut _i = #instr;
-                #(_i.extend(&#fields_compiled);)*
-                return _i;
-            };
+// returns the match arms which read each of the variants, once its opcode is extracted
+fn read_arms(
+    name: &syn::Ident,
+    reader_name: &Ident,
+    opcode_var: &Ident,
+    variants: &[&VariantCode],
+) -> Vec<proc_macro2::TokenStream> {
+    variants
+        .iter()
+        .map(|v| {
+            let opcodes = v.opcode_patterns();
+            let read = read_variant(name, reader_name, opcode_var, v);
             quote! {
-                #name::#ident {#(#field_names),* } => {
-                    #compiled
+                #(#opcodes)|* => {
+                    #read
                 }
             }
+        })
+        .collect()
+}
+
+// Returns the match arm for a prefix, which extracts the opcode after the prefix
+// and matches it among the variants of the group. The group is parsed in a closure
+// from the bytes after the prefix, same as a separate enum would be, and then
+// the length of the prefix is added to the size and the error offsets.
+fn parse_prefix_group(
+    name: &syn::Ident,
+    tag: util::Tag,
+    prefix: usize,
+    group: &[&VariantCode],
+    param_name: &Ident,
+    opcode_var: &Ident,
+) -> proc_macro2::TokenStream {
+    let path = name.to_string();
+    let max_opcode = group.iter().flat_map(|v| v.opcodes()).max().unwrap_or(0);
+    let parse_opcode = tag.parse_instr(param_name, opcode_var, max_opcode, &path);
+    let arms = parse_arms(name, param_name, opcode_var, group);
+    let prefix = prefix as u32;
+    quote! {
+        #prefix => {
+            let __prefix_len: usize = __opcode_len;
+            let #param_name = &#param_name[__prefix_len..];
+            #[allow(clippy::redundant_closure_call)]
+            let __ret = (|| -> core::result::Result<(Self, usize), bytecode::BytecodeError> {
+                #parse_opcode
+                match #opcode_var {
+                    #(#arms),*
+                    _ => core::result::Result::Err(
+                        bytecode::BytecodeError::invalid(&#param_name[..__opcode_len]).within(#path)
+                    ),
+                }
+            })();
+            __ret
+                .map(|(v, size)| (v, size + __prefix_len))
+                .map_err(|e| e.offset_by(__prefix_len))
         }
     }
 }
 
-// helper function which returns the code for parsing
-// single byte long instruction
-fn parse_single_byte(
-    enum_name: &syn::Ident,
-    param_name: &syn::Ident,
-    variant: &syn::Variant,
+// returns the match arm for a prefix when reading, same as parse_prefix_group
+fn read_prefix_group(
+    name: &syn::Ident,
+    tag: util::Tag,
+    prefix: usize,
+    group: &[&VariantCode],
+    reader_name: &Ident,
+    opcode_var: &Ident,
 ) -> proc_macro2::TokenStream {
-    let variant_name = &variant.ident;
-    match &variant.fields {
-        syn::Fields::Unit => parse_unit_field(enum_name, variant_name, 1),
-        syn::Fields::Unnamed(fields) => {
-            parse_unnamed_field(fields, enum_name, variant_name, param_name, 1)
+    let path = name.to_string();
+    let max_opcode = group.iter().flat_map(|v| v.opcodes()).max().unwrap_or(0);
+    let opcode_bytes = Ident::new("__opcode_bytes", name.span());
+    let read_opcode = tag.read_instr(reader_name, &opcode_bytes, max_opcode, &path);
+    let extract_opcode = tag.parse_instr(&opcode_bytes, opcode_var, max_opcode, &path);
+    let arms = read_arms(name, reader_name, opcode_var, group);
+    let prefix = prefix as u32;
+    quote! {
+        #prefix => {
+            let __prefix_len: usize = __opcode_len;
+            #[allow(clippy::redundant_closure_call)]
+            let __ret = (|| -> core::result::Result<Self, bytecode::BytecodeError> {
+                #read_opcode
+                #extract_opcode
+                match #opcode_var {
+                    #(#arms),*
+                    _ => core::result::Result::Err(
+                        bytecode::BytecodeError::invalid(#opcode_bytes).within(#path)
+                    ),
+                }
+            })();
+            __ret.map_err(|e| e.offset_by(__prefix_len))
         }
-        syn::Fields::Named(fields) => parse_named_field(fields, enum_name, variant_name, 1),
     }
 }
 
-// helper function which returns code for parsing
-// two byte instruction length variants
-fn parse_two_byte(
-    enum_name: &syn::Ident,
-    param_name: &syn::Ident,
-    lower_byte: u8,
-    variant: &syn::Variant,
+// helper function which returns code for compiling
+// individual enum variant into the `out` sink
+fn compile_enum_variant(
+    name: &syn::Ident,
+    tag: util::Tag,
+    out: &Ident,
+    v: &VariantCode,
 ) -> proc_macro2::TokenStream {
-    let variant_name = &variant.ident;
-    let parsed = match &variant.fields {
-        syn::Fields::Unit => parse_unit_field(enum_name, variant_name, 2),
-        syn::Fields::Unnamed(fields) => {
-            parse_unnamed_field(fields, enum_name, variant_name, param_name, 2)
+    let prefix = v.prefix.map(|p| tag.compile_instr(p, out));
+    let op = Ident::new("__op", name.span());
+    let instr = match v.embed {
+        Some(e) => {
+            let f = &v.fields[e.field];
+            let msg = format!(
+                "value of field `{}` does not fit in {} bits",
+                f.name(e.field),
+                e.bits
+            );
+            let error = quote! {
+                .map_err(|_| bytecode::BytecodeError::new(bytecode::ErrorKind::Other(#msg)))?
+            };
+            let opcode = embedded_opcode(v, &op, error);
+            let compile = tag.compile_dynamic(&op, out);
+            quote! {
+                #opcode
+                #compile
+            }
         }
-        syn::Fields::Named(fields) => parse_named_field(fields, enum_name, variant_name, 2),
+        None => tag.compile_instr(v.opcode, out),
     };
+    let pattern = variant_pattern(name, v.variant, &v.fields);
+    let compiled = fields::compile_fields(&v.fields, out);
     quote! {
-        #lower_byte =>{#parsed}
+        #pattern => {
+            #prefix
+            #instr
+            #compiled
+        }
     }
 }
 
-fn parse_unit_field(
+// Generates the statement which sets the u32 variable `op` to the opcode of a variant
+// with an embedded field, by setting the lower bits of the opcode to the value of the field.
+// `on_error` is applied to the result of converting the value, to get the value itself
+fn embedded_opcode(
+    v: &VariantCode,
+    op: &Ident,
+    on_error: proc_macro2::TokenStream,
+) -> Option<proc_macro2::TokenStream> {
+    let e = v.embed?;
+    let f = &v.fields[e.field];
+    let (ty, access, bits) = (&f.field.ty, &f.access, e.bits);
+    let opcode = v.opcode as u32;
+    Some(quote! {
+        let #op: u32 = #opcode | <#ty as bytecode::BitPackable>::to_bits(&#access, #bits)#on_error as u32;
+    })
+}
+
+// Generates the statement which extracts the embedded field of the variant, if any,
+// from the lower bits of the opcode in `opcode_var`. `bytes` are the bytes of the
+// opcode, which are reported if the value is not valid for the field
+fn parse_embedded(
     enum_name: &syn::Ident,
-    variant: &syn::Ident,
-    init_size: usize,
-) -> proc_macro2::TokenStream {
-    quote! {
-        return std::result::Result::Ok((#enum_name::#variant,#init_size));
-    }
+    v: &VariantCode,
+    opcode_var: &Ident,
+    bytes: proc_macro2::TokenStream,
+) -> Option<proc_macro2::TokenStream> {
+    let e = v.embed?;
+    let f = &v.fields[e.field];
+    let (ty, var, bits) = (&f.field.ty, &f.var, e.bits);
+    let mask = e.mask() as u32;
+    let segment = format!("{}::{}.{}", enum_name, v.variant.ident, f.name(e.field));
+    Some(quote! {
+        let #var = <#ty as bytecode::BitPackable>::from_bits((#opcode_var & #mask) as u64, #bits)
+            .map_err(|e| e.with_bytes(#bytes).within(#segment))?;
+    })
 }
 
-fn parse_unnamed_field(
-    fields: &syn::FieldsUnnamed,
+// helper function which returns the code for parsing
+// the fields of a variant, once its opcode is matched
+fn parse_variant(
     enum_name: &syn::Ident,
-    variant: &syn::Ident,
     param_name: &syn::Ident,
-    init_size: usize,
+    opcode_var: &Ident,
+    v: &VariantCode,
 ) -> proc_macro2::TokenStream {
-    let size_counter_var = Ident::new("_count", variant.span());
-    let field_list = fields
-        .unnamed
-        .iter()
-        .enumerate()
-        .map(|(i, _)| Ident::new(&format!("v{}", i), variant.span()));
-    let field_parsed_list = fields.unnamed.iter().zip(field_list.clone()).map(|(f, v)| {
-        quote! {
-            let (#v,size) = #f::parse(&#param_name[#size_counter_var..])?;
-            #size_counter_var += size;
-        }
-    });
-    let field_list_bracketed = quote! {
-        #enum_name::#variant(#(#field_list),*)
-    };
+    // the opcode can be an alias, which may take a different number of bytes
+    let init_size = quote! { __opcode_len };
+    let pattern = variant_pattern(enum_name, v.variant, &v.fields);
+    let embedded = parse_embedded(
+        enum_name,
+        v,
+        opcode_var,
+        quote! { &#param_name[..__opcode_len] },
+    );
+    if v.fields.iter().all(|f| f.embedded) {
+        return quote! {
+            #embedded
+            return core::result::Result::Ok((#pattern,#init_size));
+        };
+    }
+    let size_counter_var = Ident::new("_count", v.variant.ident.span());
+    let path = format!("{}::{}", enum_name, v.variant.ident);
+    let parsed = fields::parse_fields(&v.fields, param_name, &size_counter_var, &path);
     quote! {
+        #embedded
         let mut #size_counter_var :usize = #init_size;
-        #(#field_parsed_list)*
-        return std::result::Result::Ok((#field_list_bracketed,#size_counter_var));
+        #parsed
+        return core::result::Result::Ok((#pattern,#size_counter_var));
     }
 }
 
-fn parse_named_field(
-    fields: &syn::FieldsNamed,
+// helper function which returns the code for reading
+// the fields of a variant from the reader, once its opcode is matched
+fn read_variant(
     enum_name: &syn::Ident,
-    variant: &syn::Ident,
-    init_size: usize,
+    reader_name: &syn::Ident,
+    opcode_var: &Ident,
+    v: &VariantCode,
 ) -> proc_macro2::TokenStream {
-    let fields = &fields.named;
-
-    let size_counter_var = syn::Ident::new("_count", enum_name.span());
-    let parse_fn_param_name = syn::Ident::new("__bytes", enum_name.span());
+    // the opcode can be an alias, which may take a different number of bytes
+    let init_size = quote! { __opcode_len };
+    let pattern = variant_pattern(enum_name, v.variant, &v.fields);
+    let opcode_bytes = Ident::new("__opcode_bytes", enum_name.span());
+    let embedded = parse_embedded(enum_name, v, opcode_var, opcode_bytes.to_token_stream());
+    if v.fields.iter().all(|f| f.embedded) {
+        return quote! {
+            #embedded
+            return core::result::Result::Ok(#pattern);
+        };
+    }
+    let size_counter_var = Ident::new("_count", v.variant.ident.span());
+    let path = format!("{}::{}", enum_name, v.variant.ident);
+    let read = fields::read_fields(&v.fields, reader_name, &size_counter_var, &path);
+    quote! {
+        #embedded
+        let mut #size_counter_var :usize = #init_size;
+        #read
+        return core::result::Result::Ok(#pattern);
+    }
+}
 
-    let parsed = fields.iter().map(|f| {
-        let ident = f.ident.as_ref().unwrap();
-        let ty = &f.ty;
+// Enums with only unit variants, such as registers, can be packed into bits of
+// other values, by using the opcode of the variant as the packed value
+fn derive_bit_packable(
+    name: &syn::Ident,
+    generics: &syn::Generics,
+    variants: &[VariantCode],
+) -> proc_macro2::TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let to_bits = variants.iter().map(|v| {
+        let ident = &v.variant.ident;
+        let opcode = v.opcode as u64;
         quote! {
-            let (#ident,size) = #ty::parse(&#parse_fn_param_name[#size_counter_var..])?;
-            #size_counter_var += size;
+            #name::#ident => #opcode
+        }
+    });
+    let from_bits = variants.iter().map(|v| {
+        let ident = &v.variant.ident;
+        let opcodes = v.opcodes().map(|op| op as u64);
+        quote! {
+            #(#opcodes)|* => core::result::Result::Ok(#name::#ident)
         }
     });
-
-    let field_name_list = fields.iter().map(|f| f.ident.as_ref().unwrap());
-
     quote! {
-        let mut #size_counter_var = #init_size;
-        #(#parsed)*
-        let _t = #enum_name::#variant{
-            #(#field_name_list),*
-        };
-        std::result::Result::Ok((_t,#size_counter_var))
+        impl #impl_generics bytecode::BitPackable for #name #ty_generics #where_clause{
+            fn to_bits(&self, bits: u32)->core::result::Result<u64,bytecode::BytecodeError>{
+                let v: u64 = match self {
+                    #(#to_bits),*
+                };
+                bytecode::BitPackable::to_bits(&v, bits)
+            }
 
+            fn from_bits(value: u64, bits: u32)->core::result::Result<Self,bytecode::BytecodeError>{
+                match <u64 as bytecode::BitPackable>::from_bits(value, bits)? {
+                    #(#from_bits,)*
+                    _ => core::result::Result::Err(bytecode::BytecodeError::new(bytecode::ErrorKind::InvalidInstruction))
+                }
+            }
+        }
     }
 }
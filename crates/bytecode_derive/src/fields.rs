@@ -0,0 +1,732 @@
+use crate::attr;
+use crate::errors::Errors;
+use crate::generics;
+use crate::impls::DECODE_LIFETIME;
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote, ToTokens};
+use syn::spanned::Spanned;
+
+// largest bit width a packed field can have, as BitPackable works with u64
+const MAX_FIELD_BITS: usize = 64;
+
+// options which can be given on a field using #[bytecode(...)]
+#[derive(Default)]
+pub struct FieldAttrs {
+    // number of bits the field is packed in, if it is bit-packed
+    pub bits: Option<u32>,
+    // type used to store the length, for collections given with `len = u16`
+    pub len: Option<syn::Type>,
+    // maximum number of elements, for collections given with `max_len = 256`
+    pub max_len: Option<syn::Expr>,
+    // if the integer field is stored as a variable length integer
+    pub varint: bool,
+    // byte order of the field, given on the field or on the type containing it
+    pub endian: Option<ByteOrder>,
+    // the earlier fields the encoding of this one depends on, given with `depends = field`
+    // or `depends = (field, other)`
+    pub depends: Option<syn::Expr>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    Big,
+    Little,
+}
+
+// parses the value of `endian = "big"`, which can be given on fields and types
+pub fn parse_endian(arg: &attr::BytecodeArg) -> syn::Result<ByteOrder> {
+    let expr = arg.expr()?;
+    match attr::expr_to_string(expr)?.as_str() {
+        "big" => Ok(ByteOrder::Big),
+        "little" => Ok(ByteOrder::Little),
+        _ => Err(syn::Error::new(
+            expr.span(),
+            "expected `endian = \"big\"` or `endian = \"little\"`",
+        )),
+    }
+}
+
+impl FieldAttrs {
+    // collections with a custom length prefix or maximum length are compiled
+    // using the functions of bytecode::collections, instead of their Bytecodable impl
+    pub fn prefixed(&self) -> bool {
+        self.len.is_some() || self.max_len.is_some()
+    }
+}
+
+fn parse_field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let mut ret = FieldAttrs::default();
+    let mut errors = Errors::default();
+    for arg in attr::parse_attrs(&field.attrs)? {
+        match arg.key().as_str() {
+            "bits" => ret.bits = errors.check(parse_bits(&arg)),
+            "len" => ret.len = errors.check(parse_len(&arg)),
+            "max_len" => ret.max_len = errors.check(arg.expr().cloned()),
+            "varint" => ret.varint = errors.check(arg.expect_flag()).is_some(),
+            "endian" => ret.endian = errors.check(parse_endian(&arg)),
+            "depends" => ret.depends = errors.check(arg.expr().cloned()),
+            _ => errors.push(arg.unknown()),
+        }
+    }
+    if ret.bits.is_some() && ret.prefixed() {
+        errors.push(syn::Error::new(
+            field.span(),
+            "bit-packed fields cannot have a length prefix",
+        ));
+    }
+    if ret.varint && (ret.bits.is_some() || ret.prefixed()) {
+        errors.push(syn::Error::new(
+            field.span(),
+            "varint fields cannot be bit-packed or have a length prefix",
+        ));
+    }
+    if ret.endian.is_some() && (ret.bits.is_some() || ret.varint) {
+        errors.push(syn::Error::new(
+            field.span(),
+            "byte order cannot be given for varint or bit-packed fields",
+        ));
+    }
+    if ret.depends.is_some()
+        && (ret.bits.is_some() || ret.varint || ret.prefixed() || ret.endian.is_some())
+    {
+        errors.push(syn::Error::new(
+            field.span(),
+            "dependent fields cannot be bit-packed, varint, or have a length prefix or byte order",
+        ));
+    }
+    errors.finish()?;
+    Ok(ret)
+}
+
+// the length prefix is given as a type, such as `len = u16`
+fn parse_len(arg: &attr::BytecodeArg) -> syn::Result<syn::Type> {
+    syn::parse2(arg.expr()?.to_token_stream())
+}
+
+fn parse_bits(arg: &attr::BytecodeArg) -> syn::Result<u32> {
+    let expr = arg.expr()?;
+    let bits = attr::expr_to_usize(expr)?;
+    if bits == 0 || bits > MAX_FIELD_BITS {
+        return Err(syn::Error::new(
+            expr.span(),
+            format!("bit width must be between 1 and {}", MAX_FIELD_BITS),
+        ));
+    }
+    Ok(bits as u32)
+}
+
+// some kinds of types can never be encoded in the bytecode,
+// so we report them here, rather than leaving it to confusing trait errors
+fn check_field_type(ty: &syn::Type) -> syn::Result<()> {
+    let msg = match ty {
+        syn::Type::Group(g) => return check_field_type(&g.elem),
+        syn::Type::Paren(p) => return check_field_type(&p.elem),
+        // shared references can be decoded by borrowing from the bytecode
+        syn::Type::Reference(r) if r.mutability.is_none() => return check_field_type(&r.elem),
+        syn::Type::Reference(_) => "mutable references are not supported as fields",
+        syn::Type::Ptr(_) => "raw pointers are not supported as fields",
+        syn::Type::BareFn(_) => "function pointers are not supported as fields",
+        syn::Type::TraitObject(_) | syn::Type::ImplTrait(_) => {
+            "trait objects are not supported as fields"
+        }
+        syn::Type::Never(_) => "never type is not supported as field",
+        _ => return Ok(()),
+    };
+    Err(syn::Error::new(ty.span(), msg))
+}
+
+// finds the field given by name, or by position for tuple fields, such as the
+// field named in `depends = field`
+pub fn field_position(fields: &syn::Fields, expr: &syn::Expr) -> syn::Result<usize> {
+    let found = match expr {
+        syn::Expr::Path(p) => {
+            let ident = p.path.get_ident();
+            let position = fields
+                .iter()
+                .position(|f| f.ident.is_some() && f.ident.as_ref() == ident);
+            position.ok_or("no field with this name")
+        }
+        _ => {
+            let index = attr::expr_to_usize(expr)?;
+            match fields {
+                syn::Fields::Unnamed(_) if index < fields.len() => Ok(index),
+                _ => Err("no field at this position"),
+            }
+        }
+    };
+    found.map_err(|msg| syn::Error::new(expr.span(), msg))
+}
+
+// the earlier fields a dependent field is encoded against, each with the expression
+// to access its value in the compile method, and the variable it is parsed into
+pub struct Context<'a> {
+    pub fields: Vec<(&'a syn::Field, TokenStream, Ident)>,
+}
+
+impl Context<'_> {
+    // the type given to bytecode::Dependent, which is a tuple for more than one field
+    pub fn ty(&self) -> TokenStream {
+        match self.fields.as_slice() {
+            [(f, _, _)] => f.ty.to_token_stream(),
+            fields => {
+                let tys = fields.iter().map(|(f, _, _)| &f.ty);
+                quote! { (#(#tys,)*) }
+            }
+        }
+    }
+
+    // The context passed to the methods of bytecode::Dependent, which is a reference to the
+    // value of the field, or to a tuple of clones of the values. `parsed` tells if the values
+    // are in their variables, rather than accessed as in the compile method
+    fn value(&self, parsed: bool) -> TokenStream {
+        let values = self.fields.iter().map(|(_, access, var)| match parsed {
+            true => var.to_token_stream(),
+            false => access.clone(),
+        });
+        match self.fields.len() {
+            1 => quote! { &#(#values)* },
+            _ => quote! { &(#(core::clone::Clone::clone(&#values),)*) },
+        }
+    }
+}
+
+// A field of a struct or an enum variant, along with the expression used to access
+// its value in the compile method, and the variable its value is parsed into in the parse method
+pub struct FieldCode<'a> {
+    pub field: &'a syn::Field,
+    pub attrs: FieldAttrs,
+    pub access: TokenStream,
+    pub var: Ident,
+    // if the field borrows from the bytecode, such as &[u8], in which case
+    // BytecodeEncode and BytecodeDecode are used for it instead of Bytecodable
+    pub borrowed: bool,
+    // if the field is embedded in the opcode of an enum variant, in which case
+    // it is compiled and parsed along with the opcode, rather than with the fields
+    pub embedded: bool,
+    // the field this one depends on, in which case bytecode::Dependent is used for it
+    pub context: Option<Context<'a>>,
+}
+
+impl<'a> FieldCode<'a> {
+    // name used for the field in messages, for tuple fields this is the index
+    pub fn name(&self, index: usize) -> String {
+        match &self.field.ident {
+            Some(i) => i.to_string(),
+            None => index.to_string(),
+        }
+    }
+
+    // if the field is stored in big endian, in which case the methods of
+    // bytecode::endian::Endian are used for it, instead of Bytecodable
+    pub fn big_endian(&self) -> bool {
+        self.attrs.endian == Some(ByteOrder::Big)
+    }
+
+    // the trait used to compile the field
+    fn encode_trait(&self) -> TokenStream {
+        if self.borrowed {
+            quote! { bytecode::BytecodeEncode }
+        } else {
+            quote! { bytecode::Bytecodable }
+        }
+    }
+
+    // the type used to store the length and the maximum length of a prefixed field
+    fn prefix(&self) -> (TokenStream, TokenStream) {
+        let len = match &self.attrs.len {
+            Some(ty) if self.big_endian() => quote! { bytecode::BigEndian<#ty> },
+            Some(ty) => ty.to_token_stream(),
+            None => quote! { bytecode::VarU64 },
+        };
+        let max_len = match &self.attrs.max_len {
+            Some(e) => e.to_token_stream(),
+            None => quote! { bytecode::collections::DEFAULT_MAX_LEN },
+        };
+        (len, max_len)
+    }
+
+    // the function of bytecode::collections used for a prefixed field, the ones
+    // ending in `_be` store the elements in big endian as well
+    fn prefixed_fn(&self, name: &str) -> Ident {
+        match self.big_endian() {
+            true => format_ident!("{}_be", name),
+            false => format_ident!("{}", name),
+        }
+    }
+
+    // The methods below generate the code for a field which is not bit-packed,
+    // depending on how it is encoded
+
+    fn min_size(&self) -> TokenStream {
+        let ty = &self.field.ty;
+        if let Some(ctx) = &self.context {
+            let cty = ctx.ty();
+            quote! { <#ty as bytecode::Dependent<#cty>>::MIN_SIZE }
+        } else if self.attrs.prefixed() {
+            let (len, _) = self.prefix();
+            quote! { <#len as bytecode::collections::LengthPrefix>::MIN_SIZE }
+        } else if self.attrs.varint {
+            quote! { 1 }
+        } else {
+            let encode = self.encode_trait();
+            quote! { <#ty as #encode>::MIN_SIZE }
+        }
+    }
+
+    fn max_size(&self) -> TokenStream {
+        let ty = &self.field.ty;
+        if let Some(ctx) = &self.context {
+            let cty = ctx.ty();
+            quote! { <#ty as bytecode::Dependent<#cty>>::MAX_SIZE }
+        } else if self.attrs.prefixed() {
+            // collections are unbounded, irrespective of their length prefix
+            quote! { core::option::Option::None }
+        } else if self.attrs.varint {
+            quote! { core::option::Option::Some(<#ty as bytecode::varint::Varint>::MAX_LEN) }
+        } else {
+            let encode = self.encode_trait();
+            quote! { <#ty as #encode>::MAX_SIZE }
+        }
+    }
+
+    // The number of bytes the given value of the field takes, which is a reference to it.
+    // `parsed` tells if the value of the field it depends on is in its variable,
+    // rather than accessed as in the compile method
+    fn encoded_len_of(&self, value: TokenStream, parsed: bool) -> TokenStream {
+        let ty = &self.field.ty;
+        if let Some(ctx) = &self.context {
+            let (cty, ctx) = (ctx.ty(), ctx.value(parsed));
+            quote! { <#ty as bytecode::Dependent<#cty>>::encoded_len_with(#value, #ctx) }
+        } else if self.attrs.prefixed() {
+            let (len, _) = self.prefix();
+            quote! { bytecode::collections::prefixed_encoded_len::<#len, #ty>(#value) }
+        } else if self.attrs.varint {
+            quote! { bytecode::varint::varint_len::<#ty>(#value) }
+        } else {
+            let encode = self.encode_trait();
+            quote! { <#ty as #encode>::encoded_len(#value) }
+        }
+    }
+
+    // the call which compiles the field into the `out` sink
+    fn compile_call(&self, out: &Ident) -> TokenStream {
+        let ty = &self.field.ty;
+        let access = &self.access;
+        if let Some(ctx) = &self.context {
+            let (cty, ctx) = (ctx.ty(), ctx.value(false));
+            quote! { <#ty as bytecode::Dependent<#cty>>::compile_with(&#access, #ctx, &mut *#out) }
+        } else if self.attrs.prefixed() {
+            let (len, max_len) = self.prefix();
+            let compile = self.prefixed_fn("compile_prefixed");
+            quote! { bytecode::collections::#compile::<#len, #ty, _>(&#access, #max_len, &mut *#out) }
+        } else if self.attrs.varint {
+            quote! { bytecode::varint::compile_varint::<#ty, _>(&#access, &mut *#out) }
+        } else if self.big_endian() {
+            quote! { <#ty as bytecode::endian::Endian>::compile_be(&#access, &mut *#out) }
+        } else {
+            let encode = self.encode_trait();
+            quote! { <#ty as #encode>::compile_into(&#access, &mut *#out) }
+        }
+    }
+
+    // the call which parses the field from given bytes
+    fn parse_call(&self, bytes: TokenStream) -> TokenStream {
+        let ty = &self.field.ty;
+        if let Some(ctx) = &self.context {
+            let (cty, ctx) = (ctx.ty(), ctx.value(true));
+            quote! { <#ty as bytecode::Dependent<#cty>>::parse_with(#bytes, #ctx) }
+        } else if self.attrs.prefixed() {
+            let (len, max_len) = self.prefix();
+            let parse = self.prefixed_fn("parse_prefixed");
+            quote! { bytecode::collections::#parse::<#len, #ty>(#bytes, #max_len) }
+        } else if self.attrs.varint {
+            quote! { bytecode::varint::parse_varint::<#ty>(#bytes) }
+        } else if self.big_endian() {
+            quote! { <#ty as bytecode::endian::Endian>::parse_be(#bytes) }
+        } else if self.borrowed {
+            let de = syn::Lifetime::new(DECODE_LIFETIME, self.field.ty.span());
+            quote! { <#ty as bytecode::BytecodeDecode<#de>>::decode(#bytes) }
+        } else {
+            quote! { <#ty as bytecode::Bytecodable>::parse(#bytes) }
+        }
+    }
+
+    // the call which reads the field from the reader
+    fn read_call(&self, reader: &Ident) -> TokenStream {
+        let ty = &self.field.ty;
+        if let Some(ctx) = &self.context {
+            let (cty, ctx) = (ctx.ty(), ctx.value(true));
+            quote! { <#ty as bytecode::Dependent<#cty>>::read_with(&mut *#reader, #ctx) }
+        } else if self.attrs.prefixed() {
+            let (len, max_len) = self.prefix();
+            let read = self.prefixed_fn("read_prefixed");
+            quote! { bytecode::collections::#read::<#len, #ty, _>(&mut *#reader, #max_len) }
+        } else if self.attrs.varint {
+            quote! { bytecode::varint::read_varint::<#ty, _>(&mut *#reader) }
+        } else if self.big_endian() {
+            quote! { <#ty as bytecode::endian::Endian>::read_be(&mut *#reader) }
+        } else {
+            quote! { <#ty as bytecode::Bytecodable>::read_from(&mut *#reader) }
+        }
+    }
+}
+
+// fields of variable length cannot be in types with fixed size, which is checked here
+// as their types might be of fixed size otherwise, such as varint integers
+pub fn check_fixed_size<'f, 'a: 'f>(
+    name: &syn::Ident,
+    mut fields: impl Iterator<Item = &'f FieldCode<'a>>,
+) -> syn::Result<()> {
+    let mut errors = Errors::default();
+    for f in fields.by_ref() {
+        if f.borrowed {
+            return Err(syn::Error::new(
+                name.span(),
+                "fixed_size is not supported for types with borrowed fields",
+            ));
+        }
+        if f.attrs.varint || f.attrs.prefixed() || f.context.is_some() {
+            errors.push(syn::Error::new(
+                f.field.span(),
+                "fields of variable length are not supported in fixed_size types",
+            ));
+        }
+    }
+    errors.finish()
+}
+
+// Builds the list of fields, `access` gives the expression used to access
+// the field value in compile method, given the field and its index.
+// This reports the errors of all the fields together.
+pub fn field_list<'a>(
+    fields: &'a syn::Fields,
+    access: impl Fn(&'a syn::Field, usize) -> TokenStream,
+    var: impl Fn(&'a syn::Field, usize) -> Ident,
+) -> syn::Result<Vec<FieldCode<'a>>> {
+    let mut errors = Errors::default();
+    let mut ret = Vec::with_capacity(fields.len());
+    for (i, f) in fields.iter().enumerate() {
+        errors.check(check_field_type(&f.ty));
+        if let Some(attrs) = errors.check(parse_field_attrs(f)) {
+            let borrowed = generics::mentions_lifetime(f.ty.to_token_stream());
+            if borrowed && attrs.prefixed() {
+                errors.push(syn::Error::new(
+                    f.ty.span(),
+                    "length prefix cannot be changed for borrowed fields",
+                ));
+            }
+            if borrowed && attrs.varint {
+                errors.push(syn::Error::new(
+                    f.ty.span(),
+                    "borrowed fields cannot be varint",
+                ));
+            }
+            if borrowed && attrs.endian.is_some() {
+                errors.push(syn::Error::new(
+                    f.ty.span(),
+                    "byte order cannot be given for borrowed fields",
+                ));
+            }
+            if borrowed && attrs.depends.is_some() {
+                errors.push(syn::Error::new(
+                    f.ty.span(),
+                    "borrowed fields cannot depend on other fields",
+                ));
+            }
+            let context = match &attrs.depends {
+                Some(expr) => errors.check(depends_on(fields, expr, i)).map(|positions| {
+                    let fields = positions.into_iter().map(|j| {
+                        let field = fields.iter().nth(j).unwrap();
+                        (field, access(field, j), var(field, j))
+                    });
+                    Context {
+                        fields: fields.collect(),
+                    }
+                }),
+                None => None,
+            };
+            ret.push(FieldCode {
+                field: f,
+                attrs,
+                access: access(f, i),
+                var: var(f, i),
+                borrowed,
+                embedded: false,
+                context,
+            });
+        }
+    }
+    errors.finish()?;
+    Ok(ret)
+}
+
+// finds the fields given in `depends = ..` of the field at `index`, which is a single field
+// or a tuple of them. These have to be before it, so that their values are known when this
+// one is parsed
+fn depends_on(fields: &syn::Fields, expr: &syn::Expr, index: usize) -> syn::Result<Vec<usize>> {
+    let exprs: Vec<_> = match expr {
+        syn::Expr::Tuple(t) if t.elems.is_empty() => {
+            return Err(syn::Error::new(expr.span(), "expected at least one field"))
+        }
+        syn::Expr::Tuple(t) => t.elems.iter().collect(),
+        _ => vec![expr],
+    };
+    let mut errors = Errors::default();
+    let mut ret = Vec::with_capacity(exprs.len());
+    for e in exprs {
+        match errors.check(field_position(fields, e)) {
+            Some(position) if position >= index => errors.push(syn::Error::new(
+                e.span(),
+                "a field can only depend on the fields before it",
+            )),
+            Some(position) => ret.push(position),
+            None => {}
+        }
+    }
+    errors.finish()?;
+    Ok(ret)
+}
+
+// applies the byte order given on the type to its fields which do not have their own.
+// Bit-packed, varint, borrowed and dependent fields do not have a byte order,
+// so they are left as is
+pub fn apply_endian(fields: &mut [FieldCode], order: Option<ByteOrder>) {
+    for f in fields {
+        if f.attrs.endian.is_none()
+            && f.attrs.bits.is_none()
+            && !f.attrs.varint
+            && !f.borrowed
+            && f.context.is_none()
+        {
+            f.attrs.endian = order;
+        }
+    }
+}
+
+// consecutive bit-packed fields share bytes, so they are handled as a group
+enum FieldGroup<'f, 'a> {
+    // the field, along with its index
+    Single(usize, &'f FieldCode<'a>),
+    // fields packed together, along with their index
+    Bits(Vec<(usize, &'f FieldCode<'a>)>),
+}
+
+fn groups<'f, 'a>(fields: &'f [FieldCode<'a>]) -> Vec<FieldGroup<'f, 'a>> {
+    let mut ret = Vec::new();
+    for (i, f) in fields.iter().enumerate() {
+        if f.embedded {
+            continue;
+        }
+        if f.attrs.bits.is_none() {
+            ret.push(FieldGroup::Single(i, f));
+            continue;
+        }
+        match ret.last_mut() {
+            Some(FieldGroup::Bits(packed)) => packed.push((i, f)),
+            _ => ret.push(FieldGroup::Bits(vec![(i, f)])),
+        }
+    }
+    ret
+}
+
+// number of bytes a group of bit-packed fields takes, which does not depend on the values
+fn packed_bytes(packed: &[(usize, &FieldCode)]) -> usize {
+    let total_bits: u32 = packed.iter().map(|(_, f)| f.attrs.bits.unwrap()).sum();
+    total_bits.div_ceil(8) as usize
+}
+
+// generates an expression for the sum of the sizes of the fields, with
+// `single` giving the size of a field which is not bit-packed
+fn size_sum(fields: &[FieldCode], single: impl Fn(&FieldCode) -> TokenStream) -> TokenStream {
+    let sizes = groups(fields).into_iter().map(|g| match g {
+        FieldGroup::Single(_, f) => single(f),
+        FieldGroup::Bits(packed) => {
+            let bytes = packed_bytes(&packed);
+            quote! { #bytes }
+        }
+    });
+    quote! {
+        0 #(+ #sizes)*
+    }
+}
+
+// generates an expression for the minimum number of bytes the fields take
+pub fn min_size(fields: &[FieldCode]) -> TokenStream {
+    size_sum(fields, |f| f.min_size())
+}
+
+// generates an expression for the maximum number of bytes the fields take,
+// which is None if any one of them is unbounded
+pub fn max_size(fields: &[FieldCode]) -> TokenStream {
+    let sizes = groups(fields).into_iter().map(|g| match g {
+        FieldGroup::Single(_, f) => f.max_size(),
+        FieldGroup::Bits(packed) => {
+            let bytes = packed_bytes(&packed);
+            quote! { core::option::Option::Some(#bytes) }
+        }
+    });
+    quote! {
+        bytecode::size::max_size_sum(&[#(#sizes),*])
+    }
+}
+
+// generates an expression for the number of bytes the values of the fields take
+pub fn encoded_len(fields: &[FieldCode]) -> TokenStream {
+    size_sum(fields, |f| {
+        let access = &f.access;
+        f.encoded_len_of(quote! { &#access }, false)
+    })
+}
+
+// generates an expression for the size of the fields, when all of them are of fixed size
+pub fn fixed_size(fields: &[FieldCode]) -> TokenStream {
+    size_sum(fields, |f| {
+        let ty = &f.field.ty;
+        quote! { <#ty as bytecode::FixedSize>::SIZE }
+    })
+}
+
+// generates the statements which compile the fields in order,
+// and write the compiled bytes into the `out` sink
+pub fn compile_fields(fields: &[FieldCode], out: &Ident) -> TokenStream {
+    let compiled = groups(fields).into_iter().map(|g| match g {
+        // the calls are fully qualified, so that any type expression can be used
+        // and inherent methods of the field type with same name do not interfere
+        FieldGroup::Single(_, f) => {
+            let compile = f.compile_call(out);
+            quote! {
+                #compile?;
+            }
+        }
+        FieldGroup::Bits(packed) => {
+            let writes = packed.iter().map(|(i, f)| {
+                let ty = &f.field.ty;
+                let access = &f.access;
+                let bits = f.attrs.bits.unwrap();
+                let msg = format!(
+                    "value of field `{}` does not fit in {} bits",
+                    f.name(*i),
+                    bits
+                );
+                quote! {
+                    _bits.write(
+                        <#ty as bytecode::BitPackable>::to_bits(&#access, #bits)
+                            .map_err(|_| bytecode::BytecodeError::new(bytecode::ErrorKind::Other(#msg)))?,
+                        #bits,
+                    )?;
+                }
+            });
+            quote! {
+                {
+                    let mut _bits = bytecode::BitWriter::new(&mut *#out);
+                    #(#writes)*
+                    _bits.finish()?;
+                }
+            }
+        }
+    });
+    quote! {
+        #(#compiled)*
+    }
+}
+
+// generates the statements which read the fields of a bit-packed group from the
+// BitReader `_bits` over the bytes of the group in `_group`
+fn bit_reads(packed: &[(usize, &FieldCode)], count: &Ident, path: &str) -> TokenStream {
+    let reads = packed.iter().map(|(i, f)| {
+        let ty = &f.field.ty;
+        let var = &f.var;
+        let bits = f.attrs.bits.unwrap();
+        let segment = format!("{}.{}", path, f.name(*i));
+        // the bytes are read before converting, so if the value is not valid,
+        // the bytes of the group are available to be reported
+        quote! {
+            let #var = _bits.read(#bits)
+                .and_then(|v| <#ty as bytecode::BitPackable>::from_bits(v, #bits).map_err(|e| {
+                    e.with_bytes(_group)
+                }))
+                .map_err(|e| e.offset_by(#count).within(#segment))?;
+        }
+    });
+    quote! {
+        let mut _bits = bytecode::BitReader::new(_group);
+        #(#reads)*
+    }
+}
+
+// generates the statements which parse the fields in order from `param` starting at
+// `count`, into their variables. `count` is incremented by the number of bytes used.
+// Errors are located at the offset of the field, and `path` followed by the field name
+// is added to their path, so for enums `path` should include the variant
+pub fn parse_fields(fields: &[FieldCode], param: &Ident, count: &Ident, path: &str) -> TokenStream {
+    let parsed = groups(fields).into_iter().map(|g| match g {
+        FieldGroup::Single(i, f) => {
+            let var = &f.var;
+            let segment = format!("{}.{}", path, f.name(i));
+            let parse = f.parse_call(quote! { &#param[#count..] });
+            quote! {
+                let (#var,size) = #parse
+                    .map_err(|e| e.offset_by(#count).within(#segment))?;
+                #count += size;
+            }
+        }
+        FieldGroup::Bits(packed) => {
+            let total_bytes = packed_bytes(&packed);
+            // the size of the group is known, so if the bytes are not enough, all the bytes
+            // needed are reported for the first field of the group, same as in read_fields
+            let (first, f) = packed[0];
+            let segment = format!("{}.{}", path, f.name(first));
+            let reads = bit_reads(&packed, count, path);
+            quote! {
+                let _group = &#param[#count..#param.len().min(#count + #total_bytes)];
+                if _group.len() < #total_bytes {
+                    return Err(bytecode::BytecodeError::incomplete(#total_bytes - _group.len())
+                        .offset_by(#count)
+                        .within(#segment));
+                }
+                #reads
+                #count += #total_bytes;
+            }
+        }
+    });
+    quote! {
+        #(#parsed)*
+    }
+}
+
+// same as parse_fields, but reads the fields from `reader`, which is a `&mut impl Read`.
+// `count` is used to track the offset of the fields for errors
+pub fn read_fields(fields: &[FieldCode], reader: &Ident, count: &Ident, path: &str) -> TokenStream {
+    let read = groups(fields).into_iter().map(|g| match g {
+        FieldGroup::Single(i, f) => {
+            let var = &f.var;
+            let segment = format!("{}.{}", path, f.name(i));
+            let read = f.read_call(reader);
+            let len = f.encoded_len_of(quote! { &#var }, true);
+            quote! {
+                let #var = #read
+                    .map_err(|e| e.offset_by(#count).within(#segment))?;
+                #count += #len;
+            }
+        }
+        FieldGroup::Bits(packed) => {
+            let total_bytes = packed_bytes(&packed);
+            // the bytes of the group are read together, so if they are not available
+            // the error is reported for the first field of the group
+            let (first, f) = packed[0];
+            let segment = format!("{}.{}", path, f.name(first));
+            let reads = bit_reads(&packed, count, path);
+            quote! {
+                let mut _group = [0_u8; #total_bytes];
+                bytecode::read_bytes(&mut *#reader, &mut _group)
+                    .map_err(|e| e.offset_by(#count).within(#segment))?;
+                let _group = &_group[..];
+                #reads
+                #count += #total_bytes;
+            }
+        }
+    });
+    quote! {
+        #(#read)*
+    }
+}
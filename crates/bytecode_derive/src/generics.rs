@@ -0,0 +1,125 @@
+use crate::attr;
+use crate::fields;
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::punctuated::Punctuated;
+use syn::{parse_quote, Token};
+
+// parses the value of #[bytecode(bound = "T: Trait, U: Other")], which replaces
+// the bounds inferred for the generic parameters of the type
+pub fn parse_bound(arg: &attr::BytecodeArg) -> syn::Result<Vec<syn::WherePredicate>> {
+    let expr = arg.expr()?;
+    let bound = attr::expr_to_string(expr)?;
+    let predicates = syn::parse::Parser::parse_str(
+        Punctuated::<syn::WherePredicate, Token![,]>::parse_terminated,
+        &bound,
+    )
+    .map_err(|e| syn::Error::new_spanned(expr, e))?;
+    Ok(predicates.into_iter().collect())
+}
+
+// checks if the given ident is used anywhere in the tokens
+fn mentions(tokens: TokenStream, ident: &syn::Ident) -> bool {
+    tokens.into_iter().any(|t| match t {
+        TokenTree::Ident(i) => &i == ident,
+        TokenTree::Group(g) => mentions(g.stream(), ident),
+        _ => false,
+    })
+}
+
+// checks if any lifetime is used anywhere in the tokens
+pub fn mentions_lifetime(tokens: TokenStream) -> bool {
+    tokens.into_iter().any(|t| match t {
+        TokenTree::Punct(p) => p.as_char() == '\'',
+        TokenTree::Group(g) => mentions_lifetime(g.stream()),
+        _ => false,
+    })
+}
+
+// Returns the generics of the type with bounds needed by the generated FixedSize impl,
+// which are same as the ones for Bytecodable, except the type parameters used in
+// fields which are not bit-packed need to be of fixed size as well
+pub fn fixed_size_generics<'f, 'a: 'f>(
+    generics: &syn::Generics,
+    fields: impl Iterator<Item = &'f fields::FieldCode<'a>> + Clone,
+    bound: Option<&[syn::WherePredicate]>,
+) -> syn::Generics {
+    let mut ret = bounded_generics(generics, fields.clone(), bound);
+    if bound.is_some() {
+        return ret;
+    }
+    let predicates = generics.type_params().filter_map(|param| {
+        let ident = &param.ident;
+        fields
+            .clone()
+            .any(|f| f.attrs.bits.is_none() && mentions(f.field.ty.to_token_stream(), ident))
+            .then(|| -> syn::WherePredicate { parse_quote!(#ident: bytecode::FixedSize) })
+    });
+    ret.make_where_clause().predicates.extend(predicates);
+    ret
+}
+
+// Returns the generics of the type with bounds needed by the generated impl.
+// If bounds are given explicitly, they are used as is, otherwise each type parameter used
+// in a field gets the `Bytecodable` bound, `BitPackable` bound if used in a bit-packed field,
+// `Varint` bound if used as the type of a varint field, and `Endian` bound if used
+// in a big endian field. The types of dependent fields which use any of the type parameters
+// get the `Dependent` bound, with the type of the fields they depend on
+pub fn bounded_generics<'f, 'a: 'f>(
+    generics: &syn::Generics,
+    fields: impl Iterator<Item = &'f fields::FieldCode<'a>> + Clone,
+    bound: Option<&[syn::WherePredicate]>,
+) -> syn::Generics {
+    let mut ret = generics.clone();
+    let predicates = match bound {
+        Some(bound) => bound.to_vec(),
+        None => {
+            let mut predicates = Vec::new();
+            for param in generics.type_params() {
+                let ident = &param.ident;
+                let (mut bytecodable, mut bit_packable, mut varint, mut endian) =
+                    (false, false, false, false);
+                for f in fields.clone() {
+                    // dependent fields get their own bound below
+                    if f.context.is_some() || !mentions(f.field.ty.to_token_stream(), ident) {
+                        continue;
+                    }
+                    if f.attrs.bits.is_some() {
+                        bit_packable = true;
+                    } else if f.attrs.varint {
+                        varint = true;
+                    } else {
+                        bytecodable = true;
+                        endian |= f.big_endian();
+                    }
+                }
+                if bytecodable {
+                    predicates.push(parse_quote!(#ident: bytecode::Bytecodable));
+                }
+                if bit_packable {
+                    predicates.push(parse_quote!(#ident: bytecode::BitPackable));
+                }
+                if varint {
+                    predicates.push(parse_quote!(#ident: bytecode::varint::Varint));
+                }
+                if endian {
+                    predicates.push(parse_quote!(#ident: bytecode::endian::Endian));
+                }
+            }
+            for f in fields.clone() {
+                let ty = &f.field.ty;
+                let Some(ctx) = &f.context else { continue };
+                let generic = generics
+                    .type_params()
+                    .any(|p| mentions(ty.to_token_stream(), &p.ident));
+                if generic {
+                    let cty = ctx.ty();
+                    predicates.push(parse_quote!(#ty: bytecode::Dependent<#cty>));
+                }
+            }
+            predicates
+        }
+    };
+    ret.make_where_clause().predicates.extend(predicates);
+    ret
+}
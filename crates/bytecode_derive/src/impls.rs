@@ -0,0 +1,90 @@
+use crate::fields;
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+use syn::parse_quote;
+
+// lifetime of the bytes which the types with borrowed fields are decoded from
+pub const DECODE_LIFETIME: &str = "'__de";
+
+// The parts of the generated impls, which are generated separately for enums and structs
+pub struct ImplParts {
+    // the consts and methods for compiling the value
+    pub encode: TokenStream,
+    // name of the parameter of parse method, which holds the bytes
+    pub parse_param: Ident,
+    // body of the parse method
+    pub parse_body: TokenStream,
+    // the read_from method, which is only used for owned types
+    pub read: TokenStream,
+}
+
+// Types with borrowed fields cannot implement Bytecodable, as they cannot be parsed from
+// bytes of any lifetime, so for them BytecodeEncode and BytecodeDecode are implemented instead
+pub fn codec_impls<'f, 'a: 'f>(
+    name: &syn::Ident,
+    generics: &syn::Generics,
+    mut fields: impl Iterator<Item = &'f fields::FieldCode<'a>> + Clone,
+    parts: ImplParts,
+) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let ImplParts {
+        encode,
+        parse_param,
+        parse_body,
+        read,
+    } = parts;
+
+    if !fields.clone().any(|f| f.borrowed) {
+        return quote! {
+            impl #impl_generics bytecode::Bytecodable for #name #ty_generics #where_clause{
+                #encode
+
+                fn parse(#parse_param:&[u8])->core::result::Result<(Self,usize),bytecode::BytecodeError>{
+                    #parse_body
+                }
+
+                #read
+            }
+
+            // the type has its own byte order, so it is same in big endian types
+            impl #impl_generics bytecode::endian::Endian for #name #ty_generics #where_clause{}
+        };
+    }
+
+    let de = syn::Lifetime::new(DECODE_LIFETIME, Span::call_site());
+    let mut decode_generics = generics.clone();
+    // the decoded value cannot outlive the bytes it borrows from
+    let lifetimes: Vec<_> = generics.lifetimes().map(|l| l.lifetime.clone()).collect();
+    let de_param: syn::LifetimeDef = if lifetimes.is_empty() {
+        parse_quote!(#de)
+    } else {
+        parse_quote!(#de: #(#lifetimes)+*)
+    };
+    decode_generics
+        .params
+        .insert(0, syn::GenericParam::Lifetime(de_param));
+    let predicates = fields
+        .by_ref()
+        .filter(|f| f.borrowed)
+        .map(|f| -> syn::WherePredicate {
+            let ty = &f.field.ty;
+            parse_quote!(#ty: bytecode::BytecodeDecode<#de>)
+        });
+    decode_generics
+        .make_where_clause()
+        .predicates
+        .extend(predicates);
+    let (decode_impl_generics, _, decode_where_clause) = decode_generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics bytecode::BytecodeEncode for #name #ty_generics #where_clause{
+            #encode
+        }
+
+        impl #decode_impl_generics bytecode::BytecodeDecode<#de> for #name #ty_generics #decode_where_clause{
+            fn decode(#parse_param:&#de [u8])->core::result::Result<(Self,usize),bytecode::BytecodeError>{
+                #parse_body
+            }
+        }
+    }
+}
@@ -1,13 +1,22 @@
 use crate::enum_derive::derive_enum;
 use crate::struct_derive::derive_struct;
-use proc_macro::TokenStream;
+use proc_macro2::TokenStream;
 
-// TODO add better error reporting, instead of manual panics use macro derive errors
-
-pub fn derive(input: syn::DeriveInput) -> TokenStream {
+pub fn derive(input: syn::DeriveInput) -> syn::Result<TokenStream> {
     match input.data {
-        syn::Data::Enum(ref input_enum) => derive_enum(&input.ident, input_enum),
-        syn::Data::Struct(ref input_struct) => derive_struct(&input.ident, input_struct),
-        syn::Data::Union(_) => panic!("Bytecode is not supported for Unions"),
+        syn::Data::Enum(ref input_enum) => derive_enum(
+            &input.ident,
+            &input.vis,
+            &input.attrs,
+            &input.generics,
+            input_enum,
+        ),
+        syn::Data::Struct(ref input_struct) => {
+            derive_struct(&input.ident, &input.attrs, &input.generics, input_struct)
+        }
+        syn::Data::Union(ref input_union) => Err(syn::Error::new(
+            input_union.union_token.span,
+            "Bytecode is not supported for Unions",
+        )),
     }
 }
@@ -1,12 +1,22 @@
+mod attr;
 mod enum_derive;
+mod errors;
+mod fields;
+mod generics;
+mod impls;
 mod internal;
+mod lockfile;
+mod opcode;
 mod struct_derive;
 mod util;
+mod word;
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, DeriveInput};
 
-#[proc_macro_derive(Bytecode)]
+#[proc_macro_derive(Bytecode, attributes(bytecode))]
 pub fn derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     internal::derive(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
 }
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+pub const LOCKFILE_NAME: &str = "bytecode.lock";
+
+// the lockfile is only written when this is set while building, otherwise
+// the opcodes are checked against it
+pub const UPDATE_VAR: &str = "BYTECODE_UPDATE_LOCK";
+
+const LOCKFILE_HEADER: &str = "\
+# This file is generated by the bytecode derive macro, and records the opcodes
+# assigned to enum variants, so they stay the same when the enum changes.
+# It should be checked in along with the code.
+";
+
+// opcodes recorded for a variant, from `opcode` to `last`, which are the same unless the
+// variant has an embedded field. Removed variants are kept as reserved entries
+// so that their opcodes are never assigned again
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LockEntry {
+    pub opcode: usize,
+    pub last: usize,
+    pub removed: bool,
+}
+
+impl LockEntry {
+    pub fn opcodes(&self) -> std::ops::RangeInclusive<usize> {
+        self.opcode..=self.last
+    }
+}
+
+pub type LockSection = BTreeMap<String, LockEntry>;
+
+// The lockfile is a simple text file, with one section per enum :
+//
+// [Opcode]
+// Hlt = 0x0
+// Add = 0x1
+// Sub = 0x2 removed
+// Push = 0x8..=0xf
+//
+// we keep the original text, so that we can skip writing the file when nothing changes,
+// and the original sections, to check that the file is up to date when it is not written
+pub struct Lockfile {
+    path: PathBuf,
+    sections: BTreeMap<String, LockSection>,
+    original: String,
+    original_sections: BTreeMap<String, LockSection>,
+}
+
+impl Lockfile {
+    // opens the lockfile placed next to the Cargo.toml of the crate being compiled,
+    // a missing file is treated as an empty lockfile
+    pub fn open() -> Result<Self, String> {
+        let dir = std::env::var("CARGO_MANIFEST_DIR")
+            .map_err(|_| "CARGO_MANIFEST_DIR is not set, cannot locate the lockfile".to_owned())?;
+        let path = PathBuf::from(dir).join(LOCKFILE_NAME);
+        let original = if path.exists() {
+            std::fs::read_to_string(&path)
+                .map_err(|e| format!("cannot read {} : {}", path.display(), e))?
+        } else {
+            String::new()
+        };
+        let sections = parse(&original).map_err(|e| format!("{} : {}", path.display(), e))?;
+        Ok(Lockfile {
+            path,
+            original_sections: sections.clone(),
+            sections,
+            original,
+        })
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn section(&self, key: &str) -> LockSection {
+        self.sections.get(key).cloned().unwrap_or_default()
+    }
+
+    pub fn set_section(&mut self, key: &str, section: LockSection) {
+        self.sections.insert(key.to_owned(), section);
+    }
+
+    // Writes the lockfile back, only if something has changed, so that we do not
+    // trigger unnecessary rebuilds. Unless the update is enabled with UPDATE_VAR,
+    // the file is never written, and a change is an error instead
+    pub fn save(&self) -> Result<(), String> {
+        if std::env::var_os(UPDATE_VAR).is_none() {
+            if self.sections == self.original_sections {
+                return Ok(());
+            }
+            return Err(format!(
+                "{} is missing or out of date, build with {}=1 to update it",
+                self.path.display(),
+                UPDATE_VAR
+            ));
+        }
+        let rendered = render(&self.sections);
+        if rendered == self.original {
+            return Ok(());
+        }
+        std::fs::write(&self.path, rendered)
+            .map_err(|e| format!("cannot write {} : {}", self.path.display(), e))
+    }
+}
+
+fn parse(content: &str) -> Result<BTreeMap<String, LockSection>, String> {
+    let mut sections = BTreeMap::new();
+    let mut current: Option<&mut LockSection> = None;
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: &str| format!("line {} : {}", i + 1, msg);
+        if let Some(key) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(sections.entry(key.trim().to_owned()).or_default());
+            continue;
+        }
+        let section = current
+            .as_mut()
+            .ok_or_else(|| err("entry is not in any [section]"))?;
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| err("expected `Variant = opcode`"))?;
+        let mut value = value.split_whitespace();
+        let (opcode, last) = value
+            .next()
+            .and_then(parse_range)
+            .ok_or_else(|| err("invalid opcode value"))?;
+        let removed = match value.next() {
+            None => false,
+            Some("removed") => true,
+            Some(_) => return Err(err("unexpected value after the opcode")),
+        };
+        let entry = LockEntry {
+            opcode,
+            last,
+            removed,
+        };
+        section.insert(name.trim().to_owned(), entry);
+    }
+    Ok(sections)
+}
+
+// an opcode, or the range of opcodes of a variant with an embedded field
+fn parse_range(s: &str) -> Option<(usize, usize)> {
+    match s.split_once("..=") {
+        Some((first, last)) => {
+            let (first, last) = (parse_number(first)?, parse_number(last)?);
+            (first <= last).then_some((first, last))
+        }
+        None => parse_number(s).map(|v| (v, v)),
+    }
+}
+
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn render(sections: &BTreeMap<String, LockSection>) -> String {
+    let mut ret = String::from(LOCKFILE_HEADER);
+    for (key, section) in sections.iter() {
+        ret.push_str(&format!("\n[{}]\n", key));
+        // entries are written in order of opcodes, as that is easier to read
+        let mut entries: Vec<_> = section.iter().collect();
+        entries.sort_by_key(|(_, e)| e.opcode);
+        for (name, entry) in entries {
+            ret.push_str(&format!("{} = {:#x}", name, entry.opcode));
+            if entry.last != entry.opcode {
+                ret.push_str(&format!("..={:#x}", entry.last));
+            }
+            if entry.removed {
+                ret.push_str(" removed");
+            }
+            ret.push('\n');
+        }
+    }
+    ret
+}
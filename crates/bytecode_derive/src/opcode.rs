@@ -0,0 +1,553 @@
+use crate::attr;
+use crate::errors::Errors;
+use crate::fields;
+use crate::lockfile::{LockEntry, LockSection, LOCKFILE_NAME};
+use proc_macro2::Span;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use syn::spanned::Spanned;
+
+// largest number of bits a field embedded in the opcode can take
+const MAX_EMBED_BITS: usize = 8;
+
+// an enum variant along with the opcode value assigned to it
+pub struct VariantOpcode<'a> {
+    pub variant: &'a syn::Variant,
+    // position of the variant in the enum
+    pub index: usize,
+    pub opcode: usize,
+    // other opcodes which are parsed as this variant, but never compiled
+    pub aliases: Vec<usize>,
+    // the prefix which comes before the opcode, if the variant is in a prefix group
+    pub prefix: Option<usize>,
+    // the field which is stored in the lower bits of the opcode, if any
+    pub embed: Option<Embed>,
+}
+
+// A field of the variant which is stored in the lower `bits` bits of the opcode, given as
+// #[bytecode(embed(field = 0, bits = 3))]. The variant takes all the opcodes from its
+// opcode, which has those bits clear, up to the one with all of them set
+#[derive(Clone, Copy)]
+pub struct Embed {
+    // position of the field in the variant
+    pub field: usize,
+    pub bits: u32,
+}
+
+impl Embed {
+    // the bits of the opcode which hold the field
+    pub fn mask(&self) -> usize {
+        (1 << self.bits) - 1
+    }
+}
+
+// options which can be given on a variant using #[bytecode(...)]
+#[derive(Default)]
+pub struct VariantAttrs {
+    // the explicit opcode, along with the span to which errors about the value should point
+    pub opcode: Option<(usize, Span)>,
+    // the alias opcodes given by #[bytecode(alias = [..])], with their spans
+    pub aliases: Vec<(usize, Span)>,
+    // the prefix of the group the variant is in, with its span
+    pub prefix: Option<(usize, Span)>,
+    // if the variant holds the opcodes which are not assigned to any other variant
+    pub unknown: Option<Span>,
+    // if the variant is deprecated, along with the note given for it
+    pub deprecated: Option<Option<String>>,
+    // the field embedded in the opcode, with the span of the attribute
+    pub embed: Option<(Embed, Span)>,
+    // the bits of the word which are compared with the opcode, for enums with `word`
+    pub mask: Option<(usize, Span)>,
+}
+
+pub fn parse_variant_attrs(variant: &syn::Variant) -> syn::Result<VariantAttrs> {
+    let mut ret = VariantAttrs::default();
+    let mut errors = Errors::default();
+    for arg in attr::parse_attrs(&variant.attrs)? {
+        match arg.key().as_str() {
+            "opcode" => {
+                ret.opcode = errors.check(
+                    arg.expr()
+                        .and_then(|expr| Ok((attr::expr_to_usize(expr)?, expr.span()))),
+                );
+            }
+            "mask" => {
+                ret.mask = errors.check(
+                    arg.expr()
+                        .and_then(|expr| Ok((attr::expr_to_usize(expr)?, expr.span()))),
+                );
+            }
+            "prefix" => {
+                ret.prefix = errors.check(
+                    arg.expr()
+                        .and_then(|expr| Ok((attr::expr_to_usize(expr)?, expr.span()))),
+                );
+            }
+            "alias" => {
+                if let Some(aliases) = errors.check(arg.expr().and_then(parse_aliases)) {
+                    ret.aliases = aliases;
+                }
+            }
+            "unknown" => {
+                if errors.check(arg.expect_flag()).is_some() {
+                    ret.unknown = Some(arg.span());
+                }
+            }
+            // given as a flag, or with a note as #[bytecode(deprecated = "...")]
+            "deprecated" => {
+                ret.deprecated = match &arg.value {
+                    attr::ArgValue::Flag => Some(None),
+                    _ => errors
+                        .check(arg.expr().and_then(attr::expr_to_string))
+                        .map(Some),
+                };
+            }
+            "embed" => {
+                ret.embed = errors
+                    .check(parse_embed(variant, &arg))
+                    .map(|e| (e, arg.span()));
+            }
+            _ => errors.push(arg.unknown()),
+        }
+    }
+    errors.finish()?;
+    if let Some(span) = ret.unknown {
+        if ret.opcode.is_some()
+            || !ret.aliases.is_empty()
+            || ret.prefix.is_some()
+            || ret.embed.is_some()
+            || variant.discriminant.is_some()
+        {
+            return Err(syn::Error::new(
+                span,
+                "the unknown variant cannot have an opcode",
+            ));
+        }
+    }
+    if let Some((_, span)) = ret.embed {
+        // the opcodes of the variant depend on the field, so they cannot be numbered
+        // automatically, and there is a single range of them
+        if ret.opcode.is_none() && variant.discriminant.is_none() {
+            return Err(syn::Error::new(
+                span,
+                "variants with an embedded field need an explicit opcode",
+            ));
+        }
+        if !ret.aliases.is_empty() {
+            return Err(syn::Error::new(
+                span,
+                "variants with an embedded field cannot have aliases",
+            ));
+        }
+    }
+    Ok(ret)
+}
+
+// parses the arguments of `embed(field = 0, bits = 3)`, where the field is
+// given by its position, or by its name for named fields
+fn parse_embed(variant: &syn::Variant, embed: &attr::BytecodeArg) -> syn::Result<Embed> {
+    let mut errors = Errors::default();
+    let (mut field, mut bits) = (None, None);
+    for arg in embed.list()? {
+        match arg.key().as_str() {
+            "field" => field = errors.check(arg.expr().and_then(|e| embed_field(variant, e))),
+            "bits" => {
+                bits = errors.check(arg.expr().and_then(|expr| {
+                    let bits = attr::expr_to_usize(expr)?;
+                    if bits == 0 || bits > MAX_EMBED_BITS {
+                        return Err(syn::Error::new(
+                            expr.span(),
+                            format!("bit width must be between 1 and {}", MAX_EMBED_BITS),
+                        ));
+                    }
+                    Ok(bits as u32)
+                }))
+            }
+            _ => errors.push(arg.unknown()),
+        }
+    }
+    errors.finish()?;
+    match (field, bits) {
+        (Some(field), Some(bits)) => Ok(Embed { field, bits }),
+        _ => Err(syn::Error::new(
+            embed.span(),
+            "expected `embed(field = .., bits = ..)`",
+        )),
+    }
+}
+
+// finds the position of the field given in `embed(field = ..)`. The field
+// is compiled into the opcode, so it cannot have attributes of its own
+fn embed_field(variant: &syn::Variant, expr: &syn::Expr) -> syn::Result<usize> {
+    let index = fields::field_position(&variant.fields, expr)?;
+    let field = variant.fields.iter().nth(index).unwrap();
+    if let Some(a) = field.attrs.iter().find(|a| a.path.is_ident("bytecode")) {
+        return Err(syn::Error::new_spanned(
+            a,
+            "the embedded field cannot have bytecode attributes",
+        ));
+    }
+    Ok(index)
+}
+
+// parses the value of `reserve = ..`, which is a range of opcodes, a single opcode,
+// or a list of these, such as `reserve = [0x10..0x20, 0xFF]`
+pub fn parse_reserve(expr: &syn::Expr) -> syn::Result<Vec<RangeInclusive<usize>>> {
+    let ranges = attr::expr_to_ranges(expr, "the reserved range is empty")?;
+    Ok(ranges.into_iter().map(|(r, _)| r).collect())
+}
+
+// parses the value of `alias = [..]`, which can also be a single opcode
+fn parse_aliases(expr: &syn::Expr) -> syn::Result<Vec<(usize, Span)>> {
+    let elems: Vec<_> = match expr {
+        syn::Expr::Array(a) => a.elems.iter().collect(),
+        _ => vec![expr],
+    };
+    elems
+        .into_iter()
+        .map(|e| Ok((attr::expr_to_usize(e)?, e.span())))
+        .collect()
+}
+
+// the distinct prefixes given on the variants, in numerical order
+pub fn prefixes(input_enum: &syn::DataEnum) -> Vec<usize> {
+    let mut ret: Vec<_> = input_enum
+        .variants
+        .iter()
+        .filter_map(|v| parse_variant_attrs(v).ok()?.prefix)
+        .map(|(p, _)| p)
+        .collect();
+    ret.sort_unstable();
+    ret.dedup();
+    ret
+}
+
+// This assigns opcode values to each of the enum variants.
+// Variants can be given an explicit opcode either by #[bytecode(opcode = N)]
+// or by the usual rust discriminant `Variant = N`, and the variants which follow
+// a discriminant take the next values, same as their discriminants. Rest of the variants
+// are numbered automatically, in the order of declaration, by taking the smallest
+// value which is not already assigned explicitly.
+//
+// `max_opcode` is the largest value the tag of the enum can store, and the
+// `reserved` opcodes are never assigned, neither explicitly nor automatically.
+//
+// The variants with #[bytecode(prefix = P)] are assigned separately for each prefix,
+// as their opcodes follow the prefix. `prefix` gives the group to assign, and the
+// variants without a prefix are the group `None`, in which the prefixes cannot be used.
+//
+// If a lockfile section is given, variants without an explicit opcode keep the
+// opcode recorded in it, and opcodes of variants which are no longer in the group
+// are kept reserved, along with the whole range of those which had an embedded field.
+// The section is then updated with the final assignment.
+pub fn assign_opcodes<'a>(
+    input_enum: &'a syn::DataEnum,
+    lock: Option<&mut LockSection>,
+    max_opcode: usize,
+    reserved: &[RangeInclusive<usize>],
+    prefix: Option<usize>,
+) -> syn::Result<Vec<VariantOpcode<'a>>> {
+    let is_reserved = |v: usize| reserved.iter().any(|r| r.contains(&v));
+    let mut errors = Errors::default();
+    let mut explicit = Vec::with_capacity(input_enum.variants.len());
+    // BTreeMap so that the values are in numerical order, which makes finding gaps easy
+    let mut used: BTreeMap<usize, String> = BTreeMap::new();
+    let implicit = implicit_discriminants(input_enum);
+
+    // the unknown variant does not have an opcode of its own, so it is left out here.
+    // Variants with errors in the attributes are kept in the group without a prefix,
+    // so that the errors are reported once
+    let variants: Vec<_> = input_enum
+        .variants
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| match parse_variant_attrs(v) {
+            Ok(a) => a.unknown.is_none() && a.prefix.map(|(p, _)| p) == prefix,
+            Err(_) => prefix.is_none(),
+        })
+        .collect();
+
+    // the prefixes are opcodes of the group without a prefix, so they are checked here
+    let mut prefixes: BTreeMap<usize, Span> = BTreeMap::new();
+    if prefix.is_none() {
+        for v in input_enum.variants.iter() {
+            if let Ok(VariantAttrs {
+                prefix: Some((p, span)),
+                ..
+            }) = parse_variant_attrs(v)
+            {
+                prefixes.entry(p).or_insert(span);
+            }
+        }
+    }
+    for (&p, &span) in prefixes.iter() {
+        if p > max_opcode {
+            errors.push(syn::Error::new(
+                span,
+                format!(
+                    "prefix {:#x} is too large, at max {:#x} is supported",
+                    p, max_opcode
+                ),
+            ));
+        } else if is_reserved(p) {
+            errors.push(syn::Error::new(
+                span,
+                format!("prefix {:#x} is reserved", p),
+            ));
+        }
+    }
+
+    // the aliases of each variant, which are checked along with the explicit opcodes
+    let mut aliases = Vec::with_capacity(variants.len());
+    let mut embeds = Vec::with_capacity(variants.len());
+    for &(i, variant) in variants.iter() {
+        let attrs = errors
+            .check(parse_variant_attrs(variant))
+            .unwrap_or_default();
+        let opcode = errors
+            .check(explicit_opcode(variant, attrs.opcode, implicit[i]))
+            .flatten();
+        if let Some((_, span)) = attrs.mask {
+            errors.push(syn::Error::new(
+                span,
+                "`mask` can only be given for variants of enums with `word`",
+            ));
+        }
+        let alias = attrs.aliases;
+        let embed = attrs.embed.map(|(e, _)| e);
+        // a variant with an embedded field takes all the opcodes in its range, of which
+        // only the first error is reported. The opcode has the lower bits clear, so
+        // the range fits in the tag if the opcode does, as max_opcode is all ones
+        let mut range = Vec::new();
+        match (opcode, embed) {
+            (Some((value, span)), Some(e)) if value & e.mask() != 0 => {
+                errors.push(syn::Error::new(
+                    span,
+                    format!(
+                        "opcode {:#x} has some of the lower {} bits set, which hold the embedded field",
+                        value, e.bits
+                    ),
+                ));
+            }
+            (Some((value, span)), Some(e)) => {
+                range.extend((value..=value | e.mask()).map(|v| (v, span)));
+            }
+            _ => range.extend(opcode),
+        }
+        for (value, span) in range.iter().chain(alias.iter()).copied() {
+            if value > max_opcode {
+                errors.push(syn::Error::new(
+                    span,
+                    format!(
+                        "opcode {:#x} is too large, at max {:#x} is supported",
+                        value, max_opcode
+                    ),
+                ));
+            } else if is_reserved(value) {
+                errors.push(syn::Error::new(
+                    span,
+                    format!("opcode {:#x} is reserved", value),
+                ));
+            } else if prefixes.contains_key(&value) {
+                errors.push(syn::Error::new(
+                    span,
+                    format!("opcode {:#x} is used as a prefix", value),
+                ));
+            } else if let Some(other) = used.get(&value) {
+                errors.push(syn::Error::new(
+                    span,
+                    format!(
+                        "opcode {:#x} is already assigned to variant `{}`",
+                        value, other
+                    ),
+                ));
+            } else {
+                used.insert(value, variant.ident.to_string());
+                continue;
+            }
+            if embed.is_some() {
+                break;
+            }
+        }
+        explicit.push(opcode.map(|(v, _)| v));
+        aliases.push(alias.into_iter().map(|(v, _)| v).collect::<Vec<_>>());
+        embeds.push(embed);
+    }
+
+    // opcodes of removed variants, which must not be assigned to anything else
+    let mut removed: BTreeMap<usize, String> = BTreeMap::new();
+    let mut removed_entries = Vec::new();
+    if let Some(lock) = &lock {
+        // a variant which moved to another prefix group is removed from this one,
+        // as bytecode compiled before the move still has its old opcode
+        for (name, entry) in lock.iter() {
+            if variants.iter().any(|(_, v)| v.ident == name) {
+                continue;
+            }
+            // only the first conflict of the range is reported
+            let conflict = entry.opcodes().find_map(|v| used.get(&v).map(|o| (v, o)));
+            if let Some((opcode, variant)) = conflict {
+                let variant = input_enum
+                    .variants
+                    .iter()
+                    .find(|v| v.ident == variant)
+                    .unwrap();
+                errors.push(syn::Error::new(
+                    variant.ident.span(),
+                    format!(
+                        "opcode {:#x} is reserved in {} for the removed variant `{}`",
+                        opcode, LOCKFILE_NAME, name
+                    ),
+                ));
+            }
+            removed.extend(entry.opcodes().map(|v| (v, name.clone())));
+            let entry = LockEntry {
+                removed: true,
+                ..*entry
+            };
+            removed_entries.push((name.clone(), entry));
+        }
+        // variants already in the lockfile keep their opcode
+        for ((_, variant), opcode) in variants.iter().zip(explicit.iter_mut()) {
+            let entry = match (&opcode, lock.get(&variant.ident.to_string())) {
+                (None, Some(entry)) => entry,
+                _ => continue,
+            };
+            let other = used
+                .get(&entry.opcode)
+                .or_else(|| removed.get(&entry.opcode));
+            if let Some(other) = other {
+                errors.push(syn::Error::new(
+                    variant.ident.span(),
+                    format!(
+                        "opcode {:#x} recorded for this variant in {} is already assigned to `{}`",
+                        entry.opcode, LOCKFILE_NAME, other
+                    ),
+                ));
+                continue;
+            }
+            if is_reserved(entry.opcode) || prefixes.contains_key(&entry.opcode) {
+                errors.push(syn::Error::new(
+                    variant.ident.span(),
+                    format!(
+                        "opcode {:#x} recorded for this variant in {} is reserved",
+                        entry.opcode, LOCKFILE_NAME
+                    ),
+                ));
+                continue;
+            }
+            used.insert(entry.opcode, variant.ident.to_string());
+            *opcode = Some(entry.opcode);
+        }
+    }
+
+    // now we fill the gaps with rest of the variants
+    let mut next = 0;
+    let mut ret = Vec::with_capacity(explicit.len());
+    let variants = variants.into_iter().zip(explicit).zip(aliases).zip(embeds);
+    for ((((index, variant), opcode), aliases), embed) in variants {
+        let opcode = match opcode {
+            Some(v) => v,
+            None => {
+                loop {
+                    if let Some(r) = reserved.iter().find(|r| r.contains(&next)) {
+                        next = r.end() + 1;
+                    } else if used.contains_key(&next)
+                        || removed.contains_key(&next)
+                        || prefixes.contains_key(&next)
+                    {
+                        next += 1;
+                    } else {
+                        break;
+                    }
+                }
+                if next > max_opcode {
+                    errors.push(syn::Error::new(
+                        variant.ident.span(),
+                        "no opcode values left to assign to this variant",
+                    ));
+                    continue;
+                }
+                used.insert(next, variant.ident.to_string());
+                next
+            }
+        };
+        ret.push(VariantOpcode {
+            variant,
+            index,
+            opcode,
+            aliases,
+            prefix,
+            embed,
+        });
+    }
+    // we do not update the lockfile in case of errors,
+    // as the assignment might not be complete
+    errors.finish()?;
+
+    if let Some(lock) = lock {
+        lock.clear();
+        for v in ret.iter() {
+            let entry = LockEntry {
+                opcode: v.opcode,
+                last: v.opcode | v.embed.map_or(0, |e| e.mask()),
+                removed: false,
+            };
+            lock.insert(v.variant.ident.to_string(), entry);
+        }
+        lock.extend(removed_entries);
+    }
+    Ok(ret)
+}
+
+// The discriminants rustc gives the variants without one, when they follow a variant
+// with an explicit discriminant, which is the previous value plus one. The variants
+// before the first discriminant do not have one here, and are numbered automatically
+pub fn implicit_discriminants(input_enum: &syn::DataEnum) -> Vec<Option<usize>> {
+    let mut prev = None;
+    input_enum
+        .variants
+        .iter()
+        .map(|v| match &v.discriminant {
+            Some((_, d)) => {
+                // errors in the value are reported with the explicit opcodes
+                prev = attr::expr_to_usize(d).ok();
+                None
+            }
+            None => {
+                prev = prev.map(|p| p + 1);
+                prev
+            }
+        })
+        .collect()
+}
+
+// gets the explicitly specified opcode of the variant, if any, along with
+// the span to which errors about the value should point. `opcode` is the
+// one given in the attributes, which must match the discriminant, and `implicit`
+// is the discriminant the variant gets from the ones before it, if any
+pub fn explicit_opcode(
+    variant: &syn::Variant,
+    mut opcode: Option<(usize, Span)>,
+    implicit: Option<usize>,
+) -> syn::Result<Option<(usize, Span)>> {
+    let discriminant = match &variant.discriminant {
+        Some((_, d)) => Some((attr::expr_to_usize(d)?, d.span())),
+        None => implicit.map(|v| (v, variant.ident.span())),
+    };
+    if let Some((value, discriminant_span)) = discriminant {
+        match opcode {
+            Some((v, span)) if v != value => {
+                return Err(syn::Error::new(
+                    span,
+                    format!(
+                        "opcode {:#x} conflicts with the discriminant {:#x} of this variant",
+                        v, value
+                    ),
+                ));
+            }
+            _ => opcode = Some((value, discriminant_span)),
+        }
+    }
+    Ok(opcode)
+}
@@ -1,112 +1,172 @@
-use proc_macro::TokenStream;
+use crate::attr;
+use crate::errors::Errors;
+use crate::fields;
+use crate::generics;
+use crate::impls;
 use proc_macro2::Ident;
 use quote::quote;
 
-pub fn derive_struct(name: &syn::Ident, input_struct: &syn::DataStruct) -> TokenStream {
-    let fields = &input_struct.fields;
-    match fields {
-        syn::Fields::Unit => panic!("Bytecode is not supported for Unit type structs"),
-        syn::Fields::Unnamed(unnamed) => derive_unnamed(name, unnamed),
-        syn::Fields::Named(named) => derive_named(name, named),
-    }
+// options which can be given on the struct itself using #[bytecode(...)]
+#[derive(Default)]
+struct StructAttrs {
+    // bounds given explicitly for the generic parameters
+    bound: Option<Vec<syn::WherePredicate>>,
+    // whether to implement FixedSize for the struct
+    fixed_size: bool,
+    // byte order of the fields which do not give their own
+    endian: Option<fields::ByteOrder>,
 }
 
-fn derive_unnamed(
-    struct_name: &syn::Ident,
-    fields: &syn::FieldsUnnamed,
-) -> proc_macro::TokenStream {
-    let fields = &fields.unnamed;
-    let parse_fn_param_name = syn::Ident::new("__bytes", struct_name.span());
-
-    let field_list = fields
-        .iter()
-        .enumerate()
-        .map(|(i, _)| Ident::new(&format!("v{}", i), struct_name.span()));
-    let fields_compiled = fields.iter().enumerate().map(|(i, _)| {
-        let idx = syn::Index::from(i);
-        quote! {
-            self.#idx.compile()
+fn parse_struct_attrs(attrs: &[syn::Attribute]) -> syn::Result<StructAttrs> {
+    let mut ret = StructAttrs::default();
+    let mut errors = Errors::default();
+    for arg in attr::parse_attrs(attrs)? {
+        match arg.key().as_str() {
+            "bound" => ret.bound = errors.check(generics::parse_bound(&arg)),
+            "fixed_size" => ret.fixed_size = errors.check(arg.expect_flag()).is_some(),
+            "endian" => ret.endian = errors.check(fields::parse_endian(&arg)),
+            _ => errors.push(arg.unknown()),
         }
-    });
+    }
+    errors.finish()?;
+    Ok(ret)
+}
 
-    let compiled = quote! {
-        let mut _i = std::vec::Vec::new();
-        #(_i.extend(&#fields_compiled);)*
-        return _i;
+pub fn derive_struct(
+    name: &syn::Ident,
+    attrs: &[syn::Attribute],
+    generics: &syn::Generics,
+    input_struct: &syn::DataStruct,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut errors = Errors::default();
+    let struct_attrs = errors.check(parse_struct_attrs(attrs));
+    let fields = &input_struct.fields;
+    let field_list = match fields {
+        syn::Fields::Unit => {
+            errors.push(syn::Error::new(
+                name.span(),
+                "Bytecode is not supported for Unit type structs",
+            ));
+            None
+        }
+        syn::Fields::Unnamed(_) => errors.check(fields::field_list(
+            fields,
+            |_, i| {
+                let idx = syn::Index::from(i);
+                quote! {
+                    self.#idx
+                }
+            },
+            |_, i| Ident::new(&format!("v{}", i), name.span()),
+        )),
+        syn::Fields::Named(_) => errors.check(fields::field_list(
+            fields,
+            |f, _| {
+                let ident = &f.ident;
+                quote! {
+                    self.#ident
+                }
+            },
+            |f, _| f.ident.clone().unwrap(),
+        )),
     };
+    errors.finish()?;
+    let (struct_attrs, mut field_list) = (struct_attrs.unwrap(), field_list.unwrap());
+    fields::apply_endian(&mut field_list, struct_attrs.endian);
 
-    let size_counter_var = syn::Ident::new("_count", struct_name.span());
-
-    let parsed = fields.iter().zip(field_list.clone()).map(|(f, v)| {
-        quote! {
-            let (#v,size) = #f::parse(&#parse_fn_param_name[#size_counter_var..])?;
-            #size_counter_var += size;
-        }
-    });
+    let bound = struct_attrs.bound.as_deref();
+    let fixed_size = if struct_attrs.fixed_size {
+        fields::check_fixed_size(name, field_list.iter())?;
+        let generics = generics::fixed_size_generics(generics, field_list.iter(), bound);
+        derive_fixed_size(name, &generics, &field_list)
+    } else {
+        quote! {}
+    };
+    let generics = generics::bounded_generics(generics, field_list.iter(), bound);
 
-    let field_list_bracketed = quote! {
-        (#(#field_list),*)
+    // the expression which constructs the struct from the variables of the fields
+    let vars = field_list.iter().map(|f| &f.var);
+    let constructor = match fields {
+        syn::Fields::Unnamed(_) => quote! { Self(#(#vars),*) },
+        _ => quote! { Self{#(#vars),*} },
     };
+    let parts = struct_parts(name, &field_list, &constructor);
+    let impls = impls::codec_impls(name, &generics, field_list.iter(), parts);
+    Ok(quote! {
+        #impls
+        #fixed_size
+    })
+}
 
-    let output = quote! {
-        impl bytecode::Bytecodable for #struct_name{
-            fn compile(&self)->Vec<u8>{
-                #compiled
-            }
+// generates the size consts and encoded_len method of the Bytecodable impl
+fn size_items(fields: &[fields::FieldCode]) -> proc_macro2::TokenStream {
+    let min_size = fields::min_size(fields);
+    let max_size = fields::max_size(fields);
+    let encoded_len = fields::encoded_len(fields);
+    quote! {
+        const MIN_SIZE: usize = #min_size;
+        const MAX_SIZE: core::option::Option<usize> = #max_size;
 
-            fn parse(#parse_fn_param_name:&[u8])->std::result::Result<(#struct_name,usize),bytecode::BytecodeError>{
-                let mut #size_counter_var = 0;
-                #(#parsed)*
-                std::result::Result::Ok((Self #field_list_bracketed,#size_counter_var))
-            }
+        fn encoded_len(&self) -> usize {
+            #encoded_len
         }
-    };
-    output.into()
+    }
 }
 
-fn derive_named(struct_name: &Ident, fields: &syn::FieldsNamed) -> TokenStream {
-    let fields = &fields.named;
-    let fields_compiled = fields.iter().map(|f| {
-        let ident = &f.ident;
-        quote! {
-            self.#ident.compile()
+fn derive_fixed_size(
+    struct_name: &syn::Ident,
+    generics: &syn::Generics,
+    fields: &[fields::FieldCode],
+) -> proc_macro2::TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let size = fields::fixed_size(fields);
+    quote! {
+        impl #impl_generics bytecode::FixedSize for #struct_name #ty_generics #where_clause{
+            const SIZE: usize = #size;
         }
-    });
+    }
+}
 
-    let compiled = quote! {
-        let mut _i = std::vec::Vec::new();
-        #(_i.extend(&#fields_compiled);)*
-        return _i;
-    };
-    let size_counter_var = syn::Ident::new("_count", struct_name.span());
-    let parse_fn_param_name = syn::Ident::new("__bytes", struct_name.span());
+fn struct_parts(
+    struct_name: &syn::Ident,
+    fields: &[fields::FieldCode],
+    constructor: &proc_macro2::TokenStream,
+) -> impls::ImplParts {
+    let out = Ident::new("__out", struct_name.span());
+    let compiled = fields::compile_fields(fields, &out);
+    let sizes = size_items(fields);
 
-    let parsed = fields.iter().map(|f| {
-        let ident = f.ident.as_ref().unwrap();
-        let ty = &f.ty;
-        quote! {
-            let (#ident,size) = #ty::parse(&#parse_fn_param_name[#size_counter_var..])?;
-            #size_counter_var += size;
-        }
-    });
+    let size_counter_var = Ident::new("_count", struct_name.span());
+    let parse_fn_param_name = Ident::new("__bytes", struct_name.span());
+    let path = struct_name.to_string();
+    let parsed = fields::parse_fields(fields, &parse_fn_param_name, &size_counter_var, &path);
+    let reader_name = Ident::new("__reader", struct_name.span());
+    let read = fields::read_fields(fields, &reader_name, &size_counter_var, &path);
 
-    let field_name_list = fields.iter().map(|f| f.ident.as_ref().unwrap());
+    impls::ImplParts {
+        encode: quote! {
+            #sizes
 
-    let output = quote! {
-        impl bytecode::Bytecodable for #struct_name{
-            fn compile(&self)->Vec<u8>{
+            fn compile_into<__S: bytecode::BytecodeSink + ?Sized>(&self, #out: &mut __S)->core::result::Result<(),bytecode::BytecodeError>{
                 #compiled
+                core::result::Result::Ok(())
             }
-
-            fn parse(#parse_fn_param_name:&[u8])->std::result::Result<(#struct_name,usize),bytecode::BytecodeError>{
-                let mut #size_counter_var = 0;
-                #(#parsed)*
-                let _t = Self{
-                    #(#field_name_list),*
-                };
-                std::result::Result::Ok((_t,#size_counter_var))
+        },
+        parse_body: quote! {
+            let mut #size_counter_var = 0;
+            #parsed
+            core::result::Result::Ok((#constructor,#size_counter_var))
+        },
+        parse_param: parse_fn_param_name,
+        read: quote! {
+            // reading needs std::io, so this is only implemented when it is available
+            bytecode::__std_only! {
+                fn read_from<__R: bytecode::io::Read + ?Sized>(#reader_name: &mut __R)->core::result::Result<Self,bytecode::BytecodeError>{
+                    let mut #size_counter_var = 0;
+                    #read
+                    core::result::Result::Ok(#constructor)
+                }
             }
-        }
-    };
-    output.into()
+        },
+    }
 }
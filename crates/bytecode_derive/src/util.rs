@@ -1,28 +1,310 @@
-use quote::__private::TokenStream;
+use crate::attr;
+use crate::fields::ByteOrder;
+use proc_macro2::{Ident, TokenStream};
 use quote::quote;
+use syn::spanned::Spanned;
 
-pub fn compile_instr(v: usize) -> TokenStream {
-    if v < 1 << 7 {
-        // if the iter number is less than 1<<7, we can use it as it is
-        let v = v as u8;
+// How the opcode of a variant is stored in the bytecode,
+// which is given on the enum as #[bytecode(tag = "u16_be")]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tag {
+    // opcodes less than 1<<7 take a single byte, larger ones take two bytes,
+    // with the MSB of the first byte set to indicate the second byte
+    #[default]
+    Compact,
+    // the opcode always takes this many bytes, if the byte order is not given
+    // explicitly, the byte order of the enum is used
+    Fixed(usize, Option<ByteOrder>),
+    // the opcode is stored in LEB128, same as varint fields
+    Varint,
+}
+
+// parses the value of `tag = "..."`
+pub fn parse_tag(arg: &attr::BytecodeArg) -> syn::Result<Tag> {
+    let expr = arg.expr()?;
+    let tag = match attr::expr_to_string(expr)?.as_str() {
+        "compact" => Tag::Compact,
+        "u8" => Tag::Fixed(1, None),
+        "u16" => Tag::Fixed(2, None),
+        "u16_le" => Tag::Fixed(2, Some(ByteOrder::Little)),
+        "u16_be" => Tag::Fixed(2, Some(ByteOrder::Big)),
+        "u32" => Tag::Fixed(4, None),
+        "u32_le" => Tag::Fixed(4, Some(ByteOrder::Little)),
+        "u32_be" => Tag::Fixed(4, Some(ByteOrder::Big)),
+        "varint" => Tag::Varint,
+        _ => {
+            return Err(syn::Error::new(
+                expr.span(),
+                "expected one of \"compact\", \"u8\", \"u16\", \"u16_le\", \"u16_be\", \
+                \"u32\", \"u32_le\", \"u32_be\" or \"varint\"",
+            ))
+        }
+    };
+    Ok(tag)
+}
+
+impl Tag {
+    // the largest opcode which can be stored with this tag
+    pub fn max_opcode(&self) -> usize {
+        match self {
+            // 1 bit is reserved to show the length of the opcode
+            Tag::Compact => (1 << 15) - 1,
+            Tag::Fixed(1, _) => u8::MAX as usize,
+            Tag::Fixed(2, _) => u16::MAX as usize,
+            // opcodes are matched as u32 in the generated code
+            Tag::Fixed(_, _) | Tag::Varint => u32::MAX as usize,
+        }
+    }
+
+    // the bytes of the given opcode
+    fn bytes(&self, v: usize) -> Vec<u8> {
+        match *self {
+            Tag::Compact if v < 1 << 7 => vec![v as u8],
+            // as we restrict the opcodes to < 1<<15, we can directly take higher
+            // and lower byte of u16, and set the MSB of the first byte
+            Tag::Compact => vec![((v >> 8) as u8) | 1 << 7, v as u8],
+            Tag::Fixed(n, Some(ByteOrder::Big)) => (v as u32).to_be_bytes()[4 - n..].to_vec(),
+            Tag::Fixed(n, _) => (v as u32).to_le_bytes()[..n].to_vec(),
+            Tag::Varint => {
+                let mut ret = Vec::new();
+                let mut v = v;
+                while v >= 0x80 {
+                    ret.push(v as u8 | 0x80);
+                    v >>= 7;
+                }
+                ret.push(v as u8);
+                ret
+            }
+        }
+    }
+
+    // number of bytes the given opcode takes in the bytecode
+    pub fn instr_len(&self, v: usize) -> usize {
+        self.bytes(v).len()
+    }
+
+    // the smallest and largest number of bytes any opcode takes
+    pub fn len_range(&self) -> (usize, usize) {
+        match *self {
+            Tag::Compact => (1, 2),
+            Tag::Fixed(n, _) => (n, n),
+            Tag::Varint => (1, 5),
+        }
+    }
+
+    // The methods below generate code for an opcode which is only known at runtime,
+    // given as a u32 variable, which is used for the opcode held by the unknown variant.
+    // The opcode is expected to be checked against max_opcode already.
+
+    // generates an expression for the number of bytes the opcode takes
+    pub fn dynamic_len(&self, v: &Ident) -> TokenStream {
+        match *self {
+            Tag::Compact => quote! { if #v < 1 << 7 { 1 } else { 2 } },
+            Tag::Fixed(n, _) => quote! { #n },
+            Tag::Varint => quote! { bytecode::varint::varint_len(&#v) },
+        }
+    }
+
+    // generates the code which writes the opcode into the `out` sink
+    pub fn compile_dynamic(&self, v: &Ident, out: &Ident) -> TokenStream {
+        let bytes = match *self {
+            Tag::Compact => {
+                return quote! {
+                    if #v < 1 << 7 {
+                        #out.write_bytes(&[#v as u8])?;
+                    } else {
+                        #out.write_bytes(&[(#v >> 8) as u8 | 1 << 7, #v as u8])?;
+                    }
+                }
+            }
+            Tag::Fixed(1, _) => quote! { &[#v as u8] },
+            Tag::Fixed(2, Some(ByteOrder::Big)) => quote! { &(#v as u16).to_be_bytes() },
+            Tag::Fixed(2, _) => quote! { &(#v as u16).to_le_bytes() },
+            Tag::Fixed(_, Some(ByteOrder::Big)) => quote! { &#v.to_be_bytes() },
+            Tag::Fixed(_, _) => quote! { &#v.to_le_bytes() },
+            Tag::Varint => {
+                return quote! {
+                    bytecode::varint::compile_varint(&#v, &mut *#out)?;
+                }
+            }
+        };
         quote! {
-            vec![#v]
+            #out.write_bytes(#bytes)?;
         }
+    }
+
+    // generates the code which writes the opcode into the `out` sink
+    pub fn compile_instr(&self, v: usize, out: &Ident) -> TokenStream {
+        let bytes = self.bytes(v);
+        quote! {
+            #out.write_bytes(&[#(#bytes),*])?;
+        }
+    }
+
+    // Generates the code which extracts the opcode from the byte stream into a u32 variable
+    // `opcode_var`, and the number of bytes it takes into `__opcode_len`.
+    // `path` is the name of the type, which is added to the errors
+    pub fn parse_instr(
+        &self,
+        param_name: &Ident,
+        opcode_var: &Ident,
+        max_opcode: usize,
+        path: &str,
+    ) -> TokenStream {
+        match *self {
+            Tag::Compact => parse_compact(param_name, opcode_var, max_opcode, path),
+            Tag::Fixed(n, order) => {
+                let idx = 0..n;
+                let value = match (n, order) {
+                    (1, _) => quote! { #param_name[0] as u32 },
+                    (2, Some(ByteOrder::Big)) => {
+                        quote! { u16::from_be_bytes([#(#param_name[#idx]),*]) as u32 }
+                    }
+                    (2, _) => quote! { u16::from_le_bytes([#(#param_name[#idx]),*]) as u32 },
+                    (_, Some(ByteOrder::Big)) => {
+                        quote! { u32::from_be_bytes([#(#param_name[#idx]),*]) }
+                    }
+                    (_, _) => quote! { u32::from_le_bytes([#(#param_name[#idx]),*]) },
+                };
+                quote! {
+                    if #param_name.len() < #n {
+                        return core::result::Result::Err(
+                            bytecode::BytecodeError::incomplete(#n - #param_name.len()).within(#path)
+                        );
+                    }
+                    let (#opcode_var, __opcode_len): (u32, usize) = (#value, #n);
+                }
+            }
+            // overlong encodings are rejected, so each opcode has a single valid bytecode
+            Tag::Varint => quote! {
+                let (#opcode_var, __opcode_len): (u32, usize) =
+                    bytecode::varint::parse_varint::<u32>(#param_name).map_err(|e| e.within(#path))?;
+            },
+        }
+    }
+
+    // generates the code which reads the bytes of the opcode from `reader` into
+    // a slice `opcode_bytes`, which can then be passed to the code from parse_instr.
+    // `path` is the name of the type, which is added to the errors
+    pub fn read_instr(
+        &self,
+        reader: &Ident,
+        opcode_bytes: &Ident,
+        max_opcode: usize,
+        path: &str,
+    ) -> TokenStream {
+        match *self {
+            Tag::Compact => read_compact(reader, opcode_bytes, max_opcode, path),
+            Tag::Fixed(n, _) => quote! {
+                let mut #opcode_bytes = [0_u8; #n];
+                bytecode::read_bytes(&mut *#reader, &mut #opcode_bytes).map_err(|e| e.within(#path))?;
+                let #opcode_bytes = &#opcode_bytes[..];
+            },
+            Tag::Varint => quote! {
+                let mut #opcode_bytes = [0_u8; bytecode::varint::MAX_VARINT_LEN];
+                let __opcode_len = bytecode::varint::read_varint_bytes(&mut *#reader, &mut #opcode_bytes)
+                    .map_err(|e| e.within(#path))?;
+                let #opcode_bytes = &#opcode_bytes[..__opcode_len];
+            },
+        }
+    }
+}
+
+fn parse_compact(
+    param_name: &Ident,
+    opcode_var: &Ident,
+    max_opcode: usize,
+    path: &str,
+) -> TokenStream {
+    let empty_check = quote! {
+        if #param_name.len() < 1 {
+            return core::result::Result::Err(bytecode::BytecodeError::incomplete(1).within(#path));
+        }
+    };
+    if max_opcode < 1 << 7 {
+        // If all the opcodes are less than 1<<7, all bytecodes generated
+        // will be 1 byte length, so we have to only check the first byte
+        return quote! {
+            #empty_check
+            let (#opcode_var, __opcode_len): (u32, usize) = (#param_name[0] as u32, 1);
+        };
+    }
+    // If the 0th byte of bytestream is less than 1<<7, then it is the complete opcode,
+    // else there must be at least two bytes, and the u16 formed by both of them,
+    // excluding the length bit, is the opcode
+    quote! {
+        #empty_check
+        let (#opcode_var, __opcode_len): (u32, usize) = if #param_name[0] < 1 << 7 {
+            (#param_name[0] as u32, 1)
+        } else {
+            if #param_name.len() < 2 {
+                return core::result::Result::Err(bytecode::BytecodeError::incomplete(1).within(#path));
+            }
+            let higher_byte: u8 = #param_name[0] & !(1 << 7);
+            let lower_byte: u8 = #param_name[1];
+            let instr: u32 = (higher_byte as u32) << 8 | lower_byte as u32;
+            // opcodes less than 1<<7 are always single byte, so their
+            // two byte form is not a valid instruction
+            if instr < 1 << 7 {
+                return core::result::Result::Err(
+                    bytecode::BytecodeError::invalid(&#param_name[..2]).within(#path)
+                );
+            }
+            (instr, 2)
+        };
+    }
+}
+
+fn read_compact(
+    reader: &Ident,
+    opcode_bytes: &Ident,
+    max_opcode: usize,
+    path: &str,
+) -> TokenStream {
+    let second_byte = if max_opcode < 1 << 7 {
+        // all the opcodes are single byte
+        quote! { 1 }
     } else {
-        // else we need to split is in two u8s
-        // as we restrict number of variants to << 15, we can directly take
-        // higher byte and lower byte of u16
-        // just need to set the MSB for the first byte to indicate that this is
-        // a 2 byte spanning instruction
-        let [higher_byte, lower_byte] = split_into_instr_bytes(v);
+        // the second byte is needed only if the first one has the MSB set
         quote! {
-            vec![#higher_byte , #lower_byte]
+            if #opcode_bytes[0] < 1 << 7 {
+                1
+            } else {
+                bytecode::read_bytes(&mut *#reader, &mut #opcode_bytes[1..])
+                    .map_err(|e| e.within(#path))?;
+                2
+            }
         }
+    };
+    quote! {
+        let mut #opcode_bytes = [0_u8; 2];
+        bytecode::read_bytes(&mut *#reader, &mut #opcode_bytes[..1]).map_err(|e| e.within(#path))?;
+        let __opcode_len: usize = #second_byte;
+        let #opcode_bytes = &#opcode_bytes[..__opcode_len];
     }
 }
 
-pub fn split_into_instr_bytes(v: usize) -> [u8; 2] {
-    let higher_byte = ((v >> 8) as u8) | 1 << 7;
-    let lower_byte = v as u8 & u8::MAX;
-    [higher_byte, lower_byte]
+// converts the name of a variant into a function name, such as `MovImm` into `mov_imm`.
+// Keywords are made into raw identifiers, except the ones which cannot be raw
+pub fn snake_case(ident: &Ident) -> Ident {
+    let name = ident.to_string();
+    let mut ret = String::with_capacity(name.len() + 4);
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if prev_lower {
+                ret.push('_');
+            }
+            ret.extend(c.to_lowercase());
+            prev_lower = false;
+        } else {
+            ret.push(c);
+            prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        }
+    }
+    match ret.as_str() {
+        "self" | "super" | "crate" => Ident::new(&format!("{}_", ret), ident.span()),
+        _ if syn::parse_str::<Ident>(&ret).is_err() => Ident::new_raw(&ret, ident.span()),
+        _ => Ident::new(&ret, ident.span()),
+    }
 }
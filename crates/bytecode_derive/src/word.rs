@@ -0,0 +1,546 @@
+use crate::attr;
+use crate::enum_derive;
+use crate::errors::Errors;
+use crate::fields::{self, ByteOrder};
+use crate::generics;
+use crate::impls;
+use crate::opcode;
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{quote, ToTokens};
+use std::ops::RangeInclusive;
+use syn::spanned::Spanned;
+
+// largest number of bits a field of a word can take, as BitPackable works with u64
+const MAX_FIELD_BITS: u32 = 64;
+
+// The layout of an enum given as #[bytecode(word = u32)], in which every instruction
+// is a single word of fixed width, instead of an opcode followed by the fields.
+// Each variant is told apart by some bits of the word, and its fields are stored
+// in the other bits, at the positions given on them
+#[derive(Clone, Copy)]
+pub struct WordLayout {
+    // number of bytes in the word
+    pub bytes: usize,
+    pub order: ByteOrder,
+}
+
+// parses the value of `word = u32`
+pub fn parse_word(arg: &attr::BytecodeArg) -> syn::Result<usize> {
+    let expr = arg.expr()?;
+    let ty = match expr {
+        syn::Expr::Path(p) => p.path.get_ident().map(|i| i.to_string()),
+        _ => None,
+    };
+    match ty.as_deref() {
+        Some("u8") => Ok(1),
+        Some("u16") => Ok(2),
+        Some("u32") => Ok(4),
+        Some("u64") => Ok(8),
+        _ => Err(syn::Error::new(
+            expr.span(),
+            "expected one of `u8`, `u16`, `u32` or `u64`",
+        )),
+    }
+}
+
+impl WordLayout {
+    fn bits(&self) -> u32 {
+        self.bytes as u32 * 8
+    }
+
+    // mask with all the bits of the word set
+    fn mask(&self) -> u64 {
+        mask(self.bits())
+    }
+
+    // the unsigned integer type of the word
+    fn ty(&self) -> Ident {
+        Ident::new(&format!("u{}", self.bits()), Span::call_site())
+    }
+
+    // generates an expression for the word in the u64 variable `word`, as bytes
+    fn encode(&self, word: &Ident) -> TokenStream {
+        let ty = self.ty();
+        match self.order {
+            ByteOrder::Big => quote! { (#word as #ty).to_be_bytes() },
+            ByteOrder::Little => quote! { (#word as #ty).to_le_bytes() },
+        }
+    }
+
+    // generates an expression for the word as u64, from the `bytes`
+    // slice which holds at least as many bytes as the word
+    fn decode(&self, bytes: &Ident) -> TokenStream {
+        let ty = self.ty();
+        let idx = 0..self.bytes;
+        match self.order {
+            ByteOrder::Big => quote! { #ty::from_be_bytes([#(#bytes[#idx]),*]) as u64 },
+            ByteOrder::Little => quote! { #ty::from_le_bytes([#(#bytes[#idx]),*]) as u64 },
+        }
+    }
+}
+
+// mask of lower `bits` bits, taking care of the shift overflow for 64 bits
+fn mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+// A field of a variant, stored in the bits of the word given as #[bytecode(bits = 7..=11)].
+// A value which is split over several ranges is given as a list, such as
+// `bits = [31, 7, 25..=30, 8..=11]`, in which the first range holds the most
+// significant bits of the value. `shift = N` gives the number of lower bits of
+// the value which are always zero, and so are not stored in the word
+struct FieldBits {
+    ranges: Vec<RangeInclusive<u32>>,
+    shift: u32,
+}
+
+impl FieldBits {
+    // number of bits of the value, including the ones which are not stored
+    fn width(&self) -> u32 {
+        self.shift
+            + self
+                .ranges
+                .iter()
+                .map(|r| r.end() - r.start() + 1)
+                .sum::<u32>()
+    }
+
+    // the bits of the word which hold the field
+    fn word_mask(&self) -> u64 {
+        self.ranges
+            .iter()
+            .fold(0, |m, r| m | mask(r.end() - r.start() + 1) << r.start())
+    }
+
+    // the ranges along with the position of their lowest bit in the value,
+    // starting from the least significant bits of the value
+    fn positions(&self) -> impl Iterator<Item = (&RangeInclusive<u32>, u32)> {
+        self.ranges.iter().rev().scan(self.shift, |pos, r| {
+            let at = *pos;
+            *pos += r.end() - r.start() + 1;
+            Some((r, at))
+        })
+    }
+}
+
+// a variant along with the bits which identify it, and its fields
+struct WordVariant<'a> {
+    variant: &'a syn::Variant,
+    opcode: u64,
+    // the bits of the word which are compared with the opcode
+    mask: u64,
+    fields: Vec<fields::FieldCode<'a>>,
+    // where each of the fields is stored in the word
+    bits: Vec<FieldBits>,
+}
+
+impl WordVariant<'_> {
+    fn fields(&self) -> impl Iterator<Item = (usize, &fields::FieldCode<'_>, &FieldBits)> {
+        self.fields
+            .iter()
+            .zip(self.bits.iter())
+            .enumerate()
+            .map(|(i, (f, b))| (i, f, b))
+    }
+}
+
+// the options which are given on a field of a word enum
+fn parse_word_field<'a>(
+    layout: WordLayout,
+    field: &'a syn::Field,
+    var: Ident,
+) -> syn::Result<(fields::FieldCode<'a>, FieldBits)> {
+    let mut errors = Errors::default();
+    let (mut ranges, mut shift) = (None, 0);
+    for arg in attr::parse_attrs(&field.attrs)? {
+        match arg.key().as_str() {
+            "bits" => ranges = errors.check(arg.expr().and_then(|e| parse_bits(layout, e))),
+            "shift" => {
+                shift = errors
+                    .check(arg.expr().and_then(attr::expr_to_usize))
+                    .unwrap_or_default() as u32
+            }
+            "len" | "max_len" | "varint" | "endian" | "depends" => errors.push(syn::Error::new(
+                arg.span(),
+                format!(
+                    "`{}` is not supported for fields of enums with `word`",
+                    arg.key()
+                ),
+            )),
+            _ => errors.push(arg.unknown()),
+        }
+    }
+    errors.finish()?;
+    let ranges = ranges.ok_or_else(|| {
+        syn::Error::new_spanned(
+            field,
+            "fields of enums with `word` need the bits they are stored in, given as #[bytecode(bits = ...)]",
+        )
+    })?;
+    let bits = FieldBits { ranges, shift };
+    let width = bits.width();
+    if width > MAX_FIELD_BITS {
+        return Err(syn::Error::new_spanned(
+            field,
+            format!(
+                "the field takes {} bits, at max {} are supported",
+                width, MAX_FIELD_BITS
+            ),
+        ));
+    }
+    // the field is converted the same way as bit-packed fields, so it gets the same bounds
+    let code = fields::FieldCode {
+        field,
+        attrs: fields::FieldAttrs {
+            bits: Some(width),
+            ..Default::default()
+        },
+        access: var.to_token_stream(),
+        var,
+        borrowed: false,
+        embedded: false,
+        context: None,
+    };
+    Ok((code, bits))
+}
+
+// parses the value of `bits = ..`, which is a range of bits, a single bit,
+// or a list of these, with the bits numbered from the least significant one
+fn parse_bits(layout: WordLayout, expr: &syn::Expr) -> syn::Result<Vec<RangeInclusive<u32>>> {
+    let ranges = attr::expr_to_ranges(expr, "the range of bits is empty")?;
+    let mut ret: Vec<RangeInclusive<u32>> = Vec::with_capacity(ranges.len());
+    for (range, e) in ranges {
+        if *range.end() >= layout.bits() as usize {
+            return Err(syn::Error::new(
+                e.span(),
+                format!("the word only has {} bits", layout.bits()),
+            ));
+        }
+        let range = *range.start() as u32..=*range.end() as u32;
+        if ret
+            .iter()
+            .any(|r| r.start() <= range.end() && range.start() <= r.end())
+        {
+            return Err(syn::Error::new(
+                e.span(),
+                "the range overlaps with another range of the field",
+            ));
+        }
+        ret.push(range);
+    }
+    Ok(ret)
+}
+
+// the opcode and the fields of a variant, checking that they fit in the word.
+// `implicit` is the discriminant the variant gets from the ones before it, if any
+fn parse_word_variant(
+    layout: WordLayout,
+    variant: &syn::Variant,
+    implicit: Option<usize>,
+) -> syn::Result<WordVariant<'_>> {
+    let mut errors = Errors::default();
+    let attrs = opcode::parse_variant_attrs(variant)?;
+    let unsupported = [
+        ("alias", attrs.aliases.first().map(|(_, span)| *span)),
+        ("prefix", attrs.prefix.map(|(_, span)| span)),
+        ("unknown", attrs.unknown),
+        ("embed", attrs.embed.map(|(_, span)| span)),
+    ];
+    for (key, span) in unsupported {
+        if let Some(span) = span {
+            errors.push(syn::Error::new(
+                span,
+                format!("`{}` is not supported for enums with `word`", key),
+            ));
+        }
+    }
+
+    let var = |f: &syn::Field, i: usize| match &f.ident {
+        Some(ident) => ident.clone(),
+        None => Ident::new(&format!("v{}", i), variant.ident.span()),
+    };
+    let mut fields = Vec::with_capacity(variant.fields.len());
+    let mut bits = Vec::with_capacity(variant.fields.len());
+    // the bits used by the fields so far
+    let mut used = 0;
+    for (i, f) in variant.fields.iter().enumerate() {
+        let (code, field_bits) = match errors.check(parse_word_field(layout, f, var(f, i))) {
+            Some(field) => field,
+            None => continue,
+        };
+        if field_bits.word_mask() & used != 0 {
+            errors.push(syn::Error::new_spanned(
+                f,
+                "the bits of this field overlap with another field",
+            ));
+        }
+        used |= field_bits.word_mask();
+        fields.push(code);
+        bits.push(field_bits);
+    }
+
+    let opcode = errors.check(opcode::explicit_opcode(variant, attrs.opcode, implicit));
+    errors.finish()?;
+    let (opcode, opcode_span) = opcode.flatten().ok_or_else(|| {
+        syn::Error::new(
+            variant.ident.span(),
+            "variants of enums with `word` need an explicit opcode",
+        )
+    })?;
+    // by default, all the bits which do not hold a field are compared with the opcode
+    let (mask, mask_span) = match attrs.mask {
+        Some((mask, span)) => (mask as u64, span),
+        None => (layout.mask() & !used, opcode_span),
+    };
+    let opcode = opcode as u64;
+    for (value, span) in [(opcode, opcode_span), (mask, mask_span)] {
+        if value & !layout.mask() != 0 {
+            return Err(syn::Error::new(
+                span,
+                format!(
+                    "{:#x} does not fit in the {} bit word",
+                    value,
+                    layout.bits()
+                ),
+            ));
+        }
+    }
+    if mask & used != 0 {
+        return Err(syn::Error::new(
+            mask_span,
+            format!(
+                "mask {:#x} overlaps with the bits of the fields {:#x}",
+                mask, used
+            ),
+        ));
+    }
+    if opcode & !mask != 0 {
+        return Err(syn::Error::new(
+            opcode_span,
+            format!(
+                "opcode {:#x} has bits set outside of the mask {:#x}",
+                opcode, mask
+            ),
+        ));
+    }
+    Ok(WordVariant {
+        variant,
+        opcode,
+        mask,
+        fields,
+        bits,
+    })
+}
+
+// Derives the impls for an enum given with #[bytecode(word = ...)], in which each
+// variant is compiled into a single word, and parsed by comparing the bits in its
+// mask with its opcode
+pub fn derive_word_enum(
+    name: &syn::Ident,
+    vis: &syn::Visibility,
+    generics: &syn::Generics,
+    input_enum: &syn::DataEnum,
+    layout: WordLayout,
+    bound: Option<&[syn::WherePredicate]>,
+    assembler: bool,
+) -> syn::Result<TokenStream> {
+    let mut errors = Errors::default();
+    let implicit = opcode::implicit_discriminants(input_enum);
+    let variants: Vec<_> = input_enum
+        .variants
+        .iter()
+        .zip(implicit)
+        .filter_map(|(v, implicit)| errors.check(parse_word_variant(layout, v, implicit)))
+        .collect();
+    // a word which matches more than one variant could not be parsed
+    for (i, v) in variants.iter().enumerate() {
+        let other = variants[..i]
+            .iter()
+            .find(|o| (o.opcode ^ v.opcode) & o.mask & v.mask == 0);
+        if let Some(other) = other {
+            errors.push(syn::Error::new(
+                v.variant.ident.span(),
+                format!(
+                    "the opcode of this variant cannot be told apart from variant `{}`",
+                    other.variant.ident
+                ),
+            ));
+        }
+    }
+    errors.finish()?;
+
+    let all_fields = || variants.iter().flat_map(|v| v.fields.iter());
+    let generics_ref = generics;
+    let generics = generics::bounded_generics(generics, all_fields(), bound);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let size = layout.bytes;
+    let path = name.to_string();
+
+    let out = Ident::new("__out", name.span());
+    let word = Ident::new("__word", name.span());
+    let value = Ident::new("__v", name.span());
+    let compiled = variants.iter().map(|v| {
+        let pattern = enum_derive::variant_pattern(name, v.variant, &v.fields);
+        let opcode = v.opcode;
+        if v.fields.is_empty() {
+            return quote! { #pattern => #opcode };
+        }
+        let placed = v.fields().map(|(i, f, bits)| {
+            let (ty, access, width) = (&f.field.ty, &f.access, bits.width());
+            let msg = format!(
+                "value of field `{}` does not fit in {} bits",
+                f.name(i),
+                width
+            );
+            let error = quote! {
+                bytecode::BytecodeError::new(bytecode::ErrorKind::Other(#msg))
+            };
+            let low_bits = mask(bits.shift);
+            let shift_check = (bits.shift > 0).then(|| {
+                let msg = format!(
+                    "value of field `{}` has some of the lower {} bits set",
+                    f.name(i),
+                    bits.shift
+                );
+                quote! {
+                    if #value & #low_bits != 0 {
+                        return core::result::Result::Err(
+                            bytecode::BytecodeError::new(bytecode::ErrorKind::Other(#msg))
+                        );
+                    }
+                }
+            });
+            let parts = bits.positions().map(|(r, at)| {
+                let (start, m) = (*r.start(), mask(r.end() - r.start() + 1));
+                quote! { #word |= ((#value >> #at) & #m) << #start; }
+            });
+            quote! {
+                let #value: u64 = <#ty as bytecode::BitPackable>::to_bits(&#access, #width)
+                    .map_err(|_| #error)?;
+                #shift_check
+                #(#parts)*
+            }
+        });
+        quote! {
+            #pattern => {
+                let mut #word: u64 = #opcode;
+                #(#placed)*
+                #word
+            }
+        }
+    });
+    let to_bytes = layout.encode(&word);
+
+    let parse_param = Ident::new("__bytes", name.span());
+    let parse_word = layout.decode(&parse_param);
+    let word_bytes = quote! { &#parse_param[..#size] };
+    let parsed = decode_word(name, &variants, &word, &word_bytes, |pattern| {
+        quote! { (#pattern, #size) }
+    });
+    let reader = Ident::new("__reader", name.span());
+    let read = decode_word(name, &variants, &word, &word_bytes, |pattern| pattern);
+
+    let parts = impls::ImplParts {
+        encode: quote! {
+            const MIN_SIZE: usize = #size;
+            const MAX_SIZE: core::option::Option<usize> = core::option::Option::Some(#size);
+
+            fn encoded_len(&self) -> usize {
+                #size
+            }
+
+            fn compile_into<__S: bytecode::BytecodeSink + ?Sized>(&self, #out: &mut __S)->core::result::Result<(),bytecode::BytecodeError>{
+                let #word: u64 = match self {
+                    #(#compiled),*
+                };
+                #out.write_bytes(&#to_bytes)?;
+                core::result::Result::Ok(())
+            }
+        },
+        parse_body: quote! {
+            if #parse_param.len() < #size {
+                return core::result::Result::Err(
+                    bytecode::BytecodeError::incomplete(#size - #parse_param.len()).within(#path)
+                );
+            }
+            let #word: u64 = #parse_word;
+            #parsed
+        },
+        parse_param: parse_param.clone(),
+        read: quote! {
+            // reading needs std::io, so this is only implemented when it is available
+            bytecode::__std_only! {
+                fn read_from<__R: bytecode::io::Read + ?Sized>(#reader: &mut __R)->core::result::Result<Self,bytecode::BytecodeError>{
+                    let mut #parse_param = [0_u8; #size];
+                    bytecode::read_bytes(&mut *#reader, &mut #parse_param).map_err(|e| e.within(#path))?;
+                    let #word: u64 = #parse_word;
+                    #read
+                }
+            }
+        },
+    };
+    let impls = impls::codec_impls(name, &generics, all_fields(), parts);
+
+    let assembler = if assembler {
+        enum_derive::derive_assembler(name, vis, generics_ref, input_enum, |v| {
+            let v = variants.iter().find(|w| w.variant.ident == v.ident)?;
+            Some(format!("{:#x}", v.opcode))
+        })?
+    } else {
+        quote! {}
+    };
+
+    // every word takes the same number of bytes, irrespective of the variant
+    Ok(quote! {
+        #impls
+
+        impl #impl_generics bytecode::FixedSize for #name #ty_generics #where_clause{
+            const SIZE: usize = #size;
+        }
+
+        #assembler
+    })
+}
+
+// Generates the code which finds the variant of the word in the u64 variable `word`,
+// and extracts its fields. `bytes` holds the bytes of the word, which are reported
+// in the errors, and `ok_value` wraps the constructed variant
+fn decode_word(
+    name: &syn::Ident,
+    variants: &[WordVariant],
+    word: &Ident,
+    bytes: &TokenStream,
+    ok_value: impl Fn(TokenStream) -> TokenStream,
+) -> TokenStream {
+    let path = name.to_string();
+    let arms = variants.iter().map(|v| {
+        let (opcode, opcode_mask) = (v.opcode, v.mask);
+        let extracted = v.fields().map(|(i, f, bits)| {
+            let (ty, var, width) = (&f.field.ty, &f.var, bits.width());
+            let segment = format!("{}::{}.{}", name, v.variant.ident, f.name(i));
+            let parts = bits.positions().map(|(r, at)| {
+                let (start, m) = (*r.start(), mask(r.end() - r.start() + 1));
+                quote! { | ((#word >> #start) & #m) << #at }
+            });
+            quote! {
+                let #var = <#ty as bytecode::BitPackable>::from_bits(0 #(#parts)*, #width)
+                    .map_err(|e| e.with_bytes(#bytes).within(#segment))?;
+            }
+        });
+        let value = ok_value(enum_derive::variant_pattern(name, v.variant, &v.fields));
+        quote! {
+            if #word & #opcode_mask == #opcode {
+                #(#extracted)*
+                return core::result::Result::Ok(#value);
+            }
+        }
+    });
+    quote! {
+        #(#arms)*
+        core::result::Result::Err(bytecode::BytecodeError::invalid(#bytes).within(#path))
+    }
+}
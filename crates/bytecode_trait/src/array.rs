@@ -0,0 +1,59 @@
+use crate::{Bytecodable, BytecodeError, BytecodeSink, FixedSize};
+#[cfg(feature = "std")]
+use std::io::Read;
+
+// Builds an array by calling the function for each element in order, stopping at the first
+// error. The elements after the failed one are never produced, so nothing is dropped twice.
+pub(crate) fn try_from_fn<T, const N: usize>(
+    mut f: impl FnMut() -> Result<T, BytecodeError>,
+) -> Result<[T; N], BytecodeError> {
+    let mut err = None;
+    let values: [Option<T>; N] = core::array::from_fn(|_| {
+        if err.is_some() {
+            return None;
+        }
+        match f() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                err = Some(e);
+                None
+            }
+        }
+    });
+    match err {
+        Some(e) => Err(e),
+        None => Ok(values.map(|v| v.unwrap())),
+    }
+}
+
+// Arrays are stored as their elements one after the other, without any length, as the
+// length is a part of the type. The element type decides how they are compiled and parsed,
+// so that arrays of bytes are copied at once.
+impl<T: Bytecodable, const N: usize> Bytecodable for [T; N] {
+    const MIN_SIZE: usize = T::MIN_SIZE * N;
+    const MAX_SIZE: Option<usize> = match T::MAX_SIZE {
+        Some(s) => Some(s * N),
+        None if N == 0 => Some(0),
+        None => None,
+    };
+    fn encoded_len(&self) -> usize {
+        match T::MAX_SIZE {
+            Some(s) if s == T::MIN_SIZE => s * N,
+            _ => self.iter().map(Bytecodable::encoded_len).sum(),
+        }
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        T::compile_slice(self, out)
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        T::parse_array(bytes)
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        T::read_array(reader)
+    }
+}
+
+impl<T: FixedSize, const N: usize> FixedSize for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+}
@@ -0,0 +1,172 @@
+use crate::{BytecodeError, BytecodeSink, ErrorKind};
+use core::convert::TryFrom;
+
+/// This is implemented by types which can be packed into a given number of bits,
+/// so that multiple of them can share a single byte in the bytecode,
+/// such as fields marked with `#[bytecode(bits = N)]` in the derive macro.
+pub trait BitPackable: Sized {
+    /// This function converts the value to an unsigned value which fits in the lower `bits` bits.
+    /// It should return an error if the value cannot be represented in given number of bits.
+    fn to_bits(&self, bits: u32) -> Result<u64, BytecodeError>;
+
+    /// This function converts the lower `bits` bits of the given value back to the value.
+    fn from_bits(value: u64, bits: u32) -> Result<Self, BytecodeError>;
+}
+
+const OVERFLOW_ERROR: BytecodeError =
+    BytecodeError::new(ErrorKind::Other("value does not fit in its bit width"));
+const TYPE_OVERFLOW_ERROR: BytecodeError = BytecodeError::new(ErrorKind::Other(
+    "packed value does not fit in the field type",
+));
+
+// mask of lower `bits` bits, taking care of the shift overflow for 64 bits
+fn mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+macro_rules! impl_unsigned {
+    ($($t:ty),*) => {
+        $(
+            impl BitPackable for $t {
+                fn to_bits(&self, bits: u32) -> Result<u64, BytecodeError> {
+                    let v = *self as u64;
+                    if v & !mask(bits) != 0 {
+                        return Err(OVERFLOW_ERROR);
+                    }
+                    Ok(v)
+                }
+                fn from_bits(value: u64, bits: u32) -> Result<Self, BytecodeError> {
+                    <$t>::try_from(value & mask(bits)).map_err(|_| TYPE_OVERFLOW_ERROR)
+                }
+            }
+        )*
+    };
+}
+
+// signed values are stored as two's complement of given bit width
+macro_rules! impl_signed {
+    ($($t:ty),*) => {
+        $(
+            impl BitPackable for $t {
+                fn to_bits(&self, bits: u32) -> Result<u64, BytecodeError> {
+                    let v = *self as i64;
+                    // there is no sign bit, so only zero fits, same as for unsigned types
+                    if bits == 0 {
+                        return match v {
+                            0 => Ok(0),
+                            _ => Err(OVERFLOW_ERROR),
+                        };
+                    }
+                    if bits < 64 {
+                        let max = (1_i64 << (bits - 1)) - 1;
+                        let min = -(1_i64 << (bits - 1));
+                        if v > max || v < min {
+                            return Err(OVERFLOW_ERROR);
+                        }
+                    }
+                    Ok(v as u64 & mask(bits))
+                }
+                fn from_bits(value: u64, bits: u32) -> Result<Self, BytecodeError> {
+                    let value = value & mask(bits);
+                    // sign extend the value to 64 bits
+                    let v = if (1..64).contains(&bits) && value >> (bits - 1) != 0 {
+                        (value | !mask(bits)) as i64
+                    } else {
+                        value as i64
+                    };
+                    <$t>::try_from(v).map_err(|_| TYPE_OVERFLOW_ERROR)
+                }
+            }
+        )*
+    };
+}
+
+impl_unsigned!(u8, u16, u32, u64);
+impl_signed!(i8, i16, i32, i64);
+
+impl BitPackable for bool {
+    fn to_bits(&self, bits: u32) -> Result<u64, BytecodeError> {
+        (*self as u8).to_bits(bits)
+    }
+    fn from_bits(value: u64, bits: u32) -> Result<Self, BytecodeError> {
+        Ok(value & mask(bits) != 0)
+    }
+}
+
+/// Packs values of given bit widths one after another into the sink, starting from the
+/// most significant bit of the first byte. Each byte is written as soon as it is complete.
+#[derive(Debug)]
+pub struct BitWriter<'s, S: BytecodeSink + ?Sized> {
+    sink: &'s mut S,
+    // the partially filled byte
+    current: u8,
+    // number of bits filled in the current byte
+    len: u32,
+}
+
+impl<'s, S: BytecodeSink + ?Sized> BitWriter<'s, S> {
+    pub fn new(sink: &'s mut S) -> Self {
+        BitWriter {
+            sink,
+            current: 0,
+            len: 0,
+        }
+    }
+
+    /// Writes the lower `bits` bits of the value
+    pub fn write(&mut self, value: u64, bits: u32) -> Result<(), BytecodeError> {
+        for i in (0..bits).rev() {
+            let bit = ((value >> i) & 1) as u8;
+            self.current |= bit << (7 - self.len);
+            self.len += 1;
+            if self.len == 8 {
+                self.sink.write_bytes(&[self.current])?;
+                self.current = 0;
+                self.len = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the last partially filled byte, if any. Its unused bits are set to 0
+    pub fn finish(self) -> Result<(), BytecodeError> {
+        if self.len != 0 {
+            self.sink.write_bytes(&[self.current])?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads values of given bit widths one after another, in the same order as `BitWriter` writes them.
+#[derive(Debug)]
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    // number of bits read so far
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        BitReader { bytes, pos: 0 }
+    }
+
+    /// Reads the next `bits` bits as the lower bits of the returned value
+    pub fn read(&mut self, bits: u32) -> Result<u64, BytecodeError> {
+        let end = self.pos + bits as usize;
+        if end > self.bytes.len() * 8 {
+            let needed = end.div_ceil(8) - self.bytes.len();
+            return Err(BytecodeError::incomplete(needed).offset_by(self.pos / 8));
+        }
+        let mut value = 0;
+        for _ in 0..bits {
+            let bit = (self.bytes[self.pos / 8] >> (7 - self.pos % 8)) & 1;
+            value = value << 1 | bit as u64;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,128 @@
+use crate::leb128::{leb128_len, read_leb128, write_leb128};
+use crate::{size, BytecodeError, BytecodeSink, ErrorKind};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+/// This is the compiling half of `Bytecodable`, for types which borrow their data
+/// from the bytecode, such as `&[u8]` and `&str`, and thus cannot be parsed into owned values.
+/// These are parsed using `BytecodeDecode` instead.
+/// The derive macro implements this, instead of `Bytecodable`, for the types with borrowed fields.
+pub trait BytecodeEncode {
+    /// The minimum number of bytes the bytecode of any value of this type takes.
+    const MIN_SIZE: usize = 0;
+
+    /// The maximum number of bytes the bytecode of any value of this type takes,
+    /// None if there is no upper bound.
+    const MAX_SIZE: Option<usize> = None;
+
+    /// This function returns the number of bytes the bytecode of this value takes,
+    /// which is same as the length of `compile` output.
+    fn encoded_len(&self) -> usize {
+        let mut counter = size::SizeCounter::default();
+        let _ = self.compile_into(&mut counter);
+        counter.0
+    }
+
+    /// This function compiles the value to its bytecode representation,
+    /// and writes it into the given sink, without any intermediate allocations.
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError>;
+
+    /// This function compiles the value to its bytecode representation.
+    /// This panics if the value cannot be compiled, use `compile_into` to handle such errors.
+    #[cfg(feature = "alloc")]
+    fn compile(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        if let Err(e) = self.compile_into(&mut ret) {
+            panic!("cannot compile the value : {}", e);
+        }
+        ret
+    }
+
+    /// This function compiles the value and writes the bytecode into the writer.
+    #[cfg(feature = "std")]
+    fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> Result<(), BytecodeError> {
+        self.compile_into(&mut crate::IoSink::new(writer))
+    }
+}
+
+/// This is implemented by types which can be parsed from bytecode which lives for `'de`,
+/// and can borrow their data from it, so parsing them does not need to copy the data.
+pub trait BytecodeDecode<'de>: Sized {
+    /// This function parses the value from the bytes, and returns a result :
+    /// Ok with the parsed value and how many bytes were consumed to parse it
+    /// Err with the BytecodeError
+    fn decode(bytes: &'de [u8]) -> Result<(Self, usize), BytecodeError>;
+}
+
+impl<T: BytecodeEncode + ?Sized> BytecodeEncode for &T {
+    const MIN_SIZE: usize = T::MIN_SIZE;
+    const MAX_SIZE: Option<usize> = T::MAX_SIZE;
+
+    fn encoded_len(&self) -> usize {
+        (**self).encoded_len()
+    }
+
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        (**self).compile_into(out)
+    }
+}
+
+// byte slices and strings are stored as their length, followed by the bytes.
+// The length is LEB128 encoded, so that short ones only need a single extra byte
+
+impl BytecodeEncode for [u8] {
+    const MIN_SIZE: usize = 1;
+
+    fn encoded_len(&self) -> usize {
+        leb128_len(self.len() as u64) + self.len()
+    }
+
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        write_leb128(out, self.len() as u64)?;
+        out.write_bytes(self)
+    }
+}
+
+impl BytecodeEncode for str {
+    const MIN_SIZE: usize = 1;
+
+    fn encoded_len(&self) -> usize {
+        self.as_bytes().encoded_len()
+    }
+
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        self.as_bytes().compile_into(out)
+    }
+}
+
+impl<'de: 'a, 'a> BytecodeDecode<'de> for &'a [u8] {
+    fn decode(bytes: &'de [u8]) -> Result<(Self, usize), BytecodeError> {
+        let (len, size) = read_leb128(bytes)?;
+        let available = (bytes.len() - size) as u64;
+        if len > available {
+            // the length comes from the input, so it might not fit in usize on smaller targets
+            let needed = usize::try_from(len - available).unwrap_or(usize::MAX);
+            return Err(BytecodeError::incomplete(needed));
+        }
+        let end = size + len as usize;
+        Ok((&bytes[size..end], end))
+    }
+}
+
+impl<'de: 'a, 'a> BytecodeDecode<'de> for &'a str {
+    fn decode(bytes: &'de [u8]) -> Result<(Self, usize), BytecodeError> {
+        let (data, size) = <&[u8]>::decode(bytes)?;
+        let s = core::str::from_utf8(data)
+            .map_err(|e| invalid_utf8(data, e).offset_by(size - data.len()))?;
+        Ok((s, size))
+    }
+}
+
+// error for string bytes which are not valid UTF-8, located at the first invalid byte
+pub(crate) fn invalid_utf8(data: &[u8], e: core::str::Utf8Error) -> BytecodeError {
+    BytecodeError::new(ErrorKind::Other("string is not valid UTF-8"))
+        .with_bytes(&data[e.valid_up_to()..])
+        .offset_by(e.valid_up_to())
+}
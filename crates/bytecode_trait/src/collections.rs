@@ -0,0 +1,617 @@
+use crate::borrowed::invalid_utf8;
+use crate::endian::{BigEndian, Endian};
+use crate::varint::{compile_varint, varint_len};
+use crate::{Bytecodable, BytecodeError, BytecodeSink, ErrorKind, VarU64};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+#[cfg(feature = "std")]
+use std::io::Read;
+
+// Collections are stored as the number of their elements, followed by the elements.
+// The length comes from the input, so it is checked against a maximum before it is
+// used, and the memory for the elements is only allocated as they are parsed.
+
+/// The maximum number of elements a collection can have by default,
+/// longer ones fail to compile and parse with `ErrorKind::LengthLimit`
+pub const DEFAULT_MAX_LEN: usize = 1 << 24;
+
+// number of elements which are allocated up front, the rest are
+// allocated as they are parsed, so a large length alone cannot use up the memory
+const MAX_PREALLOC: usize = 4096;
+
+/// The types which can be used to store the length of a collection
+pub trait LengthPrefix {
+    /// The minimum number of bytes the length takes
+    const MIN_SIZE: usize;
+
+    /// The maximum number of bytes the length takes
+    const MAX_SIZE: Option<usize>;
+
+    /// The number of bytes the given length takes
+    fn len_size(len: usize) -> usize;
+
+    /// Compiles the length, this fails with `ErrorKind::LengthLimit` if
+    /// the length cannot be stored in this type
+    fn compile_len<S: BytecodeSink + ?Sized>(len: usize, out: &mut S) -> Result<(), BytecodeError>;
+
+    /// Parses the length, along with the number of bytes it takes
+    fn parse_len(bytes: &[u8]) -> Result<(usize, usize), BytecodeError>;
+
+    /// Reads the length from the reader
+    #[cfg(feature = "std")]
+    fn read_len<R: Read + ?Sized>(reader: &mut R) -> Result<usize, BytecodeError>;
+}
+
+fn len_from_u64(len: u64) -> Result<usize, BytecodeError> {
+    usize::try_from(len).map_err(|_| BytecodeError::new(ErrorKind::LengthLimit))
+}
+
+// this is the default length prefix, so lengths below 128 take a single byte
+impl LengthPrefix for VarU64 {
+    const MIN_SIZE: usize = <VarU64 as Bytecodable>::MIN_SIZE;
+    const MAX_SIZE: Option<usize> = <VarU64 as Bytecodable>::MAX_SIZE;
+
+    fn len_size(len: usize) -> usize {
+        varint_len(&len)
+    }
+
+    fn compile_len<S: BytecodeSink + ?Sized>(len: usize, out: &mut S) -> Result<(), BytecodeError> {
+        compile_varint(&len, out)
+    }
+
+    fn parse_len(bytes: &[u8]) -> Result<(usize, usize), BytecodeError> {
+        let (len, size) = VarU64::parse(bytes)?;
+        Ok((len_from_u64(len.0)?, size))
+    }
+
+    #[cfg(feature = "std")]
+    fn read_len<R: Read + ?Sized>(reader: &mut R) -> Result<usize, BytecodeError> {
+        len_from_u64(VarU64::read_from(reader)?.0)
+    }
+}
+
+macro_rules! impl_length_prefix {
+    ($($t:ty),*) => {
+        $(
+            impl LengthPrefix for $t {
+                const MIN_SIZE: usize = <$t as Bytecodable>::MIN_SIZE;
+                const MAX_SIZE: Option<usize> = <$t as Bytecodable>::MAX_SIZE;
+
+                fn len_size(_: usize) -> usize {
+                    <$t as Bytecodable>::MIN_SIZE
+                }
+
+                fn compile_len<S: BytecodeSink + ?Sized>(
+                    len: usize,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    <$t>::try_from(len)
+                        .map_err(|_| BytecodeError::new(ErrorKind::LengthLimit))?
+                        .compile_into(out)
+                }
+
+                fn parse_len(bytes: &[u8]) -> Result<(usize, usize), BytecodeError> {
+                    let (len, size) = <$t>::parse(bytes)?;
+                    Ok((len_from_u64(len as u64)?, size))
+                }
+
+                #[cfg(feature = "std")]
+                fn read_len<R: Read + ?Sized>(reader: &mut R) -> Result<usize, BytecodeError> {
+                    len_from_u64(<$t>::read_from(reader)? as u64)
+                }
+            }
+        )*
+    };
+}
+
+impl_length_prefix!(u8, u16, u32, u64);
+
+// lengths stored in big endian, which the derive uses for the
+// length prefix of fields in types with `#[bytecode(endian = "big")]`
+macro_rules! impl_big_endian_length_prefix {
+    ($($t:ty),*) => {
+        $(
+            impl LengthPrefix for BigEndian<$t> {
+                const MIN_SIZE: usize = <$t as Bytecodable>::MIN_SIZE;
+                const MAX_SIZE: Option<usize> = <$t as Bytecodable>::MAX_SIZE;
+
+                fn len_size(_: usize) -> usize {
+                    <$t as Bytecodable>::MIN_SIZE
+                }
+
+                fn compile_len<S: BytecodeSink + ?Sized>(
+                    len: usize,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    <$t>::try_from(len)
+                        .map_err(|_| BytecodeError::new(ErrorKind::LengthLimit))?
+                        .compile_be(out)
+                }
+
+                fn parse_len(bytes: &[u8]) -> Result<(usize, usize), BytecodeError> {
+                    let (len, size) = <$t>::parse_be(bytes)?;
+                    Ok((len_from_u64(len as u64)?, size))
+                }
+
+                #[cfg(feature = "std")]
+                fn read_len<R: Read + ?Sized>(reader: &mut R) -> Result<usize, BytecodeError> {
+                    len_from_u64(<$t>::read_be(reader)? as u64)
+                }
+            }
+        )*
+    };
+}
+
+impl_big_endian_length_prefix!(u8, u16, u32, u64);
+
+/// Collections which are stored as the number of their elements, followed by the elements.
+/// The functions of this module compile and parse these with any `LengthPrefix`.
+pub trait LengthPrefixed: Sized {
+    /// The minimum number of bytes an element takes, which is used to reject lengths
+    /// which the bytes cannot hold before parsing the elements
+    const ELEMENT_MIN_SIZE: usize;
+
+    /// The number of elements, which is the number of bytes for strings
+    fn prefixed_len(&self) -> usize;
+
+    /// The number of bytes the elements take
+    fn elements_encoded_len(&self) -> usize;
+
+    /// Compiles the elements, without the length
+    fn compile_elements<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError>;
+
+    /// Parses given number of elements
+    fn parse_elements(bytes: &[u8], len: usize) -> Result<(Self, usize), BytecodeError>;
+
+    /// Reads given number of elements
+    #[cfg(feature = "std")]
+    fn read_elements<R: Read + ?Sized>(reader: &mut R, len: usize) -> Result<Self, BytecodeError>;
+}
+
+/// Collections whose elements can also be stored in big endian, which is used for the
+/// collections in types with `#[bytecode(endian = "big")]`. The length is not affected
+/// by these, as it is compiled by the length prefix.
+pub trait EndianElements: LengthPrefixed {
+    /// Compiles the elements in big endian, without the length
+    fn compile_elements_be<S: BytecodeSink + ?Sized>(
+        &self,
+        out: &mut S,
+    ) -> Result<(), BytecodeError>;
+
+    /// Parses given number of elements stored in big endian
+    fn parse_elements_be(bytes: &[u8], len: usize) -> Result<(Self, usize), BytecodeError>;
+
+    /// Reads given number of elements stored in big endian
+    #[cfg(feature = "std")]
+    fn read_elements_be<R: Read + ?Sized>(
+        reader: &mut R,
+        len: usize,
+    ) -> Result<Self, BytecodeError>;
+}
+
+fn check_len(len: usize, max_len: usize) -> Result<(), BytecodeError> {
+    if len > max_len {
+        return Err(BytecodeError::new(ErrorKind::LengthLimit));
+    }
+    Ok(())
+}
+
+/// The number of bytes the value takes when compiled with the length prefix `P`
+pub fn prefixed_encoded_len<P: LengthPrefix, C: LengthPrefixed>(value: &C) -> usize {
+    P::len_size(value.prefixed_len()) + value.elements_encoded_len()
+}
+
+/// Compiles the value with the length prefix `P`, which fails if it has more than `max_len` elements
+pub fn compile_prefixed<P, C, S>(
+    value: &C,
+    max_len: usize,
+    out: &mut S,
+) -> Result<(), BytecodeError>
+where
+    P: LengthPrefix,
+    C: LengthPrefixed,
+    S: BytecodeSink + ?Sized,
+{
+    check_len(value.prefixed_len(), max_len)?;
+    P::compile_len(value.prefixed_len(), out)?;
+    value.compile_elements(out)
+}
+
+/// Same as `compile_prefixed`, with the elements stored in big endian
+pub fn compile_prefixed_be<P, C, S>(
+    value: &C,
+    max_len: usize,
+    out: &mut S,
+) -> Result<(), BytecodeError>
+where
+    P: LengthPrefix,
+    C: EndianElements,
+    S: BytecodeSink + ?Sized,
+{
+    check_len(value.prefixed_len(), max_len)?;
+    P::compile_len(value.prefixed_len(), out)?;
+    value.compile_elements_be(out)
+}
+
+/// Parses the value with the length prefix `P`, which fails if it has more than `max_len` elements
+pub fn parse_prefixed<P: LengthPrefix, C: LengthPrefixed>(
+    bytes: &[u8],
+    max_len: usize,
+) -> Result<(C, usize), BytecodeError> {
+    parse_len_then::<P, C>(bytes, max_len, C::parse_elements)
+}
+
+/// Same as `parse_prefixed`, with the elements stored in big endian
+pub fn parse_prefixed_be<P: LengthPrefix, C: EndianElements>(
+    bytes: &[u8],
+    max_len: usize,
+) -> Result<(C, usize), BytecodeError> {
+    parse_len_then::<P, C>(bytes, max_len, C::parse_elements_be)
+}
+
+// parses the length, and checks it before parsing the elements with `parse_elements`
+fn parse_len_then<P: LengthPrefix, C: LengthPrefixed>(
+    bytes: &[u8],
+    max_len: usize,
+    parse_elements: impl FnOnce(&[u8], usize) -> Result<(C, usize), BytecodeError>,
+) -> Result<(C, usize), BytecodeError> {
+    let (len, size) = P::parse_len(bytes)?;
+    check_len(len, max_len).map_err(|e| e.with_bytes(&bytes[..size]))?;
+    let available = bytes.len() - size;
+    let min_size = len.saturating_mul(C::ELEMENT_MIN_SIZE);
+    if min_size > available {
+        return Err(BytecodeError::incomplete(min_size - available).offset_by(size));
+    }
+    let (value, used) = parse_elements(&bytes[size..], len).map_err(|e| e.offset_by(size))?;
+    Ok((value, size + used))
+}
+
+/// Reads the value with the length prefix `P`, which fails if it has more than `max_len` elements
+#[cfg(feature = "std")]
+pub fn read_prefixed<P, C, R>(reader: &mut R, max_len: usize) -> Result<C, BytecodeError>
+where
+    P: LengthPrefix,
+    C: LengthPrefixed,
+    R: Read + ?Sized,
+{
+    let len = P::read_len(reader)?;
+    check_len(len, max_len)?;
+    C::read_elements(reader, len).map_err(|e| e.offset_by(P::len_size(len)))
+}
+
+/// Same as `read_prefixed`, with the elements stored in big endian
+#[cfg(feature = "std")]
+pub fn read_prefixed_be<P, C, R>(reader: &mut R, max_len: usize) -> Result<C, BytecodeError>
+where
+    P: LengthPrefix,
+    C: EndianElements,
+    R: Read + ?Sized,
+{
+    let len = P::read_len(reader)?;
+    check_len(len, max_len)?;
+    C::read_elements_be(reader, len).map_err(|e| e.offset_by(P::len_size(len)))
+}
+
+// parses the elements one after other, passing them to `push`
+fn parse_each<T: Bytecodable>(
+    bytes: &[u8],
+    len: usize,
+    mut push: impl FnMut(T),
+) -> Result<usize, BytecodeError> {
+    let mut count = 0;
+    for _ in 0..len {
+        let (v, size) = T::parse(&bytes[count..]).map_err(|e| e.offset_by(count))?;
+        push(v);
+        count += size;
+    }
+    Ok(count)
+}
+
+#[cfg(feature = "std")]
+fn read_each<T: Bytecodable, R: Read + ?Sized>(
+    reader: &mut R,
+    len: usize,
+    mut push: impl FnMut(T),
+) -> Result<(), BytecodeError> {
+    let mut count = 0;
+    for _ in 0..len {
+        let v = T::read_from(reader).map_err(|e| e.offset_by(count))?;
+        count += v.encoded_len();
+        push(v);
+    }
+    Ok(())
+}
+
+impl<T: Bytecodable> LengthPrefixed for Vec<T> {
+    const ELEMENT_MIN_SIZE: usize = T::MIN_SIZE;
+
+    fn prefixed_len(&self) -> usize {
+        self.len()
+    }
+
+    fn elements_encoded_len(&self) -> usize {
+        self.iter().map(T::encoded_len).sum()
+    }
+
+    fn compile_elements<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        T::compile_slice(self, out)
+    }
+
+    fn parse_elements(bytes: &[u8], len: usize) -> Result<(Self, usize), BytecodeError> {
+        let mut ret = Vec::with_capacity(len.min(MAX_PREALLOC));
+        let size = T::parse_into_vec(bytes, len, &mut ret)?;
+        Ok((ret, size))
+    }
+
+    #[cfg(feature = "std")]
+    fn read_elements<R: Read + ?Sized>(reader: &mut R, len: usize) -> Result<Self, BytecodeError> {
+        let mut ret = Vec::with_capacity(len.min(MAX_PREALLOC));
+        read_each(reader, len, |v| ret.push(v))?;
+        Ok(ret)
+    }
+}
+
+impl<T: Endian> EndianElements for Vec<T> {
+    fn compile_elements_be<S: BytecodeSink + ?Sized>(
+        &self,
+        out: &mut S,
+    ) -> Result<(), BytecodeError> {
+        self.iter().try_for_each(|v| v.compile_be(out))
+    }
+
+    fn parse_elements_be(bytes: &[u8], len: usize) -> Result<(Self, usize), BytecodeError> {
+        let mut ret = Vec::with_capacity(len.min(MAX_PREALLOC));
+        let size = parse_each(bytes, len, |v: BigEndian<T>| ret.push(v.0))?;
+        Ok((ret, size))
+    }
+
+    #[cfg(feature = "std")]
+    fn read_elements_be<R: Read + ?Sized>(
+        reader: &mut R,
+        len: usize,
+    ) -> Result<Self, BytecodeError> {
+        let mut ret = Vec::with_capacity(len.min(MAX_PREALLOC));
+        read_each(reader, len, |v: BigEndian<T>| ret.push(v.0))?;
+        Ok(ret)
+    }
+}
+
+impl<T: Bytecodable> LengthPrefixed for VecDeque<T> {
+    const ELEMENT_MIN_SIZE: usize = T::MIN_SIZE;
+
+    fn prefixed_len(&self) -> usize {
+        self.len()
+    }
+
+    fn elements_encoded_len(&self) -> usize {
+        self.iter().map(T::encoded_len).sum()
+    }
+
+    fn compile_elements<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        let (front, back) = self.as_slices();
+        T::compile_slice(front, out)?;
+        T::compile_slice(back, out)
+    }
+
+    fn parse_elements(bytes: &[u8], len: usize) -> Result<(Self, usize), BytecodeError> {
+        Vec::parse_elements(bytes, len).map(|(v, size)| (VecDeque::from(v), size))
+    }
+
+    #[cfg(feature = "std")]
+    fn read_elements<R: Read + ?Sized>(reader: &mut R, len: usize) -> Result<Self, BytecodeError> {
+        let mut ret = VecDeque::with_capacity(len.min(MAX_PREALLOC));
+        read_each(reader, len, |v| ret.push_back(v))?;
+        Ok(ret)
+    }
+}
+
+impl<T: Endian> EndianElements for VecDeque<T> {
+    fn compile_elements_be<S: BytecodeSink + ?Sized>(
+        &self,
+        out: &mut S,
+    ) -> Result<(), BytecodeError> {
+        self.iter().try_for_each(|v| v.compile_be(out))
+    }
+
+    fn parse_elements_be(bytes: &[u8], len: usize) -> Result<(Self, usize), BytecodeError> {
+        let mut ret = VecDeque::with_capacity(len.min(MAX_PREALLOC));
+        let size = parse_each(bytes, len, |v: BigEndian<T>| ret.push_back(v.0))?;
+        Ok((ret, size))
+    }
+
+    #[cfg(feature = "std")]
+    fn read_elements_be<R: Read + ?Sized>(
+        reader: &mut R,
+        len: usize,
+    ) -> Result<Self, BytecodeError> {
+        let mut ret = VecDeque::with_capacity(len.min(MAX_PREALLOC));
+        read_each(reader, len, |v: BigEndian<T>| ret.push_back(v.0))?;
+        Ok(ret)
+    }
+}
+
+impl<T: Bytecodable> LengthPrefixed for Box<[T]> {
+    const ELEMENT_MIN_SIZE: usize = T::MIN_SIZE;
+
+    fn prefixed_len(&self) -> usize {
+        self.len()
+    }
+
+    fn elements_encoded_len(&self) -> usize {
+        self.iter().map(T::encoded_len).sum()
+    }
+
+    fn compile_elements<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        T::compile_slice(self, out)
+    }
+
+    fn parse_elements(bytes: &[u8], len: usize) -> Result<(Self, usize), BytecodeError> {
+        Vec::parse_elements(bytes, len).map(|(v, size)| (v.into_boxed_slice(), size))
+    }
+
+    #[cfg(feature = "std")]
+    fn read_elements<R: Read + ?Sized>(reader: &mut R, len: usize) -> Result<Self, BytecodeError> {
+        Vec::read_elements(reader, len).map(Vec::into_boxed_slice)
+    }
+}
+
+impl<T: Endian> EndianElements for Box<[T]> {
+    fn compile_elements_be<S: BytecodeSink + ?Sized>(
+        &self,
+        out: &mut S,
+    ) -> Result<(), BytecodeError> {
+        self.iter().try_for_each(|v| v.compile_be(out))
+    }
+
+    fn parse_elements_be(bytes: &[u8], len: usize) -> Result<(Self, usize), BytecodeError> {
+        Vec::parse_elements_be(bytes, len).map(|(v, size)| (v.into_boxed_slice(), size))
+    }
+
+    #[cfg(feature = "std")]
+    fn read_elements_be<R: Read + ?Sized>(
+        reader: &mut R,
+        len: usize,
+    ) -> Result<Self, BytecodeError> {
+        Vec::read_elements_be(reader, len).map(Vec::into_boxed_slice)
+    }
+}
+
+// the length of strings is the number of bytes, rather than chars
+impl LengthPrefixed for String {
+    const ELEMENT_MIN_SIZE: usize = 1;
+
+    fn prefixed_len(&self) -> usize {
+        self.len()
+    }
+
+    fn elements_encoded_len(&self) -> usize {
+        self.len()
+    }
+
+    fn compile_elements<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(self.as_bytes())
+    }
+
+    fn parse_elements(bytes: &[u8], len: usize) -> Result<(Self, usize), BytecodeError> {
+        // parse_prefixed has already checked that the bytes are available
+        let data = bytes
+            .get(..len)
+            .ok_or_else(|| BytecodeError::incomplete(len - bytes.len()))?;
+        let s = core::str::from_utf8(data).map_err(|e| invalid_utf8(data, e))?;
+        Ok((String::from(s), len))
+    }
+
+    #[cfg(feature = "std")]
+    fn read_elements<R: Read + ?Sized>(reader: &mut R, len: usize) -> Result<Self, BytecodeError> {
+        let mut data = Vec::new();
+        // the string is read in chunks, so that it is only allocated as far as the reader has bytes
+        while data.len() < len {
+            let start = data.len();
+            let end = len.min(start + MAX_PREALLOC);
+            data.resize(end, 0);
+            // the error only counts the bytes missing from this chunk, so the rest are added,
+            // which gives the same error as parsing the string from the bytes read so far
+            crate::read_bytes(reader, &mut data[start..]).map_err(|e| match e.needed() {
+                Some(needed) => BytecodeError::incomplete(needed + len - end),
+                None => e,
+            })?;
+        }
+        String::from_utf8(data).map_err(|e| invalid_utf8(e.as_bytes(), e.utf8_error()))
+    }
+}
+
+// the standard collections use the VarU64 length prefix, and the default maximum length
+macro_rules! impl_collection {
+    ($([$($params:tt)*] $t:ty),*) => {
+        $(
+            impl<$($params)*> Bytecodable for $t {
+                const MIN_SIZE: usize = <VarU64 as LengthPrefix>::MIN_SIZE;
+
+                fn encoded_len(&self) -> usize {
+                    prefixed_encoded_len::<VarU64, _>(self)
+                }
+
+                fn compile_into<S: BytecodeSink + ?Sized>(
+                    &self,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    compile_prefixed::<VarU64, _, _>(self, DEFAULT_MAX_LEN, out)
+                }
+
+                fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+                    parse_prefixed::<VarU64, _>(bytes, DEFAULT_MAX_LEN)
+                }
+
+                #[cfg(feature = "std")]
+                fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+                    read_prefixed::<VarU64, _, _>(reader, DEFAULT_MAX_LEN)
+                }
+            }
+        )*
+    };
+}
+
+impl_collection!(
+    [T: Bytecodable] Vec<T>,
+    [T: Bytecodable] VecDeque<T>,
+    [T: Bytecodable] Box<[T]>,
+    [] String
+);
+
+// the bytes of strings are same in both byte orders
+impl EndianElements for String {
+    fn compile_elements_be<S: BytecodeSink + ?Sized>(
+        &self,
+        out: &mut S,
+    ) -> Result<(), BytecodeError> {
+        self.compile_elements(out)
+    }
+
+    fn parse_elements_be(bytes: &[u8], len: usize) -> Result<(Self, usize), BytecodeError> {
+        Self::parse_elements(bytes, len)
+    }
+
+    #[cfg(feature = "std")]
+    fn read_elements_be<R: Read + ?Sized>(
+        reader: &mut R,
+        len: usize,
+    ) -> Result<Self, BytecodeError> {
+        Self::read_elements(reader, len)
+    }
+}
+
+// The default length prefix is a varint, which is same in both byte orders, so
+// in big endian only the elements are changed. Strings are same in both byte orders
+macro_rules! impl_endian_collection {
+    ($([$($params:tt)*] $t:ty),*) => {
+        $(
+            impl<$($params)*> Endian for $t {
+                fn compile_be<S: BytecodeSink + ?Sized>(
+                    &self,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    compile_prefixed_be::<VarU64, _, _>(self, DEFAULT_MAX_LEN, out)
+                }
+
+                fn parse_be(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+                    parse_prefixed_be::<VarU64, _>(bytes, DEFAULT_MAX_LEN)
+                }
+
+                #[cfg(feature = "std")]
+                fn read_be<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+                    read_prefixed_be::<VarU64, _, _>(reader, DEFAULT_MAX_LEN)
+                }
+            }
+        )*
+    };
+}
+
+impl_endian_collection!(
+    [T: Endian] Vec<T>,
+    [T: Endian] VecDeque<T>,
+    [T: Endian] Box<[T]>
+);
+impl Endian for String {}
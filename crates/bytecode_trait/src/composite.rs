@@ -0,0 +1,221 @@
+use crate::endian::Endian;
+use crate::size::{max_size_of, max_size_sum, min_size_of};
+use crate::{BigEndian, Bytecodable, BytecodeError, BytecodeSink, FixedSize};
+#[cfg(feature = "std")]
+use std::io::Read;
+
+// Option and Result are stored as a one-byte tag, followed by the bytecode of the value
+// they hold, if any. Any other tag is an invalid instruction.
+
+fn parse_tag(bytes: &[u8]) -> Result<u8, BytecodeError> {
+    match bytes.first() {
+        Some(&tag) if tag <= 1 => Ok(tag),
+        Some(_) => Err(BytecodeError::invalid(&bytes[..1])),
+        None => Err(BytecodeError::incomplete(1)),
+    }
+}
+
+#[cfg(feature = "std")]
+fn read_tag<R: Read + ?Sized>(reader: &mut R) -> Result<u8, BytecodeError> {
+    let mut tag = [0; 1];
+    crate::read_bytes(reader, &mut tag)?;
+    parse_tag(&tag)
+}
+
+/// `None` is stored as 0, and `Some` as 1 followed by the value
+impl<T: Bytecodable> Bytecodable for Option<T> {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: Option<usize> = max_size_sum(&[Some(1), T::MAX_SIZE]);
+    fn encoded_len(&self) -> usize {
+        1 + self.as_ref().map_or(0, Bytecodable::encoded_len)
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        match self {
+            None => out.write_bytes(&[0]),
+            Some(v) => {
+                out.write_bytes(&[1])?;
+                v.compile_into(out)
+            }
+        }
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        if parse_tag(bytes)? == 0 {
+            return Ok((None, 1));
+        }
+        let (v, size) = T::parse(&bytes[1..]).map_err(|e| e.offset_by(1))?;
+        Ok((Some(v), size + 1))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        if read_tag(reader)? == 0 {
+            return Ok(None);
+        }
+        T::read_from(reader).map(Some).map_err(|e| e.offset_by(1))
+    }
+}
+
+/// The value is stored in big endian, the tag is a single byte
+impl<T: Endian> Endian for Option<T> {
+    fn compile_be<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        match self {
+            None => out.write_bytes(&[0]),
+            Some(v) => {
+                out.write_bytes(&[1])?;
+                v.compile_be(out)
+            }
+        }
+    }
+    fn parse_be(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        <Option<BigEndian<T>>>::parse(bytes).map(|(v, size)| (v.map(|v| v.0), size))
+    }
+    #[cfg(feature = "std")]
+    fn read_be<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        <Option<BigEndian<T>>>::read_from(reader).map(|v| v.map(|v| v.0))
+    }
+}
+
+/// `Ok` is stored as 0 followed by the value, and `Err` as 1 followed by the error
+impl<T: Bytecodable, E: Bytecodable> Bytecodable for Result<T, E> {
+    const MIN_SIZE: usize = 1 + min_size_of(&[T::MIN_SIZE, E::MIN_SIZE]);
+    const MAX_SIZE: Option<usize> =
+        max_size_sum(&[Some(1), max_size_of(&[T::MAX_SIZE, E::MAX_SIZE])]);
+    fn encoded_len(&self) -> usize {
+        1 + match self {
+            Ok(v) => v.encoded_len(),
+            Err(e) => e.encoded_len(),
+        }
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        match self {
+            Ok(v) => {
+                out.write_bytes(&[0])?;
+                v.compile_into(out)
+            }
+            Err(e) => {
+                out.write_bytes(&[1])?;
+                e.compile_into(out)
+            }
+        }
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        let ret = match parse_tag(bytes)? {
+            0 => T::parse(&bytes[1..]).map(|(v, size)| (Ok(v), size)),
+            _ => E::parse(&bytes[1..]).map(|(e, size)| (Err(e), size)),
+        };
+        ret.map(|(v, size)| (v, size + 1))
+            .map_err(|e| e.offset_by(1))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let ret = match read_tag(reader)? {
+            0 => T::read_from(reader).map(Ok),
+            _ => E::read_from(reader).map(Err),
+        };
+        ret.map_err(|e| e.offset_by(1))
+    }
+}
+
+/// The value or the error is stored in big endian, the tag is a single byte
+impl<T: Endian, E: Endian> Endian for Result<T, E> {
+    fn compile_be<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        match self {
+            Ok(v) => {
+                out.write_bytes(&[0])?;
+                v.compile_be(out)
+            }
+            Err(e) => {
+                out.write_bytes(&[1])?;
+                e.compile_be(out)
+            }
+        }
+    }
+    fn parse_be(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        <Result<BigEndian<T>, BigEndian<E>>>::parse(bytes)
+            .map(|(v, size)| (v.map(|v| v.0).map_err(|e| e.0), size))
+    }
+    #[cfg(feature = "std")]
+    fn read_be<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        <Result<BigEndian<T>, BigEndian<E>>>::read_from(reader)
+            .map(|v| v.map(|v| v.0).map_err(|e| e.0))
+    }
+}
+
+// Tuples are stored as their elements one after the other, same as the fields of a struct
+
+macro_rules! impl_tuple {
+    ($(($($t:ident $i:tt),+))*) => {
+        $(
+            impl<$($t: Bytecodable),+> Bytecodable for ($($t,)+) {
+                const MIN_SIZE: usize = 0 $(+ $t::MIN_SIZE)+;
+                const MAX_SIZE: Option<usize> = max_size_sum(&[$($t::MAX_SIZE),+]);
+                fn encoded_len(&self) -> usize {
+                    0 $(+ self.$i.encoded_len())+
+                }
+                fn compile_into<S: BytecodeSink + ?Sized>(
+                    &self,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    $(self.$i.compile_into(out)?;)+
+                    Ok(())
+                }
+                fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+                    let mut size = 0;
+                    let ret = ($({
+                        let (v, s) = $t::parse(&bytes[size..]).map_err(|e| e.offset_by(size))?;
+                        size += s;
+                        v
+                    },)+);
+                    Ok((ret, size))
+                }
+                // the size after the last element is not needed
+                #[cfg(feature = "std")]
+                #[allow(unused_assignments)]
+                fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+                    let mut size = 0;
+                    Ok(($({
+                        let v = $t::read_from(reader).map_err(|e| e.offset_by(size))?;
+                        size += v.encoded_len();
+                        v
+                    },)+))
+                }
+            }
+
+            impl<$($t: FixedSize),+> FixedSize for ($($t,)+) {
+                const SIZE: usize = 0 $(+ $t::SIZE)+;
+            }
+
+            // each element is stored in big endian
+            impl<$($t: Endian),+> Endian for ($($t,)+) {
+                fn compile_be<S: BytecodeSink + ?Sized>(
+                    &self,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    $(self.$i.compile_be(out)?;)+
+                    Ok(())
+                }
+                fn parse_be(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+                    <($(BigEndian<$t>,)+)>::parse(bytes).map(|(v, size)| (($(v.$i.0,)+), size))
+                }
+                #[cfg(feature = "std")]
+                fn read_be<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+                    <($(BigEndian<$t>,)+)>::read_from(reader).map(|v| ($(v.$i.0,)+))
+                }
+            }
+        )*
+    };
+}
+
+impl_tuple! {
+    (A 0)
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+    (A 0, B 1, C 2, D 3)
+    (A 0, B 1, C 2, D 3, E 4)
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11)
+}
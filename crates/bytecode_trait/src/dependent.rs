@@ -0,0 +1,55 @@
+use crate::BytecodeError;
+use crate::BytecodeSink;
+#[cfg(feature = "std")]
+use crate::{read_bytes, ErrorKind};
+#[cfg(feature = "std")]
+use std::io::Read;
+
+/// Types whose bytecode depends on the value of an earlier field, which is given as `ctx`.
+/// This is used for the fields with `#[bytecode(depends = field)]`, such as the displacement
+/// after a ModR/M byte, which is present, and of a size, given by the mode bits of the byte.
+/// Each method gets the value of the named field, and `C` is the type of that field.
+/// With `depends = (a, b)`, `C` is the tuple of the types of the fields, and `ctx` holds
+/// their values.
+pub trait Dependent<C: ?Sized>: Sized {
+    /// The minimum number of bytes the bytecode of any value of this type takes,
+    /// for any value of the earlier field.
+    const MIN_SIZE: usize = 0;
+
+    /// The maximum number of bytes the bytecode of any value of this type takes,
+    /// for any value of the earlier field, None if there is no upper bound.
+    const MAX_SIZE: Option<usize> = None;
+
+    /// Number of bytes the value takes, when the earlier field has the value `ctx`
+    fn encoded_len_with(&self, ctx: &C) -> usize;
+
+    /// Compiles the value into the sink. This should return an error if the value cannot
+    /// be stored along with the value of the earlier field, such as a displacement
+    /// which is larger than the one the mode bits allow.
+    fn compile_with<S: BytecodeSink + ?Sized>(
+        &self,
+        ctx: &C,
+        out: &mut S,
+    ) -> Result<(), BytecodeError>;
+
+    /// Parses the value from the bytes, returning it along with the number of bytes it took
+    fn parse_with(bytes: &[u8], ctx: &C) -> Result<(Self, usize), BytecodeError>;
+
+    /// Reads the value from the reader. Same as `Bytecodable::read_from`, by default
+    /// this reads as many bytes as `parse_with` reports to be needed, until it succeeds.
+    #[cfg(feature = "std")]
+    fn read_with<R: Read + ?Sized>(reader: &mut R, ctx: &C) -> Result<Self, BytecodeError> {
+        let mut bytes = std::vec::Vec::new();
+        loop {
+            match Self::parse_with(&bytes, ctx) {
+                Ok((v, _)) => return Ok(v),
+                Err(e) if e.kind() == ErrorKind::IncompleteInstruction => {
+                    let start = bytes.len();
+                    bytes.resize(start + e.needed().unwrap_or(1).max(1), 0);
+                    read_bytes(reader, &mut bytes[start..]).map_err(|e| e.offset_by(start))?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{invalid_char, Bytecodable, BytecodeError, BytecodeSink, FixedSize};
+use crate::{VarI32, VarI64, VarU32, VarU64, ZigZagI32, ZigZagI64};
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::io::Read;
+
+// The Bytecodable impls of the primitives store them in little endian, and this module
+// adds the big endian encoding, which is used by the fields with `#[bytecode(endian = "big")]`,
+// and by the `BigEndian` wrapper. The big endian bytecode always takes as many bytes as the
+// little endian one, so the sizes of the Bytecodable impl hold for both.
+
+/// Types which can be stored in big endian, which is used for the fields of types
+/// with `#[bytecode(endian = "big")]`. The default methods use the `Bytecodable` impl,
+/// which is right for the types whose bytecode does not depend on the byte order,
+/// such as bytes, variable length integers and the derived types, which have their own byte order.
+pub trait Endian: Bytecodable {
+    /// Compiles the value in big endian
+    fn compile_be<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        self.compile_into(out)
+    }
+
+    /// Parses a value stored in big endian
+    fn parse_be(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        Self::parse(bytes)
+    }
+
+    /// Reads a value stored in big endian
+    #[cfg(feature = "std")]
+    fn read_be<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        Self::read_from(reader)
+    }
+}
+
+macro_rules! impl_endian {
+    ($($t:ty => $size:expr),*) => {
+        $(
+            impl Endian for $t {
+                fn compile_be<S: BytecodeSink + ?Sized>(
+                    &self,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    out.write_bytes(&self.to_be_bytes())
+                }
+                fn parse_be(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+                    if bytes.len() < $size {
+                        return Err(BytecodeError::incomplete($size - bytes.len()));
+                    }
+                    let bytes = <[u8; $size]>::try_from(&bytes[..$size]).unwrap();
+                    Ok((<$t>::from_be_bytes(bytes), $size))
+                }
+                #[cfg(feature = "std")]
+                fn read_be<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+                    let mut bytes = [0; $size];
+                    crate::read_bytes(reader, &mut bytes)?;
+                    Ok(<$t>::from_be_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_endian!(
+    u16 => 2, u32 => 4, u64 => 8, u128 => 16,
+    i16 => 2, i32 => 4, i64 => 8, i128 => 16,
+    f32 => 4, f64 => 8
+);
+
+impl Endian for char {
+    fn compile_be<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        (*self as u32).compile_be(out)
+    }
+    fn parse_be(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        let (v, size) = u32::parse_be(bytes)?;
+        let c = char::from_u32(v).ok_or_else(|| invalid_char(&bytes[..size]))?;
+        Ok((c, size))
+    }
+    #[cfg(feature = "std")]
+    fn read_be<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let v = u32::read_be(reader)?;
+        char::from_u32(v).ok_or_else(|| invalid_char(&v.to_be_bytes()))
+    }
+}
+
+// these take a single byte, or are stored a byte at a time, so they are same in both orders
+impl Endian for u8 {}
+impl Endian for i8 {}
+impl Endian for bool {}
+impl Endian for usize {}
+impl Endian for isize {}
+impl Endian for () {}
+impl<T: ?Sized> Endian for PhantomData<T> {}
+impl Endian for VarU64 {}
+impl Endian for VarU32 {}
+impl Endian for ZigZagI64 {}
+impl Endian for ZigZagI32 {}
+impl Endian for VarI64 {}
+impl Endian for VarI32 {}
+
+/// Each element of the array is stored in big endian
+impl<T: Endian, const N: usize> Endian for [T; N] {
+    fn compile_be<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        self.iter().try_for_each(|v| v.compile_be(out))
+    }
+    fn parse_be(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        <[BigEndian<T>; N]>::parse(bytes).map(|(v, size)| (v.map(|v| v.0), size))
+    }
+    #[cfg(feature = "std")]
+    fn read_be<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        <[BigEndian<T>; N]>::read_from(reader).map(|v| v.map(|v| v.0))
+    }
+}
+
+/// A value which is always stored in big endian, such as the elements
+/// of a collection, or a single field of a little endian type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BigEndian<T>(pub T);
+
+/// A value which is always stored in little endian, even in a type with
+/// `#[bytecode(endian = "big")]`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LittleEndian<T>(pub T);
+
+impl<T> From<T> for BigEndian<T> {
+    fn from(value: T) -> Self {
+        BigEndian(value)
+    }
+}
+
+impl<T> From<T> for LittleEndian<T> {
+    fn from(value: T) -> Self {
+        LittleEndian(value)
+    }
+}
+
+impl<T: Endian> Bytecodable for BigEndian<T> {
+    const MIN_SIZE: usize = T::MIN_SIZE;
+    const MAX_SIZE: Option<usize> = T::MAX_SIZE;
+    fn encoded_len(&self) -> usize {
+        self.0.encoded_len()
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        self.0.compile_be(out)
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        T::parse_be(bytes).map(|(v, size)| (BigEndian(v), size))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        T::read_be(reader).map(BigEndian)
+    }
+}
+
+impl<T: Bytecodable> Bytecodable for LittleEndian<T> {
+    const MIN_SIZE: usize = T::MIN_SIZE;
+    const MAX_SIZE: Option<usize> = T::MAX_SIZE;
+    fn encoded_len(&self) -> usize {
+        self.0.encoded_len()
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        self.0.compile_into(out)
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        T::parse(bytes).map(|(v, size)| (LittleEndian(v), size))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        T::read_from(reader).map(LittleEndian)
+    }
+}
+
+// the wrappers fix the byte order, so it is not changed by the type containing them
+impl<T: Endian> Endian for BigEndian<T> {}
+impl<T: Bytecodable> Endian for LittleEndian<T> {}
+
+impl<T: Endian + FixedSize> FixedSize for BigEndian<T> {
+    const SIZE: usize = T::SIZE;
+}
+
+impl<T: FixedSize> FixedSize for LittleEndian<T> {
+    const SIZE: usize = T::SIZE;
+}
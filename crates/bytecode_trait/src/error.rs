@@ -0,0 +1,282 @@
+use core::fmt;
+use core::hash::{Hash, Hasher};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+/// This specifies the kinds of errors that might occur in compiling or parsing the bytecode
+// more kinds might be added, such as the ones which depend on features
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The instruction is invalid, that is no instruction compiles to this specific instruction byte(s)
+    InvalidInstruction,
+    /// The instruction is incomplete, more bytes are expected to complete the parsing
+    IncompleteInstruction,
+    /// The bytecode does not fit in the buffer it is being compiled into
+    BufferOverflow,
+    /// The length of a collection is more than the maximum allowed length,
+    /// or than its length prefix can hold
+    LengthLimit,
+    /// Reading or writing the bytecode failed, with the kind of the I/O error, the error
+    /// itself is the `source` of the `BytecodeError`. This is only present with the `std`
+    /// feature, so matches on the kind should have a wildcard arm, which is required
+    /// anyway as more kinds might be added.
+    #[cfg(feature = "std")]
+    Io(std::io::ErrorKind),
+    /// Some other error
+    Other(&'static str),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::InvalidInstruction => f.write_str("invalid instruction"),
+            ErrorKind::IncompleteInstruction => f.write_str("incomplete instruction"),
+            ErrorKind::BufferOverflow => f.write_str("buffer is too small for the bytecode"),
+            ErrorKind::LengthLimit => f.write_str("length of the collection exceeds its limit"),
+            #[cfg(feature = "std")]
+            ErrorKind::Io(kind) => write!(f, "I/O error : {:?}", kind),
+            ErrorKind::Other(msg) => f.write_str(msg),
+        }
+    }
+}
+
+// the error is returned in every Result of the crate, so everything is stored inline,
+// with fixed capacities, to keep it small and free of allocations
+
+// number of path segments stored, deeper nesting is truncated
+const MAX_PATH_SEGMENTS: usize = 4;
+// number of offending bytes stored
+const MAX_ERROR_BYTES: usize = 4;
+
+/// The error returned when the bytecode cannot be compiled or parsed.
+/// Along with the kind of error, for parsing errors this records where in the input
+/// the error occurred and what was being parsed at that point.
+#[derive(Clone)]
+pub struct BytecodeError {
+    kind: ErrorKind,
+    offset: usize,
+    needed: usize,
+    bytes: [u8; MAX_ERROR_BYTES],
+    bytes_len: u8,
+    // each segment is the path within one type, such as `Opcode::AddM.1`,
+    // starting from the outermost type
+    path: [&'static str; MAX_PATH_SEGMENTS],
+    path_len: u8,
+    path_truncated: bool,
+    // the I/O error of `ErrorKind::Io` errors, shared so that the error stays `Clone`
+    #[cfg(feature = "std")]
+    io: Option<Arc<std::io::Error>>,
+}
+
+impl BytecodeError {
+    /// Creates an error of given kind, at offset 0 and with no path
+    pub const fn new(kind: ErrorKind) -> Self {
+        BytecodeError {
+            kind,
+            offset: 0,
+            needed: 0,
+            bytes: [0; MAX_ERROR_BYTES],
+            bytes_len: 0,
+            path: [""; MAX_PATH_SEGMENTS],
+            path_len: 0,
+            path_truncated: false,
+            #[cfg(feature = "std")]
+            io: None,
+        }
+    }
+
+    /// Creates an invalid instruction error for the given offending bytes
+    pub fn invalid(bytes: &[u8]) -> Self {
+        BytecodeError::new(ErrorKind::InvalidInstruction).with_bytes(bytes)
+    }
+
+    /// Creates an incomplete instruction error, which needs given number of more bytes
+    pub const fn incomplete(needed: usize) -> Self {
+        let mut ret = BytecodeError::new(ErrorKind::IncompleteInstruction);
+        ret.needed = needed;
+        ret
+    }
+
+    pub const fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The offset of the byte where the error occurred, relative to the start
+    /// of the bytes given to the outermost `parse` call
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// For incomplete instructions, the number of additional bytes needed to continue parsing
+    pub const fn needed(&self) -> Option<usize> {
+        match self.kind {
+            ErrorKind::IncompleteInstruction => Some(self.needed),
+            _ => None,
+        }
+    }
+
+    /// The bytes which caused the error, if any. This only stores the first few bytes.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.bytes_len as usize]
+    }
+
+    /// The path of the value being parsed when the error occurred,
+    /// such as `Opcode::AddM.1.offset`, which displays as empty if not known
+    pub fn path(&self) -> ErrorPath<'_> {
+        ErrorPath(self)
+    }
+
+    /// Sets the offending bytes of the error
+    pub fn with_bytes(mut self, bytes: &[u8]) -> Self {
+        let len = bytes.len().min(MAX_ERROR_BYTES);
+        self.bytes[..len].copy_from_slice(&bytes[..len]);
+        self.bytes_len = len as u8;
+        self
+    }
+
+    /// Moves the offset of the error by given number of bytes. This is used when the
+    /// error comes from parsing a value which starts at the given offset.
+    pub fn offset_by(mut self, offset: usize) -> Self {
+        self.offset += offset;
+        self
+    }
+
+    /// Adds the path of the value containing the one which caused the error, which is
+    /// the type name, followed by variant and field, such as `Opcode::AddM.1`
+    pub fn within(mut self, segment: &'static str) -> Self {
+        if self.path_len as usize == MAX_PATH_SEGMENTS {
+            // the innermost segment is dropped, as the outer ones tell where to look
+            self.path_len -= 1;
+            self.path_truncated = true;
+        }
+        self.path.copy_within(..self.path_len as usize, 1);
+        self.path[0] = segment;
+        self.path_len += 1;
+        self
+    }
+}
+
+impl From<ErrorKind> for BytecodeError {
+    fn from(kind: ErrorKind) -> Self {
+        BytecodeError::new(kind)
+    }
+}
+
+/// Creates an `ErrorKind::Io` error, which keeps the I/O error as its `source`
+#[cfg(feature = "std")]
+impl From<std::io::Error> for BytecodeError {
+    fn from(e: std::io::Error) -> Self {
+        let mut ret = BytecodeError::new(ErrorKind::Io(e.kind()));
+        ret.io = Some(Arc::new(e));
+        ret
+    }
+}
+
+// I/O errors cannot be compared, so errors are equal when everything else is,
+// which includes the kind of the I/O error
+impl PartialEq for BytecodeError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.offset == other.offset
+            && self.needed == other.needed
+            && self.bytes() == other.bytes()
+            && self.path[..self.path_len as usize] == other.path[..other.path_len as usize]
+            && self.path_truncated == other.path_truncated
+    }
+}
+
+impl Eq for BytecodeError {}
+
+impl Hash for BytecodeError {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.kind.hash(state);
+        self.offset.hash(state);
+        self.needed.hash(state);
+        self.bytes().hash(state);
+        self.path[..self.path_len as usize].hash(state);
+        self.path_truncated.hash(state);
+    }
+}
+
+/// Displays the path where the error occurred, see `BytecodeError::path`
+#[derive(Clone, Copy)]
+pub struct ErrorPath<'e>(&'e BytecodeError);
+
+// shown as a string, as the segments are joined when displayed
+impl<'e> fmt::Debug for ErrorPath<'e> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+impl<'e> fmt::Display for ErrorPath<'e> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let err = self.0;
+        for (i, segment) in err.path[..err.path_len as usize].iter().enumerate() {
+            if i == 0 {
+                f.write_str(segment)?;
+                continue;
+            }
+            // the outer segment already names the field, so the type name
+            // at the start of inner segments is skipped
+            if let Some(start) = segment.find(['.', ':']) {
+                f.write_str(&segment[start..])?;
+            }
+        }
+        if err.path_truncated {
+            f.write_str("..")?;
+        }
+        Ok(())
+    }
+}
+
+// only the parts which are set are shown, rather than the fixed buffers they are stored in
+impl fmt::Debug for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BytecodeError")
+            .field("kind", &self.kind)
+            .field("offset", &self.offset)
+            .field("needed", &self.needed())
+            .field("bytes", &self.bytes())
+            .field("path", &self.path())
+            .finish()
+    }
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        // errors in compiling do not have a location, unlike the ones in parsing
+        let parsing = matches!(
+            self.kind,
+            ErrorKind::InvalidInstruction | ErrorKind::IncompleteInstruction
+        );
+        if parsing || self.offset != 0 || self.path_len != 0 {
+            write!(f, " at offset {}", self.offset)?;
+        }
+        if let Some(needed) = self.needed() {
+            write!(f, ", {} more byte(s) needed", needed)?;
+        }
+        if self.bytes_len != 0 {
+            f.write_str(", found")?;
+            for b in self.bytes() {
+                write!(f, " {:#04x}", b)?;
+            }
+        }
+        if self.path_len != 0 {
+            write!(f, " in {}", self.path())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BytecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.io {
+            Some(e) => Some(&**e),
+            None => None,
+        }
+    }
+}
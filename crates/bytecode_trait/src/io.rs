@@ -0,0 +1,38 @@
+use crate::{BytecodeError, BytecodeSink};
+pub use std::io::{Read, Write};
+
+/// Adapts a `std::io::Write` into a `BytecodeSink`, so values can be compiled directly
+/// into files, sockets etc. The bytes are written as they are compiled, so a buffered
+/// writer should be used when each write is costly.
+#[derive(Debug)]
+pub struct IoSink<'w, W: Write + ?Sized> {
+    writer: &'w mut W,
+}
+
+impl<'w, W: Write + ?Sized> IoSink<'w, W> {
+    pub fn new(writer: &'w mut W) -> Self {
+        IoSink { writer }
+    }
+}
+
+impl<'w, W: Write + ?Sized> BytecodeSink for IoSink<'w, W> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), BytecodeError> {
+        self.writer.write_all(bytes).map_err(BytecodeError::from)
+    }
+}
+
+/// Fills the buffer from the reader. If the reader ends before that, this returns
+/// an incomplete instruction error, with the number of bytes which could not be read
+/// as the needed bytes. Other I/O failures are returned as `ErrorKind::Io` errors.
+pub fn read_bytes<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<(), BytecodeError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => return Err(BytecodeError::incomplete(buf.len() - filled)),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
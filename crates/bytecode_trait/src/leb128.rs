@@ -0,0 +1,133 @@
+use crate::{BytecodeError, BytecodeSink};
+use core::convert::TryFrom;
+
+// LEB128 encoding of unsigned values, in which each byte holds 7 bits of the value,
+// starting from the lowest ones, and the MSB of a byte is set if more bytes follow.
+// This is used for lengths, so that small lengths take a single byte.
+
+// a u64 takes at most 10 bytes
+pub(crate) const MAX_LEB128_LEN: usize = 10;
+
+pub(crate) fn leb128_len(mut value: u64) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+pub(crate) fn write_leb128<S: BytecodeSink + ?Sized>(
+    out: &mut S,
+    mut value: u64,
+) -> Result<(), BytecodeError> {
+    let mut buf = [0; MAX_LEB128_LEN];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    out.write_bytes(&buf[..len])
+}
+
+// Only the shortest encoding of a value is accepted, so that every value has a single
+// valid encoding. Values which do not fit in u64 are invalid as well.
+pub(crate) fn read_leb128(bytes: &[u8]) -> Result<(u64, usize), BytecodeError> {
+    let mut value = 0_u64;
+    for (i, &byte) in bytes.iter().enumerate().take(MAX_LEB128_LEN) {
+        let bits = (byte & 0x7F) as u64;
+        // the 10th byte can only hold the highest bit of u64
+        if i == MAX_LEB128_LEN - 1 && byte > 1 {
+            return Err(BytecodeError::invalid(&bytes[..=i]));
+        }
+        value |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            // a trailing zero byte means the value could have been encoded in fewer bytes
+            if byte == 0 && i != 0 {
+                return Err(BytecodeError::invalid(&bytes[..=i]));
+            }
+            return Ok((value, i + 1));
+        }
+    }
+    Err(BytecodeError::incomplete(1))
+}
+
+// Signed LEB128, in which the value is stored as two's complement in the same way,
+// and bit 6 of the last byte is the sign, which is extended to the rest of the bits.
+
+pub(crate) fn sleb128_len(mut value: i64) -> usize {
+    let mut len = 1;
+    // more bytes are needed until the rest of the value is only the sign bits
+    while !(-0x40..0x40).contains(&value) {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+pub(crate) fn write_sleb128<S: BytecodeSink + ?Sized>(
+    out: &mut S,
+    mut value: i64,
+) -> Result<(), BytecodeError> {
+    let mut buf = [0; MAX_LEB128_LEN];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        if (-0x40..0x40).contains(&value) {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        value >>= 7;
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    out.write_bytes(&buf[..len])
+}
+
+// As for the unsigned values, only the shortest encoding is accepted
+pub(crate) fn read_sleb128(bytes: &[u8]) -> Result<(i64, usize), BytecodeError> {
+    // the 10 bytes can hold 70 bits, so the value is collected in a larger type
+    // and then checked to fit in i64
+    let mut value = 0_i128;
+    for (i, &byte) in bytes.iter().enumerate().take(MAX_LEB128_LEN) {
+        value |= ((byte & 0x7F) as i128) << (7 * i);
+        if byte & 0x80 == 0 {
+            let bits = 7 * (i + 1);
+            if byte & 0x40 != 0 {
+                value -= 1 << bits;
+            }
+            return match i64::try_from(value) {
+                Ok(v) if sleb128_len(v) == i + 1 => Ok((v, i + 1)),
+                _ => Err(BytecodeError::invalid(&bytes[..=i])),
+            };
+        }
+    }
+    if bytes.len() >= MAX_LEB128_LEN {
+        return Err(BytecodeError::invalid(&bytes[..MAX_LEB128_LEN]));
+    }
+    Err(BytecodeError::incomplete(1))
+}
+
+// Reads the bytes of a LEB128 value, signed or unsigned, from the reader into the buffer.
+// The bytes are read one at a time, so that nothing after the value is read.
+#[cfg(feature = "std")]
+pub(crate) fn read_leb128_bytes<R: std::io::Read + ?Sized>(
+    reader: &mut R,
+    bytes: &mut [u8; MAX_LEB128_LEN],
+) -> Result<usize, BytecodeError> {
+    for i in 0..MAX_LEB128_LEN {
+        crate::read_bytes(reader, &mut bytes[i..=i])?;
+        if bytes[i] & 0x80 == 0 {
+            return Ok(i + 1);
+        }
+    }
+    Ok(MAX_LEB128_LEN)
+}
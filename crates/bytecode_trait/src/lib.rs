@@ -1,138 +1,578 @@
-use std::convert::TryFrom;
-#[derive(Debug, PartialEq, Eq, Hash)]
-/// This specifies the errors that might occur in parsing the bytecode
-pub enum BytecodeError {
-    /// The instruction is invalid, that is no instruction compiles to this specific instruction byte(s)
-    InvalidInstruction,
-    /// The instruction is incomplete, more bytes are expected to complete the parsing
-    IncompleteInstruction,
-    /// Some other error
-    Other(&'static str),
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+mod array;
+mod bits;
+mod borrowed;
+#[cfg(feature = "alloc")]
+pub mod collections;
+mod composite;
+mod dependent;
+pub mod endian;
+mod error;
+#[cfg(feature = "std")]
+pub mod io;
+mod leb128;
+pub mod modrm;
+mod sink;
+pub mod size;
+mod std_types;
+pub mod varint;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+pub use bits::*;
+pub use borrowed::*;
+use core::convert::TryFrom;
+pub use dependent::*;
+pub use endian::{BigEndian, LittleEndian};
+pub use error::*;
+#[cfg(feature = "std")]
+pub use io::{read_bytes, IoSink};
+pub use modrm::{Disp32, Displacement, ModRm, Sib};
+pub use sink::*;
+pub use size::FixedSize;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
+pub use varint::{VarI32, VarI64, VarU32, VarU64, ZigZagI32, ZigZagI64};
+
+// The derive macro uses this for the items which need std, as the macro
+// cannot know the features bytecode_trait is compiled with
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __std_only {
+    ($($item:tt)*) => {
+        $($item)*
+    };
+}
+
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __std_only {
+    ($($item:tt)*) => {};
 }
 
 pub trait Bytecodable: Sized {
-    fn compile(&self) -> Vec<u8>;
+    /// The minimum number of bytes the bytecode of any value of this type takes.
+    const MIN_SIZE: usize = 0;
+
+    /// The maximum number of bytes the bytecode of any value of this type takes,
+    /// None if there is no upper bound.
+    const MAX_SIZE: Option<usize> = None;
+
+    /// This function returns the number of bytes the bytecode of this value takes,
+    /// which is same as the length of `compile` output.
+    /// By default this compiles the value into a sink which only counts the bytes,
+    /// implementations can override this to compute the length directly.
+    fn encoded_len(&self) -> usize {
+        let mut counter = size::SizeCounter::default();
+        // the errors are reported by compile functions, here we only care about the length
+        let _ = self.compile_into(&mut counter);
+        counter.0
+    }
+
+    /// This function compiles the value to its bytecode representation,
+    /// and writes it into the given sink, without any intermediate allocations.
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError>;
+
+    /// This function compiles the value to its bytecode representation.
+    /// This panics if the value cannot be compiled, such as when a bit-packed
+    /// field does not fit in its width, use `compile_into` to handle such errors.
+    #[cfg(feature = "alloc")]
+    fn compile(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        if let Err(e) = self.compile_into(&mut ret) {
+            panic!("cannot compile the value : {}", e);
+        }
+        ret
+    }
+
     fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError>;
+
+    /// This function compiles the value and writes the bytecode into the writer.
+    #[cfg(feature = "std")]
+    fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> Result<(), BytecodeError> {
+        self.compile_into(&mut IoSink::new(writer))
+    }
+
+    /// This function reads the bytecode of a value from the reader, and parses it.
+    /// Only the bytes of the value are read, so the reader can be used to read the
+    /// next value after this. If the reader ends before the value is complete,
+    /// this returns an incomplete instruction error.
+    /// By default this reads as many bytes as `parse` reports to be needed, until it succeeds,
+    /// implementations can override this to read the value directly.
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = Vec::new();
+        loop {
+            match Self::parse(&bytes) {
+                Ok((v, _)) => return Ok(v),
+                Err(e) if e.kind() == ErrorKind::IncompleteInstruction => {
+                    let start = bytes.len();
+                    bytes.resize(start + e.needed().unwrap_or(1).max(1), 0);
+                    read_bytes(reader, &mut bytes[start..]).map_err(|e| e.offset_by(start))?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// This function compiles the values one after the other. This is used by arrays and
+    /// collections, and types whose bytecode is same as their memory, such as `u8`,
+    /// override it to write all the values at once.
+    fn compile_slice<S: BytecodeSink + ?Sized>(
+        values: &[Self],
+        out: &mut S,
+    ) -> Result<(), BytecodeError> {
+        values.iter().try_for_each(|v| v.compile_into(out))
+    }
+
+    /// This function parses `N` values one after the other, into an array.
+    /// This is used by arrays, and can be overridden in the same way as `compile_slice`.
+    fn parse_array<const N: usize>(bytes: &[u8]) -> Result<([Self; N], usize), BytecodeError> {
+        // the values are at least this long, so if the bytes are not enough, this
+        // reports all the bytes needed at once, rather than one value at a time
+        let min_size = N * Self::MIN_SIZE;
+        if bytes.len() < min_size {
+            return Err(BytecodeError::incomplete(min_size - bytes.len()));
+        }
+        let mut count = 0;
+        let values = array::try_from_fn(|| {
+            let (v, size) = Self::parse(&bytes[count..]).map_err(|e| e.offset_by(count))?;
+            count += size;
+            Ok(v)
+        })?;
+        Ok((values, count))
+    }
+
+    /// This function parses `len` values one after the other, appending them to `values`,
+    /// and returns the number of bytes they took. This is used by collections,
+    /// and can be overridden in the same way as `compile_slice`.
+    #[cfg(feature = "alloc")]
+    fn parse_into_vec(
+        bytes: &[u8],
+        len: usize,
+        values: &mut Vec<Self>,
+    ) -> Result<usize, BytecodeError> {
+        let mut count = 0;
+        for _ in 0..len {
+            let (v, size) = Self::parse(&bytes[count..]).map_err(|e| e.offset_by(count))?;
+            values.push(v);
+            count += size;
+        }
+        Ok(count)
+    }
+
+    /// This function reads `N` values one after the other, into an array.
+    /// This is used by arrays, and can be overridden in the same way as `compile_slice`.
+    #[cfg(feature = "std")]
+    fn read_array<R: Read + ?Sized, const N: usize>(
+        reader: &mut R,
+    ) -> Result<[Self; N], BytecodeError> {
+        let mut count = 0;
+        array::try_from_fn(|| {
+            let v = Self::read_from(reader).map_err(|e| e.offset_by(count))?;
+            count += v.encoded_len();
+            Ok(v)
+        })
+    }
 }
 
 // Note all primitive follow little enadian encoding
 // shouldn't be a big problem, as when using Bytecodable,
 // users aren't meant to manually work with the bytecode,
-// instead treat it as blackbox, and use the trait as interface
+// instead treat it as blackbox, and use the trait as interface.
+// Formats which need big endian can use the endian module
 
 impl Bytecodable for u8 {
-    fn compile(&self) -> Vec<u8> {
-        Vec::from(self.to_le_bytes())
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: Option<usize> = Some(1);
+    fn encoded_len(&self) -> usize {
+        1
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
     }
     fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
         #[allow(clippy::len_zero)]
         if bytes.len() < 1 {
-            return Err(BytecodeError::IncompleteInstruction);
+            return Err(BytecodeError::incomplete(1 - bytes.len()));
         }
         let bytes: [u8; 1] = [bytes[0]];
         Ok((u8::from_le_bytes(bytes), 1))
     }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 1];
+        read_bytes(reader, &mut bytes)?;
+        Ok(u8::from_le_bytes(bytes))
+    }
+    // bytes are their own bytecode, so these copy all of them at once
+    fn compile_slice<S: BytecodeSink + ?Sized>(
+        values: &[Self],
+        out: &mut S,
+    ) -> Result<(), BytecodeError> {
+        out.write_bytes(values)
+    }
+    fn parse_array<const N: usize>(bytes: &[u8]) -> Result<([Self; N], usize), BytecodeError> {
+        match bytes.get(..N) {
+            Some(b) => Ok((<[u8; N]>::try_from(b).unwrap(), N)),
+            None => Err(BytecodeError::incomplete(N - bytes.len())),
+        }
+    }
+    #[cfg(feature = "alloc")]
+    fn parse_into_vec(
+        bytes: &[u8],
+        len: usize,
+        values: &mut Vec<Self>,
+    ) -> Result<usize, BytecodeError> {
+        match bytes.get(..len) {
+            Some(b) => {
+                values.extend_from_slice(b);
+                Ok(len)
+            }
+            None => Err(BytecodeError::incomplete(len - bytes.len())),
+        }
+    }
+    #[cfg(feature = "std")]
+    fn read_array<R: Read + ?Sized, const N: usize>(
+        reader: &mut R,
+    ) -> Result<[Self; N], BytecodeError> {
+        let mut bytes = [0; N];
+        read_bytes(reader, &mut bytes)?;
+        Ok(bytes)
+    }
 }
 
 impl Bytecodable for u16 {
-    fn compile(&self) -> Vec<u8> {
-        Vec::from(self.to_le_bytes())
+    const MIN_SIZE: usize = 2;
+    const MAX_SIZE: Option<usize> = Some(2);
+    fn encoded_len(&self) -> usize {
+        2
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
     }
     fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
         if bytes.len() < 2 {
-            return Err(BytecodeError::IncompleteInstruction);
+            return Err(BytecodeError::incomplete(2 - bytes.len()));
         }
         let bytes: [u8; 2] = <[u8; 2]>::try_from(&bytes[0..2]).unwrap();
         Ok((u16::from_le_bytes(bytes), 2))
     }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 2];
+        read_bytes(reader, &mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
 }
 
 impl Bytecodable for u32 {
-    fn compile(&self) -> Vec<u8> {
-        Vec::from(self.to_le_bytes())
+    const MIN_SIZE: usize = 4;
+    const MAX_SIZE: Option<usize> = Some(4);
+    fn encoded_len(&self) -> usize {
+        4
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
     }
     fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
         if bytes.len() < 4 {
-            return Err(BytecodeError::IncompleteInstruction);
+            return Err(BytecodeError::incomplete(4 - bytes.len()));
         }
         let bytes: [u8; 4] = <[u8; 4]>::try_from(&bytes[0..4]).unwrap();
         Ok((u32::from_le_bytes(bytes), 4))
     }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 4];
+        read_bytes(reader, &mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
 }
 
 impl Bytecodable for u64 {
-    fn compile(&self) -> Vec<u8> {
-        Vec::from(self.to_le_bytes())
+    const MIN_SIZE: usize = 8;
+    const MAX_SIZE: Option<usize> = Some(8);
+    fn encoded_len(&self) -> usize {
+        8
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
     }
     fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
         if bytes.len() < 8 {
-            return Err(BytecodeError::IncompleteInstruction);
+            return Err(BytecodeError::incomplete(8 - bytes.len()));
         }
         let bytes: [u8; 8] = <[u8; 8]>::try_from(&bytes[0..8]).unwrap();
         Ok((u64::from_le_bytes(bytes), 8))
     }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 8];
+        read_bytes(reader, &mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+impl Bytecodable for u128 {
+    const MIN_SIZE: usize = 16;
+    const MAX_SIZE: Option<usize> = Some(16);
+    fn encoded_len(&self) -> usize {
+        16
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        if bytes.len() < 16 {
+            return Err(BytecodeError::incomplete(16 - bytes.len()));
+        }
+        let bytes: [u8; 16] = <[u8; 16]>::try_from(&bytes[0..16]).unwrap();
+        Ok((u128::from_le_bytes(bytes), 16))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 16];
+        read_bytes(reader, &mut bytes)?;
+        Ok(u128::from_le_bytes(bytes))
+    }
 }
 
 impl Bytecodable for i8 {
-    fn compile(&self) -> Vec<u8> {
-        Vec::from(self.to_le_bytes())
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: Option<usize> = Some(1);
+    fn encoded_len(&self) -> usize {
+        1
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
     }
     fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
         #[allow(clippy::len_zero)]
         if bytes.len() < 1 {
-            return Err(BytecodeError::IncompleteInstruction);
+            return Err(BytecodeError::incomplete(1 - bytes.len()));
         }
         let bytes: [u8; 1] = [bytes[0]];
         Ok((i8::from_le_bytes(bytes), 1))
     }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 1];
+        read_bytes(reader, &mut bytes)?;
+        Ok(i8::from_le_bytes(bytes))
+    }
 }
 
 impl Bytecodable for i16 {
-    fn compile(&self) -> Vec<u8> {
-        Vec::from(self.to_le_bytes())
+    const MIN_SIZE: usize = 2;
+    const MAX_SIZE: Option<usize> = Some(2);
+    fn encoded_len(&self) -> usize {
+        2
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
     }
     fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
         if bytes.len() < 2 {
-            return Err(BytecodeError::IncompleteInstruction);
+            return Err(BytecodeError::incomplete(2 - bytes.len()));
         }
         let bytes: [u8; 2] = <[u8; 2]>::try_from(&bytes[0..2]).unwrap();
         Ok((i16::from_le_bytes(bytes), 2))
     }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 2];
+        read_bytes(reader, &mut bytes)?;
+        Ok(i16::from_le_bytes(bytes))
+    }
 }
 
 impl Bytecodable for i32 {
-    fn compile(&self) -> Vec<u8> {
-        Vec::from(self.to_le_bytes())
+    const MIN_SIZE: usize = 4;
+    const MAX_SIZE: Option<usize> = Some(4);
+    fn encoded_len(&self) -> usize {
+        4
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
     }
     fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
         if bytes.len() < 4 {
-            return Err(BytecodeError::IncompleteInstruction);
+            return Err(BytecodeError::incomplete(4 - bytes.len()));
         }
         let bytes: [u8; 4] = <[u8; 4]>::try_from(&bytes[0..4]).unwrap();
         Ok((i32::from_le_bytes(bytes), 4))
     }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 4];
+        read_bytes(reader, &mut bytes)?;
+        Ok(i32::from_le_bytes(bytes))
+    }
 }
 
 impl Bytecodable for i64 {
-    fn compile(&self) -> Vec<u8> {
-        Vec::from(self.to_le_bytes())
+    const MIN_SIZE: usize = 8;
+    const MAX_SIZE: Option<usize> = Some(8);
+    fn encoded_len(&self) -> usize {
+        8
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
     }
     fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
         if bytes.len() < 8 {
-            return Err(BytecodeError::IncompleteInstruction);
+            return Err(BytecodeError::incomplete(8 - bytes.len()));
         }
         let bytes: [u8; 8] = <[u8; 8]>::try_from(&bytes[0..8]).unwrap();
         Ok((i64::from_le_bytes(bytes), 8))
     }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 8];
+        read_bytes(reader, &mut bytes)?;
+        Ok(i64::from_le_bytes(bytes))
+    }
+}
+
+impl Bytecodable for i128 {
+    const MIN_SIZE: usize = 16;
+    const MAX_SIZE: Option<usize> = Some(16);
+    fn encoded_len(&self) -> usize {
+        16
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        if bytes.len() < 16 {
+            return Err(BytecodeError::incomplete(16 - bytes.len()));
+        }
+        let bytes: [u8; 16] = <[u8; 16]>::try_from(&bytes[0..16]).unwrap();
+        Ok((i128::from_le_bytes(bytes), 16))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 16];
+        read_bytes(reader, &mut bytes)?;
+        Ok(i128::from_le_bytes(bytes))
+    }
 }
 
 impl Bytecodable for bool {
-    fn compile(&self) -> Vec<u8> {
-        (*self as u8).compile()
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: Option<usize> = Some(1);
+    fn encoded_len(&self) -> usize {
+        1
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        (*self as u8).compile_into(out)
     }
     // this is dicey, as this essentially mimics
     // C style truth/false value
     fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
         u8::parse(bytes).map(|(x, s)| (x != 0, s))
     }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        u8::read_from(reader).map(|x| x != 0)
+    }
 }
+
+// floats are stored as their bits, so every value, including the NaN payloads,
+// is parsed back exactly as it was compiled
+
+impl Bytecodable for f32 {
+    const MIN_SIZE: usize = 4;
+    const MAX_SIZE: Option<usize> = Some(4);
+    fn encoded_len(&self) -> usize {
+        4
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        if bytes.len() < 4 {
+            return Err(BytecodeError::incomplete(4 - bytes.len()));
+        }
+        let bytes: [u8; 4] = <[u8; 4]>::try_from(&bytes[0..4]).unwrap();
+        Ok((f32::from_le_bytes(bytes), 4))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 4];
+        read_bytes(reader, &mut bytes)?;
+        Ok(f32::from_le_bytes(bytes))
+    }
+}
+
+impl Bytecodable for f64 {
+    const MIN_SIZE: usize = 8;
+    const MAX_SIZE: Option<usize> = Some(8);
+    fn encoded_len(&self) -> usize {
+        8
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        out.write_bytes(&self.to_le_bytes())
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        if bytes.len() < 8 {
+            return Err(BytecodeError::incomplete(8 - bytes.len()));
+        }
+        let bytes: [u8; 8] = <[u8; 8]>::try_from(&bytes[0..8]).unwrap();
+        Ok((f64::from_le_bytes(bytes), 8))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut bytes = [0; 8];
+        read_bytes(reader, &mut bytes)?;
+        Ok(f64::from_le_bytes(bytes))
+    }
+}
+
+impl Bytecodable for char {
+    const MIN_SIZE: usize = 4;
+    const MAX_SIZE: Option<usize> = Some(4);
+    fn encoded_len(&self) -> usize {
+        4
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        (*self as u32).compile_into(out)
+    }
+    // surrogates and values above char::MAX are not chars, so they are rejected
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        let (v, size) = u32::parse(bytes)?;
+        let c = char::from_u32(v).ok_or_else(|| invalid_char(&bytes[..size]))?;
+        Ok((c, size))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let v = u32::read_from(reader)?;
+        char::from_u32(v).ok_or_else(|| invalid_char(&v.to_le_bytes()))
+    }
+}
+
+pub(crate) fn invalid_char(bytes: &[u8]) -> BytecodeError {
+    BytecodeError::new(ErrorKind::Other("value is not a valid char")).with_bytes(bytes)
+}
+
+macro_rules! impl_fixed_size {
+    ($($t:ty => $size:expr),*) => {
+        $(
+            impl FixedSize for $t {
+                const SIZE: usize = $size;
+            }
+        )*
+    };
+}
+
+impl_fixed_size!(
+    u8 => 1, u16 => 2, u32 => 4, u64 => 8, u128 => 16,
+    i8 => 1, i16 => 2, i32 => 4, i64 => 8, i128 => 16,
+    f32 => 4, f64 => 8, bool => 1, char => 4
+);
@@ -0,0 +1,309 @@
+//! Building blocks for x86 style addressing modes, where a ModR/M byte gives the register
+//! and the addressing mode of an operand, and the mode decides if a SIB byte and
+//! a displacement follow it. The types which follow the ModR/M byte implement `Dependent`,
+//! so they can be used in derived types with `#[bytecode(depends = ..)]`, naming the fields
+//! they follow.
+//!
+//! `Displacement` is the displacement of 16 bit addressing, as in the 8086, which depends
+//! on the ModR/M byte alone. 32 bit addressing uses `Option<Sib>`, which depends on the ModR/M
+//! byte, and `Disp32`, which depends on both the ModR/M and the SIB byte, with
+//! `depends = (modrm, sib)`.
+
+use crate::{Bytecodable, BytecodeError, BytecodeSink, Dependent, ErrorKind, FixedSize};
+#[cfg(feature = "std")]
+use std::io::Read;
+
+const MISMATCH_ERROR: &str = "value does not match the addressing mode of the ModR/M byte";
+
+// both ModR/M and SIB bytes are split into 2, 3 and 3 bits, from the MSB
+const fn split(byte: u8) -> (u8, u8, u8) {
+    (byte >> 6, (byte >> 3) & 0b111, byte & 0b111)
+}
+
+const fn join(high: u8, mid: u8, low: u8) -> u8 {
+    assert!(
+        high < 4 && mid < 8 && low < 8,
+        "value does not fit in its bits"
+    );
+    high << 6 | mid << 3 | low
+}
+
+/// The ModR/M byte, which holds the addressing mode in its 2 highest bits, a register
+/// or an opcode extension in the next 3 bits, and the register or memory operand
+/// in the lowest 3 bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ModRm(u8);
+
+impl ModRm {
+    /// Creates the byte from its parts, panics if any of them does not fit in its bits
+    pub const fn new(mode: u8, reg: u8, rm: u8) -> Self {
+        ModRm(join(mode, reg, rm))
+    }
+
+    pub const fn from_byte(byte: u8) -> Self {
+        ModRm(byte)
+    }
+
+    pub const fn to_byte(self) -> u8 {
+        self.0
+    }
+
+    /// The addressing mode, from 0 to 3
+    pub const fn mode(self) -> u8 {
+        split(self.0).0
+    }
+
+    /// The register, or the opcode extension for instructions with a single operand
+    pub const fn reg(self) -> u8 {
+        split(self.0).1
+    }
+
+    /// The register in register mode, otherwise the way the address is computed
+    pub const fn rm(self) -> u8 {
+        split(self.0).2
+    }
+
+    /// If the r/m operand is a register rather than a memory address
+    pub const fn is_register(self) -> bool {
+        self.mode() == 0b11
+    }
+
+    /// Number of bytes of the displacement which follows, with 16 bit addressing as in
+    /// the 8086. Mode 1 has an 8 bit displacement and mode 2 has a 16 bit displacement,
+    /// mode 0 has none, except for rm 6, which is a direct 16 bit address.
+    pub const fn disp_len(self) -> usize {
+        match (self.mode(), self.rm()) {
+            (0b00, 0b110) | (0b10, _) => 2,
+            (0b01, _) => 1,
+            _ => 0,
+        }
+    }
+
+    /// If a SIB byte follows, with 32 bit addressing, which is the case for rm 4 in memory modes
+    pub const fn has_sib(self) -> bool {
+        !self.is_register() && self.rm() == 0b100
+    }
+
+    /// Number of bytes of the displacement which follows, with 32 bit addressing.
+    /// This also depends on the SIB byte, as the base 5 in mode 0 is a 32 bit displacement
+    pub const fn disp_len32(self, sib: Option<Sib>) -> usize {
+        let no_base = match sib {
+            Some(sib) => sib.base() == 0b101,
+            None => self.rm() == 0b101,
+        };
+        match self.mode() {
+            0b00 if no_base => 4,
+            0b01 => 1,
+            0b10 => 4,
+            _ => 0,
+        }
+    }
+}
+
+/// The SIB byte, which follows the ModR/M byte with 32 bit addressing, and holds the scale
+/// in its 2 highest bits, the index register in the next 3 bits and the base register
+/// in the lowest 3 bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Sib(u8);
+
+impl Sib {
+    /// Creates the byte from its parts, panics if any of them does not fit in its bits
+    pub const fn new(scale: u8, index: u8, base: u8) -> Self {
+        Sib(join(scale, index, base))
+    }
+
+    pub const fn from_byte(byte: u8) -> Self {
+        Sib(byte)
+    }
+
+    pub const fn to_byte(self) -> u8 {
+        self.0
+    }
+
+    /// The scale, as the power of 2 the index is multiplied with
+    pub const fn scale(self) -> u8 {
+        split(self.0).0
+    }
+
+    pub const fn index(self) -> u8 {
+        split(self.0).1
+    }
+
+    pub const fn base(self) -> u8 {
+        split(self.0).2
+    }
+}
+
+// every byte is a valid ModR/M or SIB byte, so these are stored as is
+macro_rules! impl_byte {
+    ($($name:ident),*) => {
+        $(
+            impl Bytecodable for $name {
+                const MIN_SIZE: usize = 1;
+                const MAX_SIZE: Option<usize> = Some(1);
+                fn encoded_len(&self) -> usize {
+                    1
+                }
+                fn compile_into<S: BytecodeSink + ?Sized>(
+                    &self,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    self.0.compile_into(out)
+                }
+                fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+                    u8::parse(bytes).map(|(b, s)| ($name(b), s))
+                }
+                #[cfg(feature = "std")]
+                fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+                    u8::read_from(reader).map($name)
+                }
+            }
+
+            impl FixedSize for $name {
+                const SIZE: usize = 1;
+            }
+        )*
+    };
+}
+
+impl_byte!(ModRm, Sib);
+
+// the SIB byte is present only for the modes which need it
+impl Dependent<ModRm> for Option<Sib> {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: Option<usize> = Some(1);
+    fn encoded_len_with(&self, _: &ModRm) -> usize {
+        self.is_some() as usize
+    }
+    fn compile_with<S: BytecodeSink + ?Sized>(
+        &self,
+        ctx: &ModRm,
+        out: &mut S,
+    ) -> Result<(), BytecodeError> {
+        match self {
+            Some(sib) if ctx.has_sib() => sib.compile_into(out),
+            None if !ctx.has_sib() => Ok(()),
+            _ => Err(BytecodeError::new(ErrorKind::Other(MISMATCH_ERROR))),
+        }
+    }
+    fn parse_with(bytes: &[u8], ctx: &ModRm) -> Result<(Self, usize), BytecodeError> {
+        if !ctx.has_sib() {
+            return Ok((None, 0));
+        }
+        Sib::parse(bytes).map(|(sib, s)| (Some(sib), s))
+    }
+}
+
+/// The displacement which follows the ModR/M byte with 16 bit addressing, as in the 8086,
+/// which is added to the address. Its size is given by the addressing mode, as in
+/// `ModRm::disp_len`, and compiling a displacement whose size does not match it is an error.
+/// See `Disp32` for 32 bit addressing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Displacement {
+    #[default]
+    None,
+    Disp8(i8),
+    Disp16(i16),
+}
+
+impl Displacement {
+    /// The value added to the address, the 8 bit displacement is sign extended
+    pub const fn value(self) -> i16 {
+        match self {
+            Displacement::None => 0,
+            Displacement::Disp8(d) => d as i16,
+            Displacement::Disp16(d) => d,
+        }
+    }
+}
+
+impl Dependent<ModRm> for Displacement {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: Option<usize> = Some(2);
+    fn encoded_len_with(&self, _: &ModRm) -> usize {
+        match self {
+            Displacement::None => 0,
+            Displacement::Disp8(_) => 1,
+            Displacement::Disp16(_) => 2,
+        }
+    }
+    fn compile_with<S: BytecodeSink + ?Sized>(
+        &self,
+        ctx: &ModRm,
+        out: &mut S,
+    ) -> Result<(), BytecodeError> {
+        if self.encoded_len_with(ctx) != ctx.disp_len() {
+            return Err(BytecodeError::new(ErrorKind::Other(MISMATCH_ERROR)));
+        }
+        match self {
+            Displacement::None => Ok(()),
+            Displacement::Disp8(d) => d.compile_into(out),
+            Displacement::Disp16(d) => d.compile_into(out),
+        }
+    }
+    fn parse_with(bytes: &[u8], ctx: &ModRm) -> Result<(Self, usize), BytecodeError> {
+        match ctx.disp_len() {
+            1 => i8::parse(bytes).map(|(d, s)| (Displacement::Disp8(d), s)),
+            2 => i16::parse(bytes).map(|(d, s)| (Displacement::Disp16(d), s)),
+            _ => Ok((Displacement::None, 0)),
+        }
+    }
+}
+
+/// The displacement which follows the ModR/M and SIB bytes with 32 bit addressing, which
+/// is added to the address. Its size is given by both bytes, as in `ModRm::disp_len32`,
+/// and compiling a displacement whose size does not match it is an error.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Disp32 {
+    #[default]
+    None,
+    Disp8(i8),
+    Disp32(i32),
+}
+
+impl Disp32 {
+    /// The value added to the address, the 8 bit displacement is sign extended
+    pub const fn value(self) -> i32 {
+        match self {
+            Disp32::None => 0,
+            Disp32::Disp8(d) => d as i32,
+            Disp32::Disp32(d) => d,
+        }
+    }
+}
+
+impl Dependent<(ModRm, Option<Sib>)> for Disp32 {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: Option<usize> = Some(4);
+    fn encoded_len_with(&self, _: &(ModRm, Option<Sib>)) -> usize {
+        match self {
+            Disp32::None => 0,
+            Disp32::Disp8(_) => 1,
+            Disp32::Disp32(_) => 4,
+        }
+    }
+    fn compile_with<S: BytecodeSink + ?Sized>(
+        &self,
+        ctx: &(ModRm, Option<Sib>),
+        out: &mut S,
+    ) -> Result<(), BytecodeError> {
+        if self.encoded_len_with(ctx) != ctx.0.disp_len32(ctx.1) {
+            return Err(BytecodeError::new(ErrorKind::Other(MISMATCH_ERROR)));
+        }
+        match self {
+            Disp32::None => Ok(()),
+            Disp32::Disp8(d) => d.compile_into(out),
+            Disp32::Disp32(d) => d.compile_into(out),
+        }
+    }
+    fn parse_with(
+        bytes: &[u8],
+        ctx: &(ModRm, Option<Sib>),
+    ) -> Result<(Self, usize), BytecodeError> {
+        match ctx.0.disp_len32(ctx.1) {
+            1 => i8::parse(bytes).map(|(d, s)| (Disp32::Disp8(d), s)),
+            4 => i32::parse(bytes).map(|(d, s)| (Disp32::Disp32(d), s)),
+            _ => Ok((Disp32::None, 0)),
+        }
+    }
+}
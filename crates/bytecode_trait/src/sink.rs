@@ -0,0 +1,33 @@
+use crate::{BytecodeError, ErrorKind};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// This is implemented by the destinations the bytecode can be compiled into,
+/// using `Bytecodable::compile_into`.
+pub trait BytecodeSink {
+    /// This function appends the given bytes at the end of the already written bytes.
+    /// It should return an error if the bytes cannot be written.
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), BytecodeError>;
+}
+
+#[cfg(feature = "alloc")]
+impl BytecodeSink for Vec<u8> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), BytecodeError> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+// same as std::io::Write for slices, the written bytes are removed from the
+// start of the slice, so the slice always refers to the unwritten part of the buffer
+impl BytecodeSink for &mut [u8] {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), BytecodeError> {
+        if self.len() < bytes.len() {
+            return Err(BytecodeError::new(ErrorKind::BufferOverflow));
+        }
+        let (written, rest) = core::mem::take(self).split_at_mut(bytes.len());
+        written.copy_from_slice(bytes);
+        *self = rest;
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{Bytecodable, BytecodeError, BytecodeSink};
+
+/// This is implemented by types whose bytecode is always of the same length,
+/// irrespective of the value, so it can be known without compiling the value.
+pub trait FixedSize: Bytecodable {
+    /// The number of bytes the bytecode of every value of this type takes
+    const SIZE: usize;
+}
+
+// sink which only counts the bytes written into it, used to find
+// the encoded length of values which do not compute it by themselves
+#[derive(Default)]
+pub(crate) struct SizeCounter(pub usize);
+
+impl BytecodeSink for SizeCounter {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), BytecodeError> {
+        self.0 += bytes.len();
+        Ok(())
+    }
+}
+
+// The functions below are used to combine the sizes of fields and variants
+// in the `MIN_SIZE` and `MAX_SIZE` consts, so they need to be const fns
+
+/// Adds the given maximum sizes, the total is unbounded if any one of them is unbounded
+pub const fn max_size_sum(sizes: &[Option<usize>]) -> Option<usize> {
+    let mut total = 0;
+    let mut i = 0;
+    while i < sizes.len() {
+        match sizes[i] {
+            Some(s) => total += s,
+            None => return None,
+        }
+        i += 1;
+    }
+    Some(total)
+}
+
+/// The smallest of the given sizes, or 0 if there are none
+pub const fn min_size_of(sizes: &[usize]) -> usize {
+    if sizes.is_empty() {
+        return 0;
+    }
+    let mut min = sizes[0];
+    let mut i = 1;
+    while i < sizes.len() {
+        if sizes[i] < min {
+            min = sizes[i];
+        }
+        i += 1;
+    }
+    min
+}
+
+/// The largest of the given maximum sizes, which is unbounded if any one of them is unbounded
+pub const fn max_size_of(sizes: &[Option<usize>]) -> Option<usize> {
+    let mut max = 0;
+    let mut i = 0;
+    while i < sizes.len() {
+        match sizes[i] {
+            Some(s) if s > max => max = s,
+            Some(_) => {}
+            None => return None,
+        }
+        i += 1;
+    }
+    Some(max)
+}
+
+/// Checks that all the given sizes are the same, and returns that size.
+/// This panics otherwise, which is a compile error when used in a const.
+pub const fn same_size(sizes: &[usize]) -> usize {
+    let mut i = 1;
+    while i < sizes.len() {
+        if sizes[i] != sizes[0] {
+            panic!("all variants of a fixed size type must have the same encoded size");
+        }
+        i += 1;
+    }
+    if sizes.is_empty() {
+        0
+    } else {
+        sizes[0]
+    }
+}
@@ -0,0 +1,171 @@
+use crate::endian::Endian;
+use crate::{Bytecodable, BytecodeError, BytecodeSink, ErrorKind, FixedSize};
+use core::marker::PhantomData;
+use core::num::{
+    NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+    NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
+};
+#[cfg(feature = "std")]
+use std::io::Read;
+
+// The unit type and PhantomData do not have any data, so they do not take any bytes,
+// which lets them be used as fields for markers and placeholders.
+
+impl Bytecodable for () {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: Option<usize> = Some(0);
+    fn encoded_len(&self) -> usize {
+        0
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, _: &mut S) -> Result<(), BytecodeError> {
+        Ok(())
+    }
+    fn parse(_: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        Ok(((), 0))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(_: &mut R) -> Result<Self, BytecodeError> {
+        Ok(())
+    }
+}
+
+impl<T: ?Sized> Bytecodable for PhantomData<T> {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: Option<usize> = Some(0);
+    fn encoded_len(&self) -> usize {
+        0
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, _: &mut S) -> Result<(), BytecodeError> {
+        Ok(())
+    }
+    fn parse(_: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        Ok((PhantomData, 0))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(_: &mut R) -> Result<Self, BytecodeError> {
+        Ok(PhantomData)
+    }
+}
+
+impl FixedSize for () {
+    const SIZE: usize = 0;
+}
+
+impl<T: ?Sized> FixedSize for PhantomData<T> {
+    const SIZE: usize = 0;
+}
+
+impl<T: Bytecodable> Bytecodable for Wrapping<T> {
+    const MIN_SIZE: usize = T::MIN_SIZE;
+    const MAX_SIZE: Option<usize> = T::MAX_SIZE;
+    fn encoded_len(&self) -> usize {
+        self.0.encoded_len()
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        self.0.compile_into(out)
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        T::parse(bytes).map(|(v, size)| (Wrapping(v), size))
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        T::read_from(reader).map(Wrapping)
+    }
+}
+
+impl<T: FixedSize> FixedSize for Wrapping<T> {
+    const SIZE: usize = T::SIZE;
+}
+
+impl<T: Endian> Endian for Wrapping<T> {
+    fn compile_be<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        self.0.compile_be(out)
+    }
+    fn parse_be(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        T::parse_be(bytes).map(|(v, size)| (Wrapping(v), size))
+    }
+    #[cfg(feature = "std")]
+    fn read_be<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        T::read_be(reader).map(Wrapping)
+    }
+}
+
+// the non-zero integers are stored same as the integers they wrap, in both byte orders,
+// and zero is rejected
+fn zero_error(len: usize) -> BytecodeError {
+    BytecodeError::new(ErrorKind::Other("value must not be zero")).with_bytes(&[0; 16][..len])
+}
+
+macro_rules! impl_non_zero {
+    ($($t:ty => $inner:ty),*) => {
+        $(
+            impl Bytecodable for $t {
+                const MIN_SIZE: usize = <$inner as Bytecodable>::MIN_SIZE;
+                const MAX_SIZE: Option<usize> = <$inner as Bytecodable>::MAX_SIZE;
+                fn encoded_len(&self) -> usize {
+                    self.get().encoded_len()
+                }
+                fn compile_into<S: BytecodeSink + ?Sized>(
+                    &self,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    self.get().compile_into(out)
+                }
+                fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+                    let (v, size) = <$inner>::parse(bytes)?;
+                    let v = <$t>::new(v).ok_or_else(|| zero_error(size))?;
+                    Ok((v, size))
+                }
+                #[cfg(feature = "std")]
+                fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+                    let v = <$inner>::read_from(reader)?;
+                    <$t>::new(v).ok_or_else(|| zero_error(v.encoded_len()))
+                }
+            }
+
+            impl Endian for $t {
+                fn compile_be<S: BytecodeSink + ?Sized>(
+                    &self,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    self.get().compile_be(out)
+                }
+                fn parse_be(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+                    let (v, size) = <$inner>::parse_be(bytes)?;
+                    let v = <$t>::new(v).ok_or_else(|| zero_error(size))?;
+                    Ok((v, size))
+                }
+                #[cfg(feature = "std")]
+                fn read_be<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+                    let v = <$inner>::read_be(reader)?;
+                    <$t>::new(v).ok_or_else(|| zero_error(v.encoded_len()))
+                }
+            }
+        )*
+    };
+}
+
+impl_non_zero!(
+    NonZeroU8 => u8, NonZeroU16 => u16, NonZeroU32 => u32, NonZeroU64 => u64,
+    NonZeroU128 => u128, NonZeroUsize => usize,
+    NonZeroI8 => i8, NonZeroI16 => i16, NonZeroI32 => i32, NonZeroI64 => i64,
+    NonZeroI128 => i128, NonZeroIsize => isize
+);
+
+// usize and isize are of variable length, so their non-zero types are not of fixed size
+macro_rules! impl_non_zero_fixed_size {
+    ($($t:ty => $inner:ty),*) => {
+        $(
+            impl FixedSize for $t {
+                const SIZE: usize = <$inner as FixedSize>::SIZE;
+            }
+        )*
+    };
+}
+
+impl_non_zero_fixed_size!(
+    NonZeroU8 => u8, NonZeroU16 => u16, NonZeroU32 => u32, NonZeroU64 => u64,
+    NonZeroU128 => u128,
+    NonZeroI8 => i8, NonZeroI16 => i16, NonZeroI32 => i32, NonZeroI64 => i64,
+    NonZeroI128 => i128
+);
@@ -0,0 +1,276 @@
+use crate::leb128::{
+    leb128_len, read_leb128, read_sleb128, sleb128_len, write_leb128, write_sleb128,
+};
+use crate::{Bytecodable, BytecodeError, BytecodeSink};
+use core::convert::TryFrom;
+#[cfg(feature = "std")]
+use std::io::Read;
+
+// Variable length integers take fewer bytes for smaller values, which suits
+// immediates and offsets which are usually small. All of these accept only the shortest
+// encoding of a value, so that each value has a single valid bytecode.
+
+/// Integer types which can be stored as variable length integers, which is used for the
+/// fields with `#[bytecode(varint)]`. The value is converted to a `u64` which is stored in
+/// LEB128, signed values are zigzag encoded for this, so that small negative values take few bytes.
+pub trait Varint: Copy {
+    /// The maximum number of bytes the value takes
+    const MAX_LEN: usize;
+
+    /// Converts the value to the unsigned value which is stored
+    fn to_varint(self) -> u64;
+
+    /// Converts the stored value back, None if it does not fit in this type
+    fn from_varint(value: u64) -> Option<Self>;
+}
+
+// zigzag encoding maps the signed values to unsigned ones as 0, -1, 1, -2, 2 ...
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+// number of bytes needed to store the given number of bits in LEB128
+const fn leb128_max_len(bits: u32) -> usize {
+    (bits as usize).div_ceil(7)
+}
+
+macro_rules! impl_varint_unsigned {
+    ($($t:ty),*) => {
+        $(
+            impl Varint for $t {
+                const MAX_LEN: usize = leb128_max_len(<$t>::BITS);
+
+                fn to_varint(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_varint(value: u64) -> Option<Self> {
+                    <$t>::try_from(value).ok()
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_varint_signed {
+    ($($t:ty),*) => {
+        $(
+            impl Varint for $t {
+                const MAX_LEN: usize = leb128_max_len(<$t>::BITS);
+
+                fn to_varint(self) -> u64 {
+                    zigzag(self as i64)
+                }
+
+                fn from_varint(value: u64) -> Option<Self> {
+                    <$t>::try_from(unzigzag(value)).ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_varint_unsigned!(u8, u16, u32, u64, usize);
+impl_varint_signed!(i8, i16, i32, i64, isize);
+
+/// The number of bytes the value takes as a variable length integer
+pub fn varint_len<T: Varint>(value: &T) -> usize {
+    leb128_len(value.to_varint())
+}
+
+/// Compiles the value as a variable length integer
+pub fn compile_varint<T: Varint, S: BytecodeSink + ?Sized>(
+    value: &T,
+    out: &mut S,
+) -> Result<(), BytecodeError> {
+    write_leb128(out, value.to_varint())
+}
+
+/// Parses a variable length integer, which is invalid if it does not fit in `T`
+pub fn parse_varint<T: Varint>(bytes: &[u8]) -> Result<(T, usize), BytecodeError> {
+    let (value, size) = read_leb128(bytes)?;
+    match T::from_varint(value) {
+        Some(v) => Ok((v, size)),
+        None => Err(BytecodeError::invalid(&bytes[..size])),
+    }
+}
+
+/// Reads a variable length integer, which is invalid if it does not fit in `T`
+#[cfg(feature = "std")]
+pub fn read_varint<T: Varint, R: Read + ?Sized>(reader: &mut R) -> Result<T, BytecodeError> {
+    let mut bytes = [0; MAX_VARINT_LEN];
+    let len = read_varint_bytes(reader, &mut bytes)?;
+    parse_varint(&bytes[..len]).map(|(v, _)| v)
+}
+
+/// The maximum number of bytes a variable length integer takes
+pub const MAX_VARINT_LEN: usize = crate::leb128::MAX_LEB128_LEN;
+
+/// Reads the bytes of a variable length integer without decoding them, and returns
+/// their number. This lets the bytes be reported in errors, if the value is not valid.
+#[cfg(feature = "std")]
+pub fn read_varint_bytes<R: Read + ?Sized>(
+    reader: &mut R,
+    bytes: &mut [u8; MAX_VARINT_LEN],
+) -> Result<usize, BytecodeError> {
+    crate::leb128::read_leb128_bytes(reader, bytes)
+}
+
+// usize and isize are of different sizes on different targets, so they are stored as
+// variable length integers, which gives the same bytecode on all of them
+
+impl Bytecodable for usize {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: Option<usize> = Some(<u64 as Varint>::MAX_LEN);
+    fn encoded_len(&self) -> usize {
+        varint_len(self)
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        compile_varint(self, out)
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        parse_varint(bytes)
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        read_varint(reader)
+    }
+}
+
+impl Bytecodable for isize {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: Option<usize> = Some(<i64 as Varint>::MAX_LEN);
+    fn encoded_len(&self) -> usize {
+        varint_len(self)
+    }
+    fn compile_into<S: BytecodeSink + ?Sized>(&self, out: &mut S) -> Result<(), BytecodeError> {
+        compile_varint(self, out)
+    }
+    fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+        parse_varint(bytes)
+    }
+    #[cfg(feature = "std")]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+        read_varint(reader)
+    }
+}
+
+/// An unsigned integer stored in LEB128, in which each byte holds 7 bits of the value,
+/// starting from the lowest ones, with the MSB set if more bytes follow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VarU64(pub u64);
+
+/// Same as `VarU64`, for values which fit in `u32`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VarU32(pub u32);
+
+/// A signed integer stored in zigzag encoding, which maps 0, -1, 1, -2, 2 ... to
+/// 0, 1, 2, 3, 4 ..., and then in LEB128 as for `VarU64`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ZigZagI64(pub i64);
+
+/// Same as `ZigZagI64`, for values which fit in `i32`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ZigZagI32(pub i32);
+
+macro_rules! impl_var_wrapper {
+    ($($name:ident($t:ty)),*) => {
+        $(
+            impl From<$t> for $name {
+                fn from(value: $t) -> Self {
+                    $name(value)
+                }
+            }
+
+            impl From<$name> for $t {
+                fn from(value: $name) -> Self {
+                    value.0
+                }
+            }
+
+            impl Bytecodable for $name {
+                const MIN_SIZE: usize = 1;
+                const MAX_SIZE: Option<usize> = Some(<$t as Varint>::MAX_LEN);
+                fn encoded_len(&self) -> usize {
+                    varint_len(&self.0)
+                }
+                fn compile_into<S: BytecodeSink + ?Sized>(
+                    &self,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    compile_varint(&self.0, out)
+                }
+                fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+                    parse_varint(bytes).map(|(v, size)| ($name(v), size))
+                }
+                #[cfg(feature = "std")]
+                fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+                    read_varint(reader).map($name)
+                }
+            }
+        )*
+    };
+}
+
+impl_var_wrapper!(VarU64(u64), VarU32(u32), ZigZagI64(i64), ZigZagI32(i32));
+
+/// A signed integer stored in signed LEB128, which is same as `VarU64` for the two's
+/// complement of the value, with bit 6 of the last byte being the sign. This is the
+/// encoding used by DWARF and WebAssembly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VarI64(pub i64);
+
+/// Same as `VarI64`, for values which fit in `i32`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VarI32(pub i32);
+
+macro_rules! impl_sleb128_wrapper {
+    ($($name:ident($t:ty)),*) => {
+        $(
+            impl From<$t> for $name {
+                fn from(value: $t) -> Self {
+                    $name(value)
+                }
+            }
+
+            impl From<$name> for $t {
+                fn from(value: $name) -> Self {
+                    value.0
+                }
+            }
+
+            impl Bytecodable for $name {
+                const MIN_SIZE: usize = 1;
+                const MAX_SIZE: Option<usize> = Some(leb128_max_len(<$t>::BITS));
+                fn encoded_len(&self) -> usize {
+                    sleb128_len(self.0 as i64)
+                }
+                fn compile_into<S: BytecodeSink + ?Sized>(
+                    &self,
+                    out: &mut S,
+                ) -> Result<(), BytecodeError> {
+                    write_sleb128(out, self.0 as i64)
+                }
+                fn parse(bytes: &[u8]) -> Result<(Self, usize), BytecodeError> {
+                    let (value, size) = read_sleb128(bytes)?;
+                    match <$t>::try_from(value) {
+                        Ok(v) => Ok(($name(v), size)),
+                        Err(_) => Err(BytecodeError::invalid(&bytes[..size])),
+                    }
+                }
+                #[cfg(feature = "std")]
+                fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self, BytecodeError> {
+                    let mut bytes = [0; crate::leb128::MAX_LEB128_LEN];
+                    let len = crate::leb128::read_leb128_bytes(reader, &mut bytes)?;
+                    Self::parse(&bytes[..len]).map(|(v, _)| v)
+                }
+            }
+        )*
+    };
+}
+
+impl_sleb128_wrapper!(VarI64(i64), VarI32(i32));